@@ -1,4 +1,25 @@
-use gpui::actions;
+use crate::config::KeysConfig;
+use gpui::{App, KeyBinding, actions};
 
 actions!(crop, [CropImage]);
 actions!(crop, [CancelCrop]);
+actions!(crop, [OpenImage]);
+actions!(crop, [PasteImage]);
+actions!(crop, [CopyCrop]);
+actions!(crop, [RotateClockwise, RotateCounterClockwise]);
+actions!(crop, [FlipHorizontal, FlipVertical]);
+
+/// Binds the actions to the keys from the config, once for the whole app
+pub fn bind_keys(cx: &mut App, keys: &KeysConfig) {
+    cx.bind_keys([
+        KeyBinding::new(&keys.crop, CropImage, None),
+        KeyBinding::new(&keys.cancel, CancelCrop, None),
+        KeyBinding::new(&keys.open, OpenImage, None),
+        KeyBinding::new(&keys.paste, PasteImage, None),
+        KeyBinding::new(&keys.copy, CopyCrop, None),
+        KeyBinding::new(&keys.rotate_clockwise, RotateClockwise, None),
+        KeyBinding::new(&keys.rotate_counter_clockwise, RotateCounterClockwise, None),
+        KeyBinding::new(&keys.flip_horizontal, FlipHorizontal, None),
+        KeyBinding::new(&keys.flip_vertical, FlipVertical, None),
+    ]);
+}
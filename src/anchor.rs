@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+/// One of the nine points of a rectangle that positions are measured from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+/// The anchors in reading order, as laid out in a 3x3 grid
+pub const ANCHORS: [Anchor; 9] = [
+    Anchor::TopLeft,
+    Anchor::Top,
+    Anchor::TopRight,
+    Anchor::Left,
+    Anchor::Center,
+    Anchor::Right,
+    Anchor::BottomLeft,
+    Anchor::Bottom,
+    Anchor::BottomRight,
+];
+
+impl Anchor {
+    /// Parses `top-left`, `top`, `center`, `bottom-right` and so on
+    pub fn parse(value: &str) -> Option<Anchor> {
+        ANCHORS
+            .into_iter()
+            .find(|anchor| anchor.name() == value.to_ascii_lowercase())
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Anchor::TopLeft => "top-left",
+            Anchor::Top => "top",
+            Anchor::TopRight => "top-right",
+            Anchor::Left => "left",
+            Anchor::Center => "center",
+            Anchor::Right => "right",
+            Anchor::BottomLeft => "bottom-left",
+            Anchor::Bottom => "bottom",
+            Anchor::BottomRight => "bottom-right",
+        }
+    }
+
+    /// Position of the anchor across the width and height of a rectangle,
+    /// from 0 to 1
+    pub fn fractions(&self) -> (f32, f32) {
+        let index = ANCHORS.iter().position(|anchor| anchor == self).unwrap();
+        ((index % 3) as f32 / 2., (index / 3) as f32 / 2.)
+    }
+}
@@ -0,0 +1,338 @@
+use crate::anchor::Anchor;
+use crate::canvas_fill::CanvasFill;
+use crate::crop_shape::CropShape;
+use crate::grid_slice::{GridCells, GridSlice};
+use crate::guides::Guides;
+use crate::image_crop::CropRegion;
+use crate::image_transform::{MAX_STRAIGHTEN_ANGLE, TransformOp};
+use crate::output_size::{MAX_OUTPUT_PERCENT, MAX_OUTPUT_PIXELS, OutputResize, OutputSize};
+use crate::output_template::OutputTemplate;
+use crate::recipe::SizeMismatch;
+use std::path::PathBuf;
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub help: bool,
+    pub image_path: Option<PathBuf>,
+    pub dest_image_path: Option<PathBuf>,
+    pub from_clipboard: bool,
+    pub to_clipboard: bool,
+    pub headless: bool,
+    pub transform_ops: Vec<TransformOp>,
+    pub straighten: f32,
+    pub crop_regions: Vec<CropRegion>,
+    pub canvas_fill: CanvasFill,
+    pub crop_shape: CropShape,
+    pub output_resize: OutputResize,
+    pub is_slicing: bool,
+    pub grid_slice: GridSlice,
+    pub detect_photos: bool,
+    pub detect_tolerance: Option<f32>,
+    pub preset: Option<String>,
+    pub output_template: Option<OutputTemplate>,
+    pub guides: Option<Guides>,
+    pub jpeg_quality: Option<u8>,
+    pub print_config: bool,
+    pub sidecar: bool,
+    pub apply_sidecar: bool,
+    /// The recipe of the apply-recipe command
+    pub recipe_path: Option<PathBuf>,
+    pub save_recipe_path: Option<PathBuf>,
+    pub size_mismatch: Option<SizeMismatch>,
+    pub anchor: Option<Anchor>,
+    /// Source images of --apply-sidecar and apply-recipe
+    pub image_paths: Vec<PathBuf>,
+    pub explicit: ExplicitSettings,
+}
+
+/// Which of the settings saved in a sidecar were given on the command line,
+/// those take precedence over the sidecar
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ExplicitSettings {
+    pub transform: bool,
+    pub shape: bool,
+    pub fill: bool,
+    pub resize: bool,
+    pub dest_image_path: bool,
+}
+
+impl Args {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut positional = vec![];
+
+        let mut args = args.into_iter().peekable();
+        let is_applying_recipe = args.next_if(|arg| arg == "apply-recipe").is_some();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--rotate" | "--flip" | "--straighten" => parsed.explicit.transform = true,
+                "--shape" | "--corner-radius" => parsed.explicit.shape = true,
+                "--fill" => parsed.explicit.fill = true,
+                "--resize" | "--scale" | "--fit" | "--filter" => parsed.explicit.resize = true,
+                _ => {}
+            }
+
+            match arg.as_str() {
+                "-h" | "--help" => parsed.help = true,
+                "--from-clipboard" => parsed.from_clipboard = true,
+                "--to-clipboard" => parsed.to_clipboard = true,
+                "--headless" => parsed.headless = true,
+                "--rotate" => {
+                    let value = option_value(&arg, args.next())?;
+                    let ops = TransformOp::parse_rotation(&value).ok_or_else(|| {
+                        format!("invalid rotation {value}, expected 90, 180 or 270")
+                    })?;
+                    parsed.transform_ops.extend(ops);
+                }
+                "--flip" => {
+                    let value = option_value(&arg, args.next())?;
+                    let op = TransformOp::parse_flip(&value)
+                        .ok_or_else(|| format!("invalid flip {value}, expected h or v"))?;
+                    parsed.transform_ops.push(op);
+                }
+                "--straighten" => {
+                    let value = option_value(&arg, args.next())?;
+                    parsed.straighten = value
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|degrees| degrees.abs() <= MAX_STRAIGHTEN_ANGLE)
+                        .ok_or_else(|| {
+                            format!("invalid straighten angle {value}, expected -45 to 45 degrees")
+                        })?;
+                }
+                "--crop" => {
+                    let value = option_value(&arg, args.next())?;
+                    for region in value.split(',') {
+                        let region = CropRegion::parse(region).ok_or_else(|| {
+                            format!("invalid crop {region}, expected [NAME=]WIDTHxHEIGHT+X+Y")
+                        })?;
+                        parsed.crop_regions.push(region);
+                    }
+                }
+                "--fill" => {
+                    let value = option_value(&arg, args.next())?;
+                    parsed.canvas_fill = CanvasFill::parse(&value).ok_or_else(|| {
+                        format!(
+                            "invalid fill {value}, expected transparent, edge, mirror or #RRGGBB"
+                        )
+                    })?;
+                }
+                "--shape" => {
+                    let value = option_value(&arg, args.next())?;
+                    let shape = CropShape::parse(&value).ok_or_else(|| {
+                        format!("invalid shape {value}, expected rectangle or ellipse")
+                    })?;
+                    // Keep a corner radius given before the shape
+                    parsed.crop_shape = match (shape, parsed.crop_shape) {
+                        (CropShape::Rectangle { .. }, current @ CropShape::Rectangle { .. }) => {
+                            current
+                        }
+                        _ => shape,
+                    };
+                }
+                "--corner-radius" => {
+                    let value = option_value(&arg, args.next())?;
+                    let corner_radius = value.parse::<u32>().map_err(|_| {
+                        format!("invalid corner radius {value}, expected a number of pixels")
+                    })?;
+                    parsed.crop_shape = CropShape::Rectangle { corner_radius };
+                }
+                "--resize" => {
+                    let value = option_value(&arg, args.next())?;
+                    parsed.output_resize.size =
+                        OutputSize::parse_exact(&value).ok_or_else(|| {
+                            format!(
+                                "invalid size {value}, expected WIDTHxHEIGHT, WIDTHx or xHEIGHT \
+                                 with up to {MAX_OUTPUT_PIXELS} pixels"
+                            )
+                        })?;
+                }
+                "--scale" => {
+                    let value = option_value(&arg, args.next())?;
+                    parsed.output_resize.size =
+                        OutputSize::parse_percent(&value).ok_or_else(|| {
+                            format!("invalid scale {value}, expected 0 to {MAX_OUTPUT_PERCENT}%")
+                        })?;
+                }
+                "--fit" => {
+                    let value = option_value(&arg, args.next())?;
+                    parsed.output_resize.size = OutputSize::parse_fit(&value).ok_or_else(|| {
+                        format!(
+                            "invalid size {value}, expected WIDTHxHEIGHT with up to \
+                             {MAX_OUTPUT_PIXELS} pixels"
+                        )
+                    })?;
+                }
+                "--filter" => {
+                    let value = option_value(&arg, args.next())?;
+                    parsed.output_resize.filter =
+                        OutputResize::parse_filter(&value).ok_or_else(|| {
+                            format!(
+                                "invalid filter {value}, expected nearest, triangle, \
+                                 catmullrom, gaussian or lanczos3"
+                            )
+                        })?;
+                }
+                "--grid" => {
+                    let value = option_value(&arg, args.next())?;
+                    parsed.grid_slice.cells = GridCells::parse_count(&value)
+                        .ok_or_else(|| format!("invalid grid {value}, expected COLUMNSxROWS"))?;
+                    parsed.is_slicing = true;
+                }
+                "--grid-cell" => {
+                    let value = option_value(&arg, args.next())?;
+                    parsed.grid_slice.cells = GridCells::parse_size(&value).ok_or_else(|| {
+                        format!("invalid grid cell {value}, expected WIDTHxHEIGHT")
+                    })?;
+                    parsed.is_slicing = true;
+                }
+                "--grid-margin" | "--grid-spacing" => {
+                    let value = option_value(&arg, args.next())?;
+                    let pixels = value.parse::<u32>().map_err(|_| {
+                        format!("invalid value {value} for {arg}, expected a number of pixels")
+                    })?;
+                    match arg.as_str() {
+                        "--grid-margin" => parsed.grid_slice.margin = pixels,
+                        _ => parsed.grid_slice.spacing = pixels,
+                    }
+                }
+                "--skip-empty" => parsed.grid_slice.skip_empty = true,
+                "--preset" => parsed.preset = Some(option_value(&arg, args.next())?),
+                "--detect-photos" => parsed.detect_photos = true,
+                "--detect-tolerance" => {
+                    let value = option_value(&arg, args.next())?;
+                    let tolerance = value
+                        .strip_suffix('%')
+                        .unwrap_or(&value)
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|tolerance| (0. ..=100.).contains(tolerance))
+                        .ok_or_else(|| {
+                            format!("invalid tolerance {value}, expected 0 to 100 percent")
+                        })?;
+                    parsed.detect_tolerance = Some(tolerance);
+                }
+                "--output-template" => {
+                    let value = option_value(&arg, args.next())?;
+                    parsed.output_template = Some(OutputTemplate::parse(&value)?);
+                }
+                "--guides" => {
+                    let value = option_value(&arg, args.next())?;
+                    let guides = Guides::parse(&value).ok_or_else(|| {
+                        format!("invalid guides {value}, expected none, thirds or center")
+                    })?;
+                    parsed.guides = Some(guides);
+                }
+                "--quality" => {
+                    let value = option_value(&arg, args.next())?;
+                    let quality = value
+                        .parse::<u8>()
+                        .ok()
+                        .filter(|quality| (1..=100).contains(quality))
+                        .ok_or_else(|| format!("invalid quality {value}, expected 1 to 100"))?;
+                    parsed.jpeg_quality = Some(quality);
+                }
+                "--print-config" => parsed.print_config = true,
+                "--sidecar" => parsed.sidecar = true,
+                "--apply-sidecar" => parsed.apply_sidecar = true,
+                "--save-recipe" => {
+                    parsed.save_recipe_path = Some(PathBuf::from(option_value(&arg, args.next())?));
+                }
+                "--on-size-mismatch" => {
+                    let value = option_value(&arg, args.next())?;
+                    let size_mismatch = SizeMismatch::parse(&value).ok_or_else(|| {
+                        format!("invalid size mismatch {value}, expected skip, scale or anchor")
+                    })?;
+                    parsed.size_mismatch = Some(size_mismatch);
+                }
+                "--anchor" => {
+                    let value = option_value(&arg, args.next())?;
+                    let anchor = Anchor::parse(&value).ok_or_else(|| {
+                        format!(
+                            "invalid anchor {value}, expected top-left, top, top-right, left, \
+                             center, right, bottom-left, bottom or bottom-right"
+                        )
+                    })?;
+                    parsed.anchor = Some(anchor);
+                }
+                "--" => positional.extend(args.by_ref()),
+                _ if arg.starts_with('-') && arg.len() > 1 => {
+                    return Err(format!("unknown option {arg}"));
+                }
+                _ => positional.push(arg),
+            }
+        }
+
+        if is_applying_recipe {
+            let mut positional = positional.into_iter().map(PathBuf::from);
+            parsed.recipe_path = positional.next();
+            parsed.image_paths = positional.collect();
+            if parsed.image_paths.is_empty() {
+                return Err("apply-recipe needs a recipe and source images".to_string());
+            }
+            if parsed.from_clipboard || parsed.to_clipboard {
+                return Err("apply-recipe can't be used with the clipboard".to_string());
+            }
+            return Ok(parsed);
+        }
+
+        if parsed.apply_sidecar {
+            if positional.is_empty() {
+                return Err("missing source-image for --apply-sidecar".to_string());
+            }
+            if parsed.from_clipboard || parsed.to_clipboard {
+                return Err("--apply-sidecar can't be used with the clipboard".to_string());
+            }
+            parsed.image_paths = positional.into_iter().map(PathBuf::from).collect();
+            return Ok(parsed);
+        }
+
+        // When reading from the clipboard the only path is the output-image
+        let mut positional = positional.into_iter().map(PathBuf::from);
+        if !parsed.from_clipboard {
+            parsed.image_path = positional.next();
+        }
+        parsed.dest_image_path = positional.next();
+        parsed.explicit.dest_image_path = parsed.dest_image_path.is_some();
+
+        if let Some(arg) = positional.next() {
+            return Err(format!("unexpected argument {}", arg.display()));
+        }
+
+        if parsed.headless && (parsed.from_clipboard || parsed.to_clipboard) {
+            return Err("--headless can't be used with the clipboard".to_string());
+        }
+
+        // The preset sets both the crop and the output size
+        if parsed.preset.is_some() && !parsed.crop_regions.is_empty() {
+            return Err("--preset can't be used with --crop".to_string());
+        }
+        if parsed.preset.is_some() && parsed.output_resize.size != OutputSize::Original {
+            return Err("--preset can't be used with --resize, --scale or --fit".to_string());
+        }
+
+        if parsed.detect_photos && !parsed.crop_regions.is_empty() {
+            return Err("--detect-photos can't be used with --crop".to_string());
+        }
+
+        if parsed.is_slicing && parsed.detect_photos {
+            return Err("--grid can't be used with --detect-photos".to_string());
+        }
+
+        if parsed.save_recipe_path.is_some()
+            && (parsed.is_slicing || parsed.detect_photos || parsed.crop_regions.len() > 1)
+        {
+            return Err("--save-recipe needs a single crop".to_string());
+        }
+
+        if parsed.is_slicing && parsed.crop_regions.len() > 1 {
+            return Err("--grid can't be used with several crop regions".to_string());
+        }
+
+        Ok(parsed)
+    }
+}
+
+fn option_value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("missing value for {option}"))
+}
@@ -1,199 +1,1104 @@
+use crate::anchor::Anchor;
+use crate::args::{Args, ExplicitSettings};
 use crate::basicrop_state::BasicropState;
+use crate::canvas_fill::{CANVAS_FILL_MODES, CanvasFill, format_color};
+use crate::config::Config;
+use crate::coordinate_mode::{COORDINATE_MODES, CoordinateMode};
 use crate::counter_input;
-use crate::image_crop::ImageCrop;
-use crate::main_view::render_main_view;
-use crate::misc::{CroppingMousePosition, LoadingImage};
-use gpui::{Context, ImageAssetLoader, Point, Resource, Window, prelude::*, px};
+use crate::crop_model::{CropModel, CropSource, DragEvent};
+use crate::crop_pipeline::{render_image, rgba_image};
+use crate::crop_shape::{CROP_SHAPES, CropShape};
+use crate::field_units::{DEFAULT_DPI, read_dpi};
+use crate::grid_slice::{GRID_MODES, GridCells, GridSlice};
+use crate::guides::{GUIDES, Guides};
+use crate::image_crop::{
+    CropRegion, FinalizedImageCrop, ImageCrop, InitializedImageCrop, PolygonCrop,
+};
+use crate::image_transform::{ImageTransform, MAX_STRAIGHTEN_ANGLE};
+use crate::main_view::{
+    apply_preset, handle_canvas_drag, render_main_view, reset_output_options, set_straighten_angle,
+    update_canvas_fill, update_crop_shape, update_grid_slice, update_output_options,
+    update_output_resize,
+};
+use crate::misc::{LoadingImage, SourceImage};
+use crate::output_format::{OutputFormat, OutputOptions, PNG_COMPRESSIONS};
+use crate::output_size::{FILTERS, OUTPUT_SIZE_MODES, OutputResize};
+use crate::photo_detect::DEFAULT_DETECT_TOLERANCE;
+use crate::preset::{Preset, find_preset};
+use crate::recent_files;
+use crate::sidecar::{Sidecar, sidecar_path};
+use crate::viewport_background::{VIEWPORT_BACKGROUNDS, ViewportBackground};
+use gpui::{
+    Context, ImageAssetLoader, ImageId, RenderImage, Resource, Subscription, Task, Window, div,
+    prelude::*,
+};
+use gpui_component::input::{InputEvent, InputState};
+use gpui_component::select::{SelectEvent, SelectState};
+use gpui_component::slider::{SliderEvent, SliderState};
+use gpui_component::{IndexPath, Root};
 use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
 
 pub struct Basicrop {
     state: BasicropState,
+    /// Handlers for the state's entities, replaced along with it
+    _subscriptions: Vec<Subscription>,
+    transformed_image: Option<TransformedImage>,
+    /// The transform being computed in the background
+    pending_transform: Option<(ImageTransform, Task<()>)>,
+    /// Settings from the command line, kept when a sidecar is restored
+    explicit: ExplicitSettings,
+}
+
+/// How long the transform has to stay the same before the image is
+/// transformed, so dragging the straighten slider doesn't queue up work
+const TRANSFORM_DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// The rotated and flipped image being displayed, kept so it is only
+/// recomputed when the transform or the source image changes
+struct TransformedImage {
+    source_id: ImageId,
+    image_transform: ImageTransform,
+    image: Arc<RenderImage>,
 }
 
 impl Basicrop {
     pub fn new(
         window: &mut Window,
         cx: &mut Context<Self>,
-        image_path: PathBuf,
-        dest_image_path: PathBuf,
+        image_source: Option<SourceImage>,
+        args: &Args,
+        config: Rc<Config>,
+        preset: Option<Preset>,
     ) -> Self {
-        let crop_x = cx.new(|cx| counter_input::CounterView::new(window, cx, 0));
-        let crop_y = cx.new(|cx| counter_input::CounterView::new(window, cx, 0));
-        let width = cx.new(|cx| counter_input::CounterView::new(window, cx, 0));
-        let height = cx.new(|cx| counter_input::CounterView::new(window, cx, 0));
-        let is_selecting = cx.new(|_| false);
-        let mouse_initial_pos = cx.new(|_| Point {
-            x: px(0.),
-            y: px(0.),
-        });
-        let mouse_pos = cx.new(|_| {
-            CroppingMousePosition::Moved(Point {
-                x: px(0.),
-                y: px(0.),
-            })
-        });
-        let image_path: Resource = image_path.into();
-        let image_crop = cx.new(|_| ImageCrop::Uninitialized);
-        let image_crop_initial = cx.new(|_| ImageCrop::Uninitialized);
-        let dest_image_path = cx.new(|_| dest_image_path);
-        let image_saved_notification = cx.new(|cx| {
-            cx.observe_self(|_, cx| {
-                cx.shutdown();
-            })
-            .detach();
-        });
+        if let Some(SourceImage::Path(image_path)) = &image_source {
+            recent_files::add(image_path);
+        }
 
-        // Handlers for text input updates
-        crop_x.update(cx, {
-            let image_crop_clone = image_crop.clone();
-            |view, cx| {
-                view.subscribe(
-                    window,
-                    cx,
-                    image_crop.clone(),
-                    move |new_value, image_crop, cx| {
-                        if new_value != f32::from(image_crop.crop_x) as u32 {
-                            image_crop_clone.write(
-                                cx,
-                                ImageCrop::Cropped {
-                                    crop_x: (new_value as f32).into(),
-                                    crop_y: image_crop.crop_y,
-                                    width: image_crop.width,
-                                    height: image_crop.height,
-                                },
-                            );
-                        }
-                    },
-                )
+        let image_transform = ImageTransform::from_ops(&args.transform_ops, args.straighten);
+
+        let (state, subscriptions) = Self::new_state(
+            window,
+            cx,
+            image_source,
+            args.dest_image_path.clone(),
+            args.to_clipboard,
+            image_transform,
+            args.crop_shape,
+            args.canvas_fill,
+            args.output_resize,
+            args.is_slicing,
+            args.grid_slice,
+            config.overlay.guides,
+            config.overlay.background,
+            CoordinateMode::default(),
+            Anchor::default(),
+            config,
+            preset,
+            None,
+            args.explicit,
+        );
+
+        let mut basicrop = Basicrop {
+            state,
+            _subscriptions: subscriptions,
+            transformed_image: None,
+            pending_transform: None,
+            explicit: args.explicit,
+        };
+        basicrop.load_sidecar(window, cx);
+        basicrop
+    }
+
+    /// Replaces the current image and resets all crop state
+    pub fn open_image(
+        &mut self,
+        image_source: SourceImage,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        match &image_source {
+            SourceImage::Path(image_path) => {
+                println!("info: opening image: {}", image_path.to_string_lossy());
+                recent_files::add(image_path);
             }
-        });
-        crop_y.update(cx, {
-            let image_crop_clone = image_crop.clone();
-            |view, cx| {
-                view.subscribe(
+            SourceImage::Decoded(_) => println!("info: opening image from clipboard"),
+        }
+
+        // Settings given on the command line only apply to the first image
+        self.explicit = ExplicitSettings::default();
+        self.replace_state(
+            Some(image_source),
+            None,
+            ImageTransform::default(),
+            None,
+            window,
+            cx,
+        );
+        self.pending_transform = None;
+        if let Some(transformed) = self.transformed_image.take() {
+            window.drop_image(transformed.image).ok();
+        }
+        self.load_sidecar(window, cx);
+        cx.notify();
+    }
+
+    /// Rebuilds the state for an image. The shape, fill, output size, grid,
+    /// guides, background and coordinate mode are kept for cropping several
+    /// images the same way
+    fn replace_state(
+        &mut self,
+        image_source: Option<SourceImage>,
+        dest_image_path: Option<PathBuf>,
+        image_transform: ImageTransform,
+        sidecar: Option<Sidecar>,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let to_clipboard = self.state.to_clipboard;
+        let crop_shape = *self.state.crop_shape.read(cx);
+        let canvas_fill = *self.state.canvas_fill.read(cx);
+        let output_resize = *self.state.output_resize.read(cx);
+        let is_slicing = *self.state.is_slicing.read(cx);
+        let grid_slice = *self.state.grid_slice.read(cx);
+        let guides = *self.state.guides.read(cx);
+        let background = *self.state.background.read(cx);
+        let coordinate_mode = *self.state.coordinate_mode.read(cx);
+        let anchor = *self.state.anchor.read(cx);
+        let config = self.state.config.clone();
+        let preset = self.state.preset.read(cx).clone();
+        (self.state, self._subscriptions) = Self::new_state(
+            window,
+            cx,
+            image_source,
+            dest_image_path,
+            to_clipboard,
+            image_transform,
+            crop_shape,
+            canvas_fill,
+            output_resize,
+            is_slicing,
+            grid_slice,
+            guides,
+            background,
+            coordinate_mode,
+            anchor,
+            config,
+            preset,
+            sidecar,
+            self.explicit,
+        );
+    }
+
+    /// Reads the sidecar of the image in the background, hashing a large
+    /// image takes a while, and restores the earlier crop from it
+    fn load_sidecar(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(SourceImage::Path(image_path)) = self.state.image_source.clone() else {
+            return;
+        };
+        if !sidecar_path(&image_path).exists() {
+            return;
+        }
+        let settings = self.sidecar_settings(cx);
+
+        cx.spawn_in(window, async move |this, cx| {
+            let sidecar = cx
+                .background_spawn({
+                    let image_path = image_path.clone();
+                    async move { Sidecar::load(&image_path) }
+                })
+                .await;
+            let sidecar = match sidecar {
+                Ok(Some(sidecar)) => sidecar,
+                Ok(None) => return,
+                Err(error) => {
+                    eprintln!("warn: ignoring sidecar: {error}");
+                    return;
+                }
+            };
+
+            let _ = this.update_in(cx, |this, window, cx| {
+                // Another image was opened in the meantime
+                let is_same_image = matches!(
+                    &this.state.image_source,
+                    Some(SourceImage::Path(path)) if *path == image_path
+                );
+                if !is_same_image {
+                    return;
+                }
+                if this.state.image_crop.read(cx).is_edited()
+                    || this.sidecar_settings(cx) != settings
+                {
+                    eprintln!(
+                        "warn: not restoring the crop from {}, it was changed while loading",
+                        sidecar_path(&image_path).to_string_lossy()
+                    );
+                    return;
+                }
+
+                println!(
+                    "info: restoring crop from {}",
+                    sidecar_path(&image_path).to_string_lossy()
+                );
+                let dest_image_path = this
+                    .explicit
+                    .dest_image_path
+                    .then(|| this.state.dest_image_path.read(cx).clone());
+                let image_transform = *this.state.image_transform.read(cx);
+                this.replace_state(
+                    Some(SourceImage::Path(image_path)),
+                    dest_image_path,
+                    image_transform,
+                    Some(sidecar),
                     window,
                     cx,
-                    image_crop.clone(),
-                    move |new_value, image_crop, cx| {
-                        if new_value != f32::from(image_crop.crop_y) as u32 {
-                            image_crop_clone.write(
-                                cx,
-                                ImageCrop::Cropped {
-                                    crop_x: image_crop.crop_x,
-                                    crop_y: (new_value as f32).into(),
-                                    width: image_crop.width,
-                                    height: image_crop.height,
-                                },
-                            );
-                        }
-                    },
-                )
+                );
+                cx.notify();
+            });
+        })
+        .detach();
+    }
+
+    /// The settings restored from a sidecar, to tell whether they were
+    /// changed while it was read
+    fn sidecar_settings(
+        &self,
+        cx: &Context<Self>,
+    ) -> (ImageTransform, CropShape, CanvasFill, OutputResize, PathBuf) {
+        (
+            *self.state.image_transform.read(cx),
+            *self.state.crop_shape.read(cx),
+            *self.state.canvas_fill.read(cx),
+            *self.state.output_resize.read(cx),
+            self.state.dest_image_path.read(cx).clone(),
+        )
+    }
+
+    /// Returns the source image with the current rotation and flips applied.
+    /// The transformed image is computed in the background once the transform
+    /// stops changing, the previous one is shown until then, none while it
+    /// has another size
+    fn transformed_image(
+        &mut self,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+        image: &Arc<RenderImage>,
+        image_transform: ImageTransform,
+    ) -> Option<Arc<RenderImage>> {
+        if image_transform.is_identity() {
+            self.pending_transform = None;
+            if let Some(transformed) = self.transformed_image.take() {
+                window.drop_image(transformed.image).ok();
             }
+            return Some(image.clone());
+        }
+
+        let is_current = |transformed: &TransformedImage| {
+            transformed.source_id == image.id && transformed.image_transform == image_transform
+        };
+        if let Some(transformed) = &self.transformed_image
+            && is_current(transformed)
+        {
+            return Some(transformed.image.clone());
+        }
+
+        if !self
+            .pending_transform
+            .as_ref()
+            .is_some_and(|(pending, _)| *pending == image_transform)
+        {
+            let source_id = image.id;
+            let image = image.clone();
+            let task = cx.spawn_in(window, async move |this, cx| {
+                cx.background_executor().timer(TRANSFORM_DEBOUNCE).await;
+                let transformed = cx
+                    .background_spawn(async move {
+                        let Some(image_buf) = rgba_image(&image) else {
+                            eprintln!("error: can't retrieve image buffer for transforming");
+                            return None;
+                        };
+                        Some(render_image(image_transform.apply(&image_buf)))
+                    })
+                    .await;
+                let _ = this.update_in(cx, |this, window, cx| {
+                    this.pending_transform = None;
+                    let Some(transformed) = transformed else {
+                        return;
+                    };
+                    let previous = this.transformed_image.replace(TransformedImage {
+                        source_id,
+                        image_transform,
+                        image: transformed,
+                    });
+                    if let Some(previous) = previous {
+                        window.drop_image(previous.image).ok();
+                    }
+                    cx.notify();
+                });
+            });
+            self.pending_transform = Some((image_transform, task));
+        }
+
+        // The previous image is only shown while it has the new size, so the
+        // crop lines up with it
+        let image_size = image.size(0);
+        let (width, height) =
+            image_transform.transformed_size(image_size.width.into(), image_size.height.into());
+        let previous = self.transformed_image.as_ref().filter(|transformed| {
+            let previous_size = transformed.image.size(0);
+            transformed.source_id == image.id
+                && (previous_size.width.into(), previous_size.height.into()) == (width, height)
         });
-        width.update(cx, {
-            let image_crop_clone = image_crop.clone();
-            |view, cx| {
-                view.subscribe(
-                    window,
-                    cx,
-                    image_crop.clone(),
-                    move |new_value, image_crop, cx| {
-                        if new_value != f32::from(image_crop.width) as u32 {
-                            image_crop_clone.write(
-                                cx,
-                                ImageCrop::Cropped {
-                                    crop_x: image_crop.crop_x,
-                                    crop_y: image_crop.crop_y,
-                                    width: (new_value as f32).into(),
-                                    height: image_crop.height,
-                                },
-                            );
-                        }
-                    },
-                )
+        previous.map(|transformed| transformed.image.clone())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn new_state(
+        window: &mut Window,
+        cx: &mut Context<Self>,
+        image_source: Option<SourceImage>,
+        mut dest_image_path: Option<PathBuf>,
+        to_clipboard: bool,
+        mut image_transform: ImageTransform,
+        mut crop_shape: CropShape,
+        mut canvas_fill: CanvasFill,
+        mut output_resize: OutputResize,
+        is_slicing: bool,
+        grid_slice: GridSlice,
+        guides: Guides,
+        background: ViewportBackground,
+        coordinate_mode: CoordinateMode,
+        anchor: Anchor,
+        config: Rc<Config>,
+        preset: Option<Preset>,
+        sidecar: Option<Sidecar>,
+        explicit: ExplicitSettings,
+    ) -> (BasicropState, Vec<Subscription>) {
+        // The settings of an earlier crop of the image take precedence over
+        // the kept ones, so it can be adjusted and saved again, but not over
+        // those given on the command line
+        if let Some(sidecar) = &sidecar {
+            if !explicit.transform {
+                image_transform = sidecar.transform;
+            }
+            if !explicit.shape {
+                crop_shape = sidecar.output.shape;
+            }
+            if !explicit.fill {
+                canvas_fill = sidecar.output.fill;
             }
+            if !explicit.resize {
+                output_resize = sidecar.output.resize;
+            }
+            dest_image_path = dest_image_path.or_else(|| Some(sidecar.output.path.clone()));
+        }
+        // The crop is in the transformed image, it's only kept when the image
+        // is transformed the same way
+        let restored_crop = sidecar.and_then(|sidecar| {
+            if sidecar.transform == image_transform {
+                Some(sidecar.crop)
+            } else {
+                eprintln!(
+                    "warn: not restoring the crop from the sidecar, the image is rotated, \
+                     flipped or straightened differently"
+                );
+                None
+            }
+        });
+
+        let coordinate_mode_select = cx.new(|cx| {
+            let label = coordinate_mode.label();
+            let selected_index = COORDINATE_MODES.iter().position(|mode| *mode == label);
+            SelectState::new(
+                COORDINATE_MODES.to_vec(),
+                selected_index.map(IndexPath::new),
+                window,
+                cx,
+            )
         });
-        height.update(cx, {
-            let image_crop_clone = image_crop.clone();
-            |view, cx| {
-                view.subscribe(
+        let coordinate_mode = cx.new(|_| coordinate_mode);
+        let anchor = cx.new(|_| anchor);
+        // The destination follows the output template until it's edited,
+        // the crop isn't known until the image is loaded
+        let output_template = match (&dest_image_path, &image_source) {
+            (None, Some(SourceImage::Path(_))) => Some(config.output.template.clone()),
+            _ => None,
+        };
+        let dest_image_path = dest_image_path
+            .or_else(|| match &image_source {
+                Some(SourceImage::Path(image_path)) => {
+                    let empty_crop = FinalizedImageCrop {
+                        crop_x: 0,
+                        crop_y: 0,
+                        width: 0,
+                        height: 0,
+                    };
+                    Some(config.output.template.expand(image_path, &empty_crop))
+                }
+                Some(SourceImage::Decoded(_)) => Some(config.output.clipboard_path.clone()),
+                None => None,
+            })
+            .unwrap_or_default();
+        let image_crop = cx.new(|_| CropModel::default());
+        let image_crop_initial = cx.new(|_| ImageCrop::Uninitialized);
+        let dpi = match &image_source {
+            Some(SourceImage::Path(image_path)) => read_dpi(image_path),
+            _ => None,
+        }
+        .unwrap_or(DEFAULT_DPI);
+        let [crop_x, crop_y, width, height] = [0, 1, 2, 3].map(|field| {
+            cx.new(|cx| {
+                counter_input::CounterView::new(
                     window,
                     cx,
+                    field,
                     image_crop.clone(),
-                    move |new_value, image_crop, cx| {
-                        if new_value != f32::from(image_crop.height) as u32 {
-                            image_crop_clone.write(
-                                cx,
-                                ImageCrop::Cropped {
-                                    crop_x: image_crop.crop_x,
-                                    crop_y: image_crop.crop_y,
-                                    width: image_crop.width,
-                                    height: (new_value as f32).into(),
-                                },
-                            );
-                        }
-                    },
+                    coordinate_mode.clone(),
+                    image_crop_initial.clone(),
+                    dpi,
                 )
-            }
+            })
+        });
+        let polygon_crop = cx.new(|_| PolygonCrop::default());
+        let is_drawing_polygon = cx.new(|_| false);
+        let dragged_vertex = cx.new(|_| None);
+        let canvas_view = cx.new(|_| None);
+        let crop_regions = cx.new(|_| Vec::<CropRegion>::new());
+        let region_name_input = cx.new(|cx| InputState::new(window, cx).placeholder("Name"));
+        let selected_region = cx.new(|_| None::<usize>);
+        let detect_tolerance_slider = cx.new(|_| {
+            SliderState::new()
+                .min(1.)
+                .max(50.)
+                .step(1.)
+                .default_value(DEFAULT_DETECT_TOLERANCE)
+        });
+        let is_straightening = cx.new(|_| false);
+        let straighten_drag_angle = cx.new(|_| None);
+        let straighten_slider = cx.new(|_| {
+            SliderState::new()
+                .min(-MAX_STRAIGHTEN_ANGLE)
+                .max(MAX_STRAIGHTEN_ANGLE)
+                .step(0.1)
+                .default_value(image_transform.straighten)
+        });
+        let image_transform = cx.new(|_| image_transform);
+        let crop_shape_select = cx.new(|cx| {
+            let shape = crop_shape.label();
+            let selected_index = CROP_SHAPES.iter().position(|label| *label == shape);
+            SelectState::new(
+                CROP_SHAPES.to_vec(),
+                selected_index.map(IndexPath::new),
+                window,
+                cx,
+            )
+        });
+        let corner_radius_input = cx.new(|cx| {
+            let corner_radius = match crop_shape {
+                CropShape::Rectangle { corner_radius } => corner_radius,
+                CropShape::Ellipse => 0,
+            };
+            InputState::new(window, cx).default_value(corner_radius.to_string())
+        });
+        let crop_shape = cx.new(|_| crop_shape);
+        let canvas_fill_select = cx.new(|cx| {
+            let mode = canvas_fill.mode();
+            let selected_index = CANVAS_FILL_MODES.iter().position(|label| *label == mode);
+            SelectState::new(
+                CANVAS_FILL_MODES.to_vec(),
+                selected_index.map(IndexPath::new),
+                window,
+                cx,
+            )
+        });
+        let canvas_fill_color_input = cx.new(|cx| {
+            let color = match canvas_fill {
+                CanvasFill::Color(color) => format_color(color),
+                _ => "#ffffff".to_string(),
+            };
+            InputState::new(window, cx).default_value(color)
+        });
+        let canvas_fill = cx.new(|_| canvas_fill);
+        let output_mode_select = cx.new(|cx| {
+            let mode = output_resize.size.mode();
+            let selected_index = OUTPUT_SIZE_MODES.iter().position(|label| *label == mode);
+            SelectState::new(
+                OUTPUT_SIZE_MODES.to_vec(),
+                selected_index.map(IndexPath::new),
+                window,
+                cx,
+            )
+        });
+        let output_filter_select = cx.new(|cx| {
+            let filter = output_resize.filter_name();
+            let filter_names = FILTERS.map(|(name, _)| name).to_vec();
+            let selected_index = filter_names.iter().position(|name| *name == filter);
+            SelectState::new(filter_names, selected_index.map(IndexPath::new), window, cx)
+        });
+        let (output_width, output_height, output_percent) = output_resize.size.field_values();
+        let output_width_input = cx.new(|cx| {
+            InputState::new(window, cx)
+                .placeholder("auto")
+                .default_value(output_width)
+        });
+        let output_height_input = cx.new(|cx| {
+            InputState::new(window, cx)
+                .placeholder("auto")
+                .default_value(output_height)
+        });
+        let output_percent_input =
+            cx.new(|cx| InputState::new(window, cx).default_value(output_percent));
+        let output_resize = cx.new(|_| output_resize);
+        let output_options = OutputOptions::new(config.output.jpeg_quality);
+        let jpeg_quality_input = cx.new(|cx| {
+            InputState::new(window, cx).default_value(output_options.jpeg_quality.to_string())
+        });
+        let png_compression_select = cx.new(|cx| {
+            let compression = output_options.png_compression_label();
+            let labels = PNG_COMPRESSIONS.map(|(label, _)| label).to_vec();
+            let selected_index = labels.iter().position(|label| *label == compression);
+            SelectState::new(labels, selected_index.map(IndexPath::new), window, cx)
+        });
+        let output_options = cx.new(|_| output_options);
+        let preset_select = cx.new(|cx| {
+            let names: Vec<_> = config
+                .presets
+                .iter()
+                .map(|preset| preset.name.clone())
+                .collect();
+            let selected_index = preset
+                .as_ref()
+                .and_then(|preset| names.iter().position(|name| *name == preset.name));
+            SelectState::new(names, selected_index.map(IndexPath::new), window, cx)
+        });
+        let preset = cx.new(|_| preset);
+        let is_slicing = cx.new(|_| is_slicing);
+        let grid_mode_select = cx.new(|cx| {
+            let mode = grid_slice.cells.mode();
+            let selected_index = GRID_MODES.iter().position(|label| *label == mode);
+            SelectState::new(
+                GRID_MODES.to_vec(),
+                selected_index.map(IndexPath::new),
+                window,
+                cx,
+            )
+        });
+        let (grid_horizontal, grid_vertical) = grid_slice.cells.field_values();
+        let grid_horizontal_input =
+            cx.new(|cx| InputState::new(window, cx).default_value(grid_horizontal));
+        let grid_vertical_input =
+            cx.new(|cx| InputState::new(window, cx).default_value(grid_vertical));
+        let grid_margin_input =
+            cx.new(|cx| InputState::new(window, cx).default_value(grid_slice.margin.to_string()));
+        let grid_spacing_input =
+            cx.new(|cx| InputState::new(window, cx).default_value(grid_slice.spacing.to_string()));
+        let grid_slice = cx.new(|_| grid_slice);
+        let guides_select = cx.new(|cx| {
+            let label = guides.label();
+            let selected_index = GUIDES.iter().position(|guides| *guides == label);
+            SelectState::new(
+                GUIDES.to_vec(),
+                selected_index.map(IndexPath::new),
+                window,
+                cx,
+            )
+        });
+        let guides = cx.new(|_| guides);
+        let background_select = cx.new(|cx| {
+            let label = background.label();
+            let selected_index = VIEWPORT_BACKGROUNDS
+                .iter()
+                .position(|(background_label, _)| *background_label == label);
+            SelectState::new(
+                VIEWPORT_BACKGROUNDS.map(|(label, _)| label).to_vec(),
+                selected_index.map(IndexPath::new),
+                window,
+                cx,
+            )
+        });
+        let background = cx.new(|_| background);
+        let dest_input = cx.new(|cx| {
+            InputState::new(window, cx).default_value(dest_image_path.to_string_lossy().to_string())
         });
+        let dest_image_path = cx.new(|_| dest_image_path);
+        let image_saved_notification = cx.new(|cx| {
+            cx.observe_self(|_, cx| {
+                cx.shutdown();
+            })
+            .detach();
+        });
+        let open_image_request = cx.new(|_| None);
+        let recent_files = recent_files::load();
+
+        // The handlers are dropped with the state when another image is opened
+        let mut subscriptions = Vec::new();
 
-        Basicrop {
-            state: BasicropState {
-                crop_x,
-                crop_y,
-                width,
-                height,
-                is_selecting,
-                mouse_initial_pos,
-                mouse_pos,
-                image_path,
-                image_crop,
-                image_crop_initial,
-                dest_image_path,
-                image_saved_notification,
+        // Handler for opening a different image
+        subscriptions.push(cx.observe_in(
+            &open_image_request,
+            window,
+            |this, open_image_request, window, cx| {
+                if let Some(image_source) = open_image_request.read(cx).clone() {
+                    this.open_image(image_source, window, cx);
+                }
             },
+        ));
+
+        // Handler for the straighten angle slider
+        subscriptions.push(cx.subscribe_in(
+            &straighten_slider,
+            window,
+            |this, _, evt: &SliderEvent, window, cx| {
+                let SliderEvent::Change(value) = evt;
+                set_straighten_angle(window, cx, &this.state, value.start());
+            },
+        ));
+
+        // Handler for the photo detection tolerance label
+        subscriptions.push(cx.subscribe_in(
+            &detect_tolerance_slider,
+            window,
+            |_, _, _: &SliderEvent, _, cx| cx.notify(),
+        ));
+
+        // Handlers for the shape controls
+        subscriptions.push(cx.subscribe_in(
+            &crop_shape_select,
+            window,
+            |this, _, _: &SelectEvent<Vec<&'static str>>, _, cx| {
+                update_crop_shape(cx, &this.state);
+            },
+        ));
+        subscriptions.push(cx.subscribe_in(
+            &corner_radius_input,
+            window,
+            |this, _, evt: &InputEvent, _, cx| {
+                if let InputEvent::Change = evt {
+                    update_crop_shape(cx, &this.state);
+                }
+            },
+        ));
+
+        // Handlers for the canvas fill controls
+        subscriptions.push(cx.subscribe_in(
+            &canvas_fill_select,
+            window,
+            |this, _, _: &SelectEvent<Vec<&'static str>>, _, cx| {
+                update_canvas_fill(cx, &this.state);
+            },
+        ));
+        subscriptions.push(cx.subscribe_in(
+            &canvas_fill_color_input,
+            window,
+            |this, _, evt: &InputEvent, _, cx| {
+                if let InputEvent::Change = evt {
+                    update_canvas_fill(cx, &this.state);
+                }
+            },
+        ));
+
+        // Handlers for the output size controls
+        for select in [&output_mode_select, &output_filter_select] {
+            subscriptions.push(cx.subscribe_in(
+                select,
+                window,
+                |this, _, _: &SelectEvent<Vec<&'static str>>, _, cx| {
+                    update_output_resize(cx, &this.state);
+                },
+            ));
+        }
+        for input in [
+            &output_width_input,
+            &output_height_input,
+            &output_percent_input,
+        ] {
+            subscriptions.push(cx.subscribe_in(
+                input,
+                window,
+                |this, _, evt: &InputEvent, _, cx| {
+                    if let InputEvent::Change = evt {
+                        update_output_resize(cx, &this.state);
+                    }
+                },
+            ));
         }
+
+        // Handlers for the output format's options
+        subscriptions.push(cx.subscribe_in(
+            &jpeg_quality_input,
+            window,
+            |this, _, evt: &InputEvent, _, cx| {
+                if let InputEvent::Change = evt {
+                    update_output_options(cx, &this.state);
+                }
+            },
+        ));
+        subscriptions.push(cx.subscribe_in(
+            &png_compression_select,
+            window,
+            |this, _, _: &SelectEvent<Vec<&'static str>>, _, cx| {
+                update_output_options(cx, &this.state);
+            },
+        ));
+
+        // Handler for choosing a preset
+        subscriptions.push(cx.subscribe_in(
+            &preset_select,
+            window,
+            |this, _, evt: &SelectEvent<Vec<String>>, window, cx| {
+                let SelectEvent::Confirm(Some(name)) = evt else {
+                    return;
+                };
+                if let Some(preset) = find_preset(&this.state.config.presets, name).cloned() {
+                    apply_preset(window, cx, &this.state, preset);
+                }
+            },
+        ));
+
+        // Handlers for the grid controls
+        subscriptions.push(cx.subscribe_in(
+            &grid_mode_select,
+            window,
+            |this, select, _: &SelectEvent<Vec<&'static str>>, window, cx| {
+                // Column counts make no sense as cell sizes and the other
+                // way around
+                let mode = select
+                    .read(cx)
+                    .selected_value()
+                    .copied()
+                    .unwrap_or_default();
+                if mode != this.state.grid_slice.read(cx).cells.mode() {
+                    let (horizontal, vertical) = GridCells::default_for_mode(mode).field_values();
+                    let inputs = [
+                        (&this.state.grid_horizontal_input, horizontal),
+                        (&this.state.grid_vertical_input, vertical),
+                    ];
+                    for (input, value) in inputs {
+                        input.update(cx, |input, cx| input.set_value(value, window, cx));
+                    }
+                }
+                update_grid_slice(cx, &this.state);
+            },
+        ));
+        for input in [
+            &grid_horizontal_input,
+            &grid_vertical_input,
+            &grid_margin_input,
+            &grid_spacing_input,
+        ] {
+            subscriptions.push(cx.subscribe_in(
+                input,
+                window,
+                |this, _, evt: &InputEvent, _, cx| {
+                    if let InputEvent::Change = evt {
+                        update_grid_slice(cx, &this.state);
+                    }
+                },
+            ));
+        }
+
+        // Handler for what the crop fields show
+        subscriptions.push(cx.subscribe_in(
+            &coordinate_mode_select,
+            window,
+            |this, _, evt: &SelectEvent<Vec<&'static str>>, _, cx| {
+                if let SelectEvent::Confirm(Some(label)) = evt {
+                    this.state
+                        .coordinate_mode
+                        .write(cx, CoordinateMode::from_label(label));
+                }
+            },
+        ));
+
+        // Handler for the guides over the crop
+        subscriptions.push(cx.subscribe_in(
+            &guides_select,
+            window,
+            |this, _, evt: &SelectEvent<Vec<&'static str>>, _, cx| {
+                if let SelectEvent::Confirm(Some(label)) = evt {
+                    this.state.guides.write(cx, Guides::from_label(label));
+                }
+            },
+        ));
+
+        // Handler for the background behind the image
+        subscriptions.push(cx.subscribe_in(
+            &background_select,
+            window,
+            |this, _, evt: &SelectEvent<Vec<&'static str>>, _, cx| {
+                if let SelectEvent::Confirm(Some(label)) = evt {
+                    this.state
+                        .background
+                        .write(cx, ViewportBackground::from_label(label));
+                }
+            },
+        ));
+
+        // Handler for dragging across the canvas
+        subscriptions.push(cx.subscribe_in(
+            &image_crop,
+            window,
+            |this, _, evt: &DragEvent, window, cx| {
+                handle_canvas_drag(window, cx, &this.state, evt);
+            },
+        ));
+
+        // Handler for editing the selected region with the crop
+        subscriptions.push(cx.observe(&image_crop, {
+            let crop_regions = crop_regions.clone();
+            let selected_region = selected_region.clone();
+            move |_, image_crop, cx| {
+                let Some(index) = *selected_region.read(cx) else {
+                    return;
+                };
+                let image_crop = image_crop.read(cx).crop().clone();
+                let mut regions = crop_regions.read(cx).clone();
+                if let Some(region) = regions.get_mut(index)
+                    && region.image_crop != image_crop
+                {
+                    region.image_crop = image_crop;
+                    crop_regions.write(cx, regions);
+                }
+            }
+        }));
+
+        // Handler for building the destination from the output template
+        // with the crop
+        subscriptions.push(cx.observe_in(&image_crop, window, {
+            let dest_image_path = dest_image_path.clone();
+            let dest_input = dest_input.clone();
+            move |this, image_crop, window, cx| {
+                let (Some(output_template), Some(SourceImage::Path(image_path))) =
+                    (&this.state.output_template, &this.state.image_source)
+                else {
+                    return;
+                };
+                let Some(final_crop) = image_crop.read(cx).crop().to_final() else {
+                    return;
+                };
+                if !output_template.uses_crop() {
+                    return;
+                }
+
+                let path = output_template.expand(image_path, &final_crop);
+                if &path != dest_image_path.read(cx) {
+                    dest_image_path.write(cx, path.clone());
+                    dest_input.update(cx, |input, cx| {
+                        input.set_value(path.to_string_lossy().to_string(), window, cx);
+                    });
+                }
+            }
+        }));
+
+        // Handler for destination path updates
+        subscriptions.push(cx.subscribe_in(&dest_input, window, {
+            let dest_image_path = dest_image_path.clone();
+            move |this, input, evt: &InputEvent, window, cx| {
+                if let InputEvent::Change = evt {
+                    let path = PathBuf::from(input.read(cx).value().as_str());
+                    if &path != dest_image_path.read(cx) {
+                        this.state.output_template = None;
+                        let output_format = OutputFormat::from_path(&path);
+                        let format_changed =
+                            OutputFormat::from_path(dest_image_path.read(cx)) != output_format;
+                        dest_image_path.write(cx, path);
+                        if format_changed {
+                            reset_output_options(window, cx, &this.state, output_format);
+                        }
+                    }
+                }
+            }
+        }));
+
+        // Handlers writing the fields to the crop, the fields depend on the
+        // coordinate mode and show the crop again once it changed. Values are
+        // kept within the range where the crop stays valid
+        let fields = [
+            crop_x.clone(),
+            crop_y.clone(),
+            width.clone(),
+            height.clone(),
+        ];
+        for (index, field) in fields.iter().enumerate() {
+            field.update(cx, |view, _| {
+                view.on_change({
+                    let image_crop = image_crop.clone();
+                    let image_crop_initial = image_crop_initial.clone();
+                    let coordinate_mode = coordinate_mode.clone();
+                    let anchor = anchor.clone();
+                    move |new_value, crop, cx| {
+                        let mode = *coordinate_mode.read(cx);
+                        let anchor = *anchor.read(cx);
+                        if new_value == mode.field_values(&crop)[index] {
+                            return None;
+                        }
+                        if let Some(image_bounds) = image_crop_initial.read(cx).to_final()
+                            && let Some(clamped_value) = mode.clamp_field(
+                                index,
+                                new_value,
+                                &crop,
+                                anchor,
+                                image_bounds.width,
+                                image_bounds.height,
+                            )
+                        {
+                            return Some(clamped_value);
+                        }
+                        let crop = mode.apply(index, new_value, &crop, anchor)?;
+                        image_crop.update(cx, |model, cx| {
+                            model.set(crop, CropSource::Field(index), cx)
+                        });
+                        None
+                    }
+                })
+            });
+        }
+
+        let state = BasicropState {
+            crop_x,
+            crop_y,
+            width,
+            height,
+            coordinate_mode,
+            coordinate_mode_select,
+            anchor,
+            image_source,
+            image_crop,
+            image_crop_initial,
+            image_transform,
+            is_straightening,
+            straighten_slider,
+            straighten_drag_angle,
+            polygon_crop,
+            is_drawing_polygon,
+            dragged_vertex,
+            canvas_view,
+            crop_regions,
+            region_name_input,
+            selected_region,
+            detect_tolerance_slider,
+            is_slicing,
+            grid_slice,
+            grid_mode_select,
+            grid_horizontal_input,
+            grid_vertical_input,
+            grid_margin_input,
+            grid_spacing_input,
+            guides,
+            guides_select,
+            background,
+            background_select,
+            crop_shape,
+            crop_shape_select,
+            corner_radius_input,
+            canvas_fill,
+            canvas_fill_select,
+            canvas_fill_color_input,
+            output_resize,
+            output_mode_select,
+            output_filter_select,
+            output_width_input,
+            output_height_input,
+            output_percent_input,
+            output_options,
+            jpeg_quality_input,
+            png_compression_select,
+            config,
+            preset,
+            preset_select,
+            output_template,
+            restored_crop,
+            dest_image_path,
+            dest_input,
+            image_saved_notification,
+            open_image_request,
+            recent_files,
+            to_clipboard,
+        };
+
+        (state, subscriptions)
     }
 }
 
 impl Render for Basicrop {
     fn render(&mut self, window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
-        let state = &mut self.state;
+        let source_asset = match &self.state.image_source {
+            Some(SourceImage::Path(image_path)) => {
+                let image_path: Resource = image_path.clone().into();
+                match window.use_asset::<ImageAssetLoader>(&image_path, cx) {
+                    Some(Ok(asset)) => LoadingImage::Image(asset),
+                    Some(Err(_)) => LoadingImage::Failed,
+                    _ => LoadingImage::Loading,
+                }
+            }
+            Some(SourceImage::Decoded(image)) => LoadingImage::Image(image.clone()),
+            None => LoadingImage::Empty,
+        };
 
-        let image_asset = match window.use_asset::<ImageAssetLoader>(&state.image_path, cx) {
-            Some(Ok(asset)) => LoadingImage::Image(asset),
-            Some(Err(_)) => LoadingImage::Failed,
-            _ => LoadingImage::Loading,
+        // The displayed image and crop coordinates are in the rotated and
+        // flipped space, the source is kept for the save pipeline
+        let image_transform = *self.state.image_transform.read(cx);
+        let image_asset = match &source_asset {
+            LoadingImage::Image(image) => {
+                match self.transformed_image(window, cx, image, image_transform) {
+                    Some(image) => LoadingImage::Image(image),
+                    None => LoadingImage::Loading,
+                }
+            }
+            _ => source_asset.clone(),
         };
+        let state = &mut self.state;
 
         // let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
         // println!("[{}] in render", time.as_millis());
 
         // Update the imagecrop with the initial image dimensions
         if let (ImageCrop::Uninitialized, LoadingImage::Image(image)) =
-            (state.image_crop.read(cx), &image_asset)
+            (state.image_crop.read(cx).crop(), &image_asset)
         {
             let size = image.size(0);
-            let image_crop = ImageCrop::Cropped {
-                crop_x: px(0.),
-                crop_y: px(0.),
-                width: (u32::from(size.width) as f32).into(),
-                height: (u32::from(size.height) as f32).into(),
+            let image_crop_initial = ImageCrop::Cropped {
+                crop_x: 0,
+                crop_y: 0,
+                width: i32::from(size.width),
+                height: i32::from(size.height),
             };
-            state.image_crop.write(cx, image_crop.clone());
-            state.image_crop_initial.write(cx, image_crop);
-            state.height.update(cx, |input, cx| {
-                input.get_state().update(cx, |input, cx| {
-                    let height = u32::from(size.height).to_string();
-                    input.set_value(height, window, cx);
-                });
-            });
-            state.width.update(cx, |input, cx| {
-                input.get_state().update(cx, |input, cx| {
-                    let width = u32::from(size.width).to_string();
-                    input.set_value(width, window, cx);
-                });
+
+            // A straightened image starts out cropped to the region without
+            // empty corners, or to the preset's aspect ratio within it,
+            // unless the crop was restored from a sidecar
+            let (crop_x, crop_y, width, height) = image_transform
+                .straightened_bounds(u32::from(size.width) as f32, u32::from(size.height) as f32);
+            let (crop_x, crop_y, width, height) = match state.preset.read(cx) {
+                Some(preset) => preset.crop(crop_x, crop_y, width, height),
+                None => (crop_x, crop_y, width, height),
+            };
+            let (image_width, image_height) = (u32::from(size.width), u32::from(size.height));
+            let restored_crop = state.restored_crop.take().filter(|crop| {
+                let is_valid = crop.is_valid(image_width, image_height);
+                if !is_valid {
+                    eprintln!(
+                        "warn: not restoring the crop from the sidecar, it's outside the image"
+                    );
+                }
+                is_valid
             });
+            let image_crop = match restored_crop {
+                Some(crop) => crop.into(),
+                None => InitializedImageCrop::inside_edges(
+                    crop_x,
+                    crop_y,
+                    crop_x + width,
+                    crop_y + height,
+                ),
+            };
+            // The bounds first, the fields show percentages of them
+            state.image_crop_initial.write(cx, image_crop_initial);
+            state
+                .image_crop
+                .update(cx, |model, cx| model.set(image_crop, CropSource::Reset, cx));
 
             println!(
                 "info: initialized image with dimensions: {}x{}",
@@ -202,6 +1107,9 @@ impl Render for Basicrop {
             );
         }
 
-        render_main_view(state, image_asset, cx)
+        div()
+            .size_full()
+            .child(render_main_view(state, image_asset, source_asset, cx))
+            .children(Root::render_notification_layer(window, cx))
     }
 }
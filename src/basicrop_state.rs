@@ -1,8 +1,27 @@
+use crate::anchor::Anchor;
+use crate::canvas_fill::CanvasFill;
+use crate::config::Config;
+use crate::coordinate_mode::CoordinateMode;
 use crate::counter_input::CounterView;
-use crate::image_crop::ImageCrop;
-use crate::misc::CroppingMousePosition;
-use gpui::{Entity, Pixels, Point, Resource};
+use crate::crop_model::CropModel;
+use crate::crop_shape::CropShape;
+use crate::grid_slice::GridSlice;
+use crate::guides::Guides;
+use crate::image_crop::{CropRegion, FinalizedImageCrop, ImageCrop, PolygonCrop};
+use crate::image_transform::ImageTransform;
+use crate::misc::SourceImage;
+use crate::output_format::OutputOptions;
+use crate::output_size::OutputResize;
+use crate::output_template::OutputTemplate;
+use crate::preset::Preset;
+use crate::selection_canvas::CanvasView;
+use crate::viewport_background::ViewportBackground;
+use gpui::Entity;
+use gpui_component::input::InputState;
+use gpui_component::select::SelectState;
+use gpui_component::slider::SliderState;
 use std::path::PathBuf;
+use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub struct BasicropState {
@@ -10,12 +29,63 @@ pub struct BasicropState {
     pub crop_y: Entity<CounterView>,
     pub width: Entity<CounterView>,
     pub height: Entity<CounterView>,
-    pub mouse_initial_pos: Entity<Point<Pixels>>,
-    pub mouse_pos: Entity<CroppingMousePosition>,
-    pub is_selecting: Entity<bool>,
-    pub image_crop: Entity<ImageCrop>,
+    pub coordinate_mode: Entity<CoordinateMode>,
+    pub coordinate_mode_select: Entity<SelectState<Vec<&'static str>>>,
+    /// The point kept in place when the width or height is edited
+    pub anchor: Entity<Anchor>,
+    pub image_crop: Entity<CropModel>,
     pub image_crop_initial: Entity<ImageCrop>,
-    pub image_path: Resource,
+    pub image_transform: Entity<ImageTransform>,
+    pub is_straightening: Entity<bool>,
+    pub straighten_slider: Entity<SliderState>,
+    pub straighten_drag_angle: Entity<Option<f32>>,
+    pub polygon_crop: Entity<PolygonCrop>,
+    pub is_drawing_polygon: Entity<bool>,
+    pub dragged_vertex: Entity<Option<usize>>,
+    pub canvas_view: Entity<Option<CanvasView>>,
+    pub crop_regions: Entity<Vec<CropRegion>>,
+    pub region_name_input: Entity<InputState>,
+    pub selected_region: Entity<Option<usize>>,
+    pub detect_tolerance_slider: Entity<SliderState>,
+    pub is_slicing: Entity<bool>,
+    pub grid_slice: Entity<GridSlice>,
+    pub grid_mode_select: Entity<SelectState<Vec<&'static str>>>,
+    pub grid_horizontal_input: Entity<InputState>,
+    pub grid_vertical_input: Entity<InputState>,
+    pub grid_margin_input: Entity<InputState>,
+    pub grid_spacing_input: Entity<InputState>,
+    pub guides: Entity<Guides>,
+    pub guides_select: Entity<SelectState<Vec<&'static str>>>,
+    pub background: Entity<ViewportBackground>,
+    pub background_select: Entity<SelectState<Vec<&'static str>>>,
+    pub crop_shape: Entity<CropShape>,
+    pub crop_shape_select: Entity<SelectState<Vec<&'static str>>>,
+    pub corner_radius_input: Entity<InputState>,
+    pub canvas_fill: Entity<CanvasFill>,
+    pub canvas_fill_select: Entity<SelectState<Vec<&'static str>>>,
+    pub canvas_fill_color_input: Entity<InputState>,
+    pub output_resize: Entity<OutputResize>,
+    pub output_mode_select: Entity<SelectState<Vec<&'static str>>>,
+    pub output_filter_select: Entity<SelectState<Vec<&'static str>>>,
+    pub output_width_input: Entity<InputState>,
+    pub output_height_input: Entity<InputState>,
+    pub output_percent_input: Entity<InputState>,
+    /// Encoder settings, reset when the destination's format changes
+    pub output_options: Entity<OutputOptions>,
+    pub jpeg_quality_input: Entity<InputState>,
+    pub png_compression_select: Entity<SelectState<Vec<&'static str>>>,
+    pub config: Rc<Config>,
+    pub preset: Entity<Option<Preset>>,
+    pub preset_select: Entity<SelectState<Vec<String>>>,
+    pub image_source: Option<SourceImage>,
+    /// Builds the destination until it's edited
+    pub output_template: Option<OutputTemplate>,
+    /// Crop from the image's sidecar, applied once the image is loaded
+    pub restored_crop: Option<FinalizedImageCrop>,
     pub dest_image_path: Entity<PathBuf>,
+    pub dest_input: Entity<InputState>,
     pub image_saved_notification: Entity<()>,
+    pub open_image_request: Entity<Option<SourceImage>>,
+    pub recent_files: Vec<PathBuf>,
+    pub to_clipboard: bool,
 }
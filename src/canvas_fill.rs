@@ -0,0 +1,197 @@
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+/// Labels of the fill modes in the window, in the order of the variants of
+/// [`CanvasFill`]
+pub const CANVAS_FILL_MODES: [&str; 4] = ["Transparent", "Color", "Edge", "Mirror"];
+
+/// How the area of a crop outside the image is filled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum CanvasFill {
+    #[default]
+    Transparent,
+    Color(Rgba<u8>),
+    /// Repeats the nearest edge pixel
+    Edge,
+    /// Reflects the image at its edges
+    Mirror,
+}
+
+impl TryFrom<String> for CanvasFill {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        CanvasFill::parse(&value).ok_or_else(|| format!("invalid fill {value}"))
+    }
+}
+
+/// Writes the fill as the value of `--fill`
+impl From<CanvasFill> for String {
+    fn from(canvas_fill: CanvasFill) -> Self {
+        match canvas_fill {
+            CanvasFill::Transparent => "transparent".to_string(),
+            CanvasFill::Color(color) => format_color(color),
+            CanvasFill::Edge => "edge".to_string(),
+            CanvasFill::Mirror => "mirror".to_string(),
+        }
+    }
+}
+
+impl CanvasFill {
+    /// Parses the value of `--fill`, `transparent`, `edge`, `mirror` or a
+    /// `#RRGGBB[AA]` color
+    pub fn parse(value: &str) -> Option<CanvasFill> {
+        match value.to_ascii_lowercase().as_str() {
+            "transparent" => Some(CanvasFill::Transparent),
+            "edge" => Some(CanvasFill::Edge),
+            "mirror" => Some(CanvasFill::Mirror),
+            color => parse_color(color).map(CanvasFill::Color),
+        }
+    }
+
+    /// Builds the fill from the mode and color fields in the window, an
+    /// invalid color is transparent
+    pub fn from_fields(mode: &str, color: &str) -> CanvasFill {
+        match CANVAS_FILL_MODES.iter().position(|label| *label == mode) {
+            Some(1) => parse_color(color.trim())
+                .map(CanvasFill::Color)
+                .unwrap_or_default(),
+            Some(2) => CanvasFill::Edge,
+            Some(3) => CanvasFill::Mirror,
+            _ => CanvasFill::Transparent,
+        }
+    }
+
+    pub fn mode(&self) -> &'static str {
+        match self {
+            CanvasFill::Transparent => CANVAS_FILL_MODES[0],
+            CanvasFill::Color(_) => CANVAS_FILL_MODES[1],
+            CanvasFill::Edge => CANVAS_FILL_MODES[2],
+            CanvasFill::Mirror => CANVAS_FILL_MODES[3],
+        }
+    }
+
+    /// The pixel at a position that may be outside the image
+    pub fn pixel(&self, image_buf: &RgbaImage, x: i64, y: i64) -> Rgba<u8> {
+        let (width, height) = image_buf.dimensions();
+        if width == 0 || height == 0 {
+            return Rgba([0; 4]);
+        }
+
+        let inside = x >= 0 && y >= 0 && x < width as i64 && y < height as i64;
+        if inside {
+            return *image_buf.get_pixel(x as u32, y as u32);
+        }
+
+        match self {
+            CanvasFill::Transparent => Rgba([0; 4]),
+            CanvasFill::Color(color) => *color,
+            CanvasFill::Edge => *image_buf.get_pixel(
+                x.clamp(0, width as i64 - 1) as u32,
+                y.clamp(0, height as i64 - 1) as u32,
+            ),
+            CanvasFill::Mirror => *image_buf.get_pixel(mirror(x, width), mirror(y, height)),
+        }
+    }
+}
+
+/// Formats a color as `#RRGGBBAA`, leaving out opaque alpha
+pub fn format_color(color: Rgba<u8>) -> String {
+    let [red, green, blue, alpha] = color.0;
+    if alpha == 0xff {
+        format!("#{red:02x}{green:02x}{blue:02x}")
+    } else {
+        format!("#{red:02x}{green:02x}{blue:02x}{alpha:02x}")
+    }
+}
+
+pub fn parse_color(color: &str) -> Option<Rgba<u8>> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return None;
+    }
+
+    let channel = |index: usize| u8::from_str_radix(hex.get(index * 2..index * 2 + 2)?, 16).ok();
+    let alpha = if hex.len() == 8 { channel(3)? } else { 0xff };
+
+    Some(Rgba([channel(0)?, channel(1)?, channel(2)?, alpha]))
+}
+
+/// Reflects a coordinate into `0..size`, repeating the edge pixel like
+/// `cba|abc|cba`
+fn mirror(position: i64, size: u32) -> u32 {
+    let size = size as i64;
+    let position = position.rem_euclid(size * 2);
+    if position < size {
+        position as u32
+    } else {
+        (size * 2 - 1 - position) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_are_parsed_and_written_back() {
+        for value in ["transparent", "edge", "mirror", "#102030", "#10203040"] {
+            let canvas_fill = CanvasFill::parse(value).unwrap();
+            assert_eq!(String::from(canvas_fill), value);
+        }
+        assert_eq!(
+            CanvasFill::parse("#FF0000"),
+            Some(CanvasFill::Color(Rgba([255, 0, 0, 255])))
+        );
+        assert_eq!(CanvasFill::parse("#ff00"), None);
+        assert_eq!(CanvasFill::parse("#gg0000"), None);
+        assert_eq!(CanvasFill::parse("#ff00é"), None);
+    }
+
+    #[test]
+    fn fields_map_back_to_the_same_fill() {
+        let color = Rgba([1, 2, 3, 4]);
+        for canvas_fill in [
+            CanvasFill::Transparent,
+            CanvasFill::Color(color),
+            CanvasFill::Edge,
+            CanvasFill::Mirror,
+        ] {
+            assert_eq!(
+                CanvasFill::from_fields(canvas_fill.mode(), &format_color(color)),
+                canvas_fill
+            );
+        }
+        assert_eq!(
+            CanvasFill::from_fields(CANVAS_FILL_MODES[1], "red"),
+            CanvasFill::Transparent
+        );
+    }
+
+    #[test]
+    fn pixels_outside_the_image_are_filled() {
+        // A single row of three pixels, a b c
+        let image_buf = RgbaImage::from_fn(3, 1, |x, _| Rgba([x as u8, 0, 0, 255]));
+        let row = |canvas_fill: CanvasFill| {
+            (-3..6)
+                .map(|x| canvas_fill.pixel(&image_buf, x, 0).0[0])
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(row(CanvasFill::Edge), [0, 0, 0, 0, 1, 2, 2, 2, 2]);
+        assert_eq!(row(CanvasFill::Mirror), [2, 1, 0, 0, 1, 2, 2, 1, 0]);
+        assert_eq!(
+            CanvasFill::Color(Rgba([9, 8, 7, 6])).pixel(&image_buf, 1, -1),
+            Rgba([9, 8, 7, 6])
+        );
+        assert_eq!(
+            CanvasFill::Transparent.pixel(&image_buf, 3, 0),
+            Rgba([0; 4])
+        );
+        assert_eq!(
+            CanvasFill::Edge.pixel(&RgbaImage::new(0, 0), 0, 0),
+            Rgba([0; 4])
+        );
+    }
+}
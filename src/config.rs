@@ -0,0 +1,313 @@
+use crate::args::Args;
+use crate::canvas_fill::{format_color, parse_color};
+use crate::guides::Guides;
+use crate::output_template::OutputTemplate;
+use crate::preset::{Preset, default_presets};
+use crate::viewport_background::ViewportBackground;
+use gpui::Keystroke;
+use image::Rgba;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Settings read from `config.toml` in the basicrop config directory
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub output: OutputConfig,
+    pub window: WindowConfig,
+    pub overlay: OverlayConfig,
+    pub keys: KeysConfig,
+    pub presets: Vec<Preset>,
+}
+
+/// How cropped images are named and encoded
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Where the cropped image is saved when no output-image is given
+    pub template: OutputTemplate,
+    /// Destination for images pasted from the clipboard, relative to the
+    /// working directory
+    pub clipboard_path: PathBuf,
+    /// JPEG quality from 1 to 100
+    pub jpeg_quality: u8,
+    /// Saves the settings of every crop next to the source image, see
+    /// [`Sidecar`](crate::sidecar::Sidecar)
+    pub sidecar: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub width: f32,
+    pub height: f32,
+    pub min_width: f32,
+    pub min_height: f32,
+    pub theme: ThemeConfig,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeConfig {
+    /// Follows the light or dark appearance of the system
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+/// Colors of the crop overlay, the alpha channel sets their opacity
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OverlayConfig {
+    pub selection: OverlayColor,
+    pub selection_border: OverlayColor,
+    /// Darkens the image outside the crop
+    pub occlusion: OverlayColor,
+    pub guides: Guides,
+    /// Painted behind the image, where it's transparent
+    pub background: ViewportBackground,
+}
+
+/// A `#RRGGBB` or `#RRGGBBAA` color
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct OverlayColor(pub Rgba<u8>);
+
+/// Keystrokes of the window's actions, as in `secondary-shift-r` where
+/// secondary is Cmd on macOS and Ctrl elsewhere
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeysConfig {
+    pub crop: String,
+    pub cancel: String,
+    pub open: String,
+    pub paste: String,
+    pub copy: String,
+    pub rotate_clockwise: String,
+    pub rotate_counter_clockwise: String,
+    pub flip_horizontal: String,
+    pub flip_vertical: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            output: OutputConfig::default(),
+            window: WindowConfig::default(),
+            overlay: OverlayConfig::default(),
+            keys: KeysConfig::default(),
+            presets: default_presets(),
+        }
+    }
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            template: OutputTemplate::default(),
+            clipboard_path: PathBuf::from("clipboard.cropped.png"),
+            jpeg_quality: 75,
+            sidecar: false,
+        }
+    }
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig {
+            width: 500.,
+            height: 500.,
+            min_width: 750.,
+            min_height: 500.,
+            theme: ThemeConfig::default(),
+        }
+    }
+}
+
+impl Default for OverlayConfig {
+    fn default() -> Self {
+        OverlayConfig {
+            selection: OverlayColor(Rgba([0x70, 0x9e, 0xbe, 0x7f])),
+            selection_border: OverlayColor(Rgba([0x70, 0x9e, 0xbe, 0xaf])),
+            occlusion: OverlayColor(Rgba([0x00, 0x00, 0x00, 0xc8])),
+            guides: Guides::default(),
+            background: ViewportBackground::default(),
+        }
+    }
+}
+
+impl Default for KeysConfig {
+    fn default() -> Self {
+        KeysConfig {
+            crop: "enter".to_string(),
+            cancel: "escape".to_string(),
+            open: "secondary-o".to_string(),
+            paste: "secondary-v".to_string(),
+            copy: "secondary-c".to_string(),
+            rotate_clockwise: "secondary-r".to_string(),
+            rotate_counter_clockwise: "secondary-shift-r".to_string(),
+            flip_horizontal: "secondary-shift-h".to_string(),
+            flip_vertical: "secondary-shift-v".to_string(),
+        }
+    }
+}
+
+impl TryFrom<String> for OverlayColor {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        parse_color(&value)
+            .map(OverlayColor)
+            .ok_or_else(|| format!("invalid color {value}, expected #RRGGBB or #RRGGBBAA"))
+    }
+}
+
+impl From<OverlayColor> for String {
+    fn from(color: OverlayColor) -> Self {
+        format_color(color.0)
+    }
+}
+
+impl OverlayColor {
+    pub fn to_rgba(self) -> gpui::Rgba {
+        gpui::rgba(u32::from_be_bytes(self.0.0))
+    }
+}
+
+impl KeysConfig {
+    /// The keystrokes by their name in the config file
+    fn keystrokes(&self) -> [(&'static str, &str); 9] {
+        [
+            ("crop", &self.crop),
+            ("cancel", &self.cancel),
+            ("open", &self.open),
+            ("paste", &self.paste),
+            ("copy", &self.copy),
+            ("rotate_clockwise", &self.rotate_clockwise),
+            ("rotate_counter_clockwise", &self.rotate_counter_clockwise),
+            ("flip_horizontal", &self.flip_horizontal),
+            ("flip_vertical", &self.flip_vertical),
+        ]
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("basicrop").join("config.toml"))
+}
+
+impl Config {
+    /// Loads the config file, falling back to the defaults when it's
+    /// missing or invalid
+    pub fn load() -> Config {
+        let Some(path) = config_path() else {
+            return Config::default();
+        };
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return Config::default();
+        };
+
+        match Config::parse(&contents) {
+            Ok(config) => config,
+            Err(error) => {
+                eprintln!(
+                    "warn: ignoring invalid config file {}: {}",
+                    path.to_string_lossy(),
+                    error
+                );
+                Config::default()
+            }
+        }
+    }
+
+    /// Reads the contents of a config file, with the reason it's invalid
+    fn parse(contents: &str) -> Result<Config, String> {
+        toml::from_str::<Config>(contents)
+            .map_err(|error| error.message().to_string())
+            .and_then(|config| config.validate().map(|_| config))
+    }
+
+    /// Checks the values serde can't, so a bad key binding doesn't panic
+    /// when the window opens
+    fn validate(&self) -> Result<(), String> {
+        if !(1..=100).contains(&self.output.jpeg_quality) {
+            return Err(format!(
+                "invalid jpeg_quality {}, expected 1 to 100",
+                self.output.jpeg_quality
+            ));
+        }
+
+        for (name, keystrokes) in self.keys.keystrokes() {
+            let is_valid = !keystrokes.trim().is_empty()
+                && keystrokes
+                    .split_whitespace()
+                    .all(|keystroke| Keystroke::parse(keystroke).is_ok());
+            if !is_valid {
+                return Err(format!("invalid key binding \"{keystrokes}\" for {name}"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies the options given on the command line over the config file
+    pub fn override_with(&mut self, args: &Args) {
+        if let Some(template) = &args.output_template {
+            self.output.template = template.clone();
+        }
+        if args.sidecar {
+            self.output.sidecar = true;
+        }
+        if let Some(guides) = args.guides {
+            self.overlay.guides = guides;
+        }
+        if let Some(jpeg_quality) = args.jpeg_quality {
+            self.output.jpeg_quality = jpeg_quality;
+        }
+    }
+
+    /// The effective configuration in the format of the config file
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explicit_options_win_over_the_config_file() {
+        let mut config = Config::parse(
+            "[output]\njpeg_quality = 70\nsidecar = false\n\n[overlay]\nguides = \"center\"\n",
+        )
+        .unwrap();
+        let args = Args {
+            jpeg_quality: Some(95),
+            guides: Some(Guides::Thirds),
+            sidecar: true,
+            ..Args::default()
+        };
+        config.override_with(&args);
+        assert_eq!(config.output.jpeg_quality, 95);
+        assert_eq!(config.overlay.guides, Guides::Thirds);
+        assert!(config.output.sidecar);
+
+        // Options left out keep the config file's values
+        let mut config = Config::parse("[output]\njpeg_quality = 70\n").unwrap();
+        config.override_with(&Args::default());
+        assert_eq!(config.output.jpeg_quality, 70);
+    }
+
+    #[test]
+    fn invalid_config_files_are_explained() {
+        let error = Config::parse("[output]\nquality = 70\n").unwrap_err();
+        assert!(error.contains("unknown field `quality`"), "{error}");
+        let error = Config::parse("[output]\njpeg_quality = 0\n").unwrap_err();
+        assert_eq!(error, "invalid jpeg_quality 0, expected 1 to 100");
+        let error = Config::parse("[keys]\ncrop = \"\"\n").unwrap_err();
+        assert_eq!(error, "invalid key binding \"\" for crop");
+        let error = Config::parse("[overlay]\nselection_border = \"red\"\n").unwrap_err();
+        assert!(error.contains("invalid color red"), "{error}");
+    }
+}
@@ -0,0 +1,362 @@
+use crate::anchor::Anchor;
+use crate::image_crop::{InitializedImageCrop, snap_to_pixel};
+
+/// Labels of the coordinate modes in the window, in the order of the
+/// variants of [`CoordinateMode`]
+pub const COORDINATE_MODES: [&str; 3] = ["Position", "Edges", "Center"];
+
+/// What the four crop fields show
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CoordinateMode {
+    /// X, Y, Width and Height
+    #[default]
+    Position,
+    /// Left, Top, Right and Bottom
+    Edges,
+    /// Center X, Center Y, Width and Height
+    Center,
+}
+
+impl CoordinateMode {
+    pub fn from_label(label: &str) -> CoordinateMode {
+        match COORDINATE_MODES.iter().position(|mode| *mode == label) {
+            Some(1) => CoordinateMode::Edges,
+            Some(2) => CoordinateMode::Center,
+            _ => CoordinateMode::Position,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CoordinateMode::Position => COORDINATE_MODES[0],
+            CoordinateMode::Edges => COORDINATE_MODES[1],
+            CoordinateMode::Center => COORDINATE_MODES[2],
+        }
+    }
+
+    /// Labels of the four fields, the first and third measured along the
+    /// image's width
+    pub fn field_labels(&self) -> [&'static str; 4] {
+        match self {
+            CoordinateMode::Position => ["X:", "Y:", "Width:", "Height:"],
+            CoordinateMode::Edges => ["Left:", "Top:", "Right:", "Bottom:"],
+            CoordinateMode::Center => ["Center X:", "Center Y:", "Width:", "Height:"],
+        }
+    }
+
+    /// Values of the four fields, the center of an odd size is the pixel
+    /// boundary left of or above the middle
+    pub fn field_values(&self, crop: &InitializedImageCrop) -> [i32; 4] {
+        let (x, y, width, height) = (crop.crop_x, crop.crop_y, crop.width, crop.height);
+        match self {
+            CoordinateMode::Position => [x, y, width, height],
+            CoordinateMode::Edges => [x, y, x + width, y + height],
+            CoordinateMode::Center => [x + width / 2, y + height / 2, width, height],
+        }
+    }
+
+    /// The lowest and highest values of a field that keep the crop valid,
+    /// see [`crate::image_crop::FinalizedImageCrop::is_valid`]
+    pub fn field_range(
+        &self,
+        field: usize,
+        crop: &InitializedImageCrop,
+        anchor: Anchor,
+        image_width: u32,
+        image_height: u32,
+    ) -> (i32, i32) {
+        let (anchor_x, anchor_y) = anchor.fractions();
+        let (position, size, extent, anchor) = match field % 2 {
+            0 => (crop.crop_x, crop.width, image_width, anchor_x),
+            _ => (crop.crop_y, crop.height, image_height, anchor_y),
+        };
+        let (position, size, extent) = (position as f32, size as f32, extent as f32);
+        let half_size = (size / 2.).floor();
+
+        let (min, max) = match (self, field) {
+            (CoordinateMode::Position, 0 | 1) => (
+                (-extent).max(1. - size),
+                (extent - 1.).min(2. * extent - size),
+            ),
+            (CoordinateMode::Center, 0 | 1) => (
+                (-extent).max(1. - size) + half_size,
+                (extent - 1.).min(2. * extent - size) + half_size,
+            ),
+            (CoordinateMode::Edges, 0 | 1) => (-extent, (position + size - 1.).min(extent - 1.)),
+            (CoordinateMode::Edges, _) => ((position + 1.).max(1.), 2. * extent),
+            // The anchor stays in place, snapped to a pixel boundary as in
+            // `apply`, so the size is limited by how far the edges on either
+            // side of it can move
+            (_, _) => {
+                let (mut min, mut max) = (1_f32, f32::MAX);
+                if anchor > 0. {
+                    min = min.max((size - (extent - position - 0.5) / anchor).floor() + 1.);
+                    max = max.min((size + (extent + position + 0.5) / anchor).floor());
+                }
+                if anchor < 1. {
+                    let end_offset = position + size * anchor + 0.5;
+                    min = min.max((1. - end_offset) / (1. - anchor));
+                    max = max.min(((2. * extent + 1. - end_offset) / (1. - anchor)).ceil() - 1.);
+                }
+                (min, max)
+            }
+        };
+
+        (min.ceil() as i32, max.floor() as i32)
+    }
+
+    /// The nearest value of a field in [`Self::field_range`], none when the
+    /// value is in range already
+    pub fn clamp_field(
+        &self,
+        field: usize,
+        value: i32,
+        crop: &InitializedImageCrop,
+        anchor: Anchor,
+        image_width: u32,
+        image_height: u32,
+    ) -> Option<i32> {
+        let (min, max) = self.field_range(field, crop, anchor, image_width, image_height);
+        (!(min..=max).contains(&value)).then(|| value.min(max).max(min))
+    }
+
+    /// The crop with one of its fields changed, none when that would make
+    /// it negative. A changed width or height keeps the anchor in place,
+    /// snapped to a pixel boundary, and a changed edge keeps the opposite one
+    pub fn apply(
+        &self,
+        field: usize,
+        value: i32,
+        crop: &InitializedImageCrop,
+        anchor: Anchor,
+    ) -> Option<InitializedImageCrop> {
+        let (mut x, mut y) = (crop.crop_x, crop.crop_y);
+        let (mut width, mut height) = (crop.width, crop.height);
+        let (anchor_x, anchor_y) = anchor.fractions();
+        let anchor_offset =
+            |size_change: i32, anchor: f32| snap_to_pixel(size_change as f32 * anchor);
+
+        match (self, field) {
+            (CoordinateMode::Position, 0) => x = value,
+            (CoordinateMode::Position, 1) => y = value,
+            (CoordinateMode::Edges, 0) => (x, width) = (value, x + width - value),
+            (CoordinateMode::Edges, 1) => (y, height) = (value, y + height - value),
+            (CoordinateMode::Edges, 2) => width = value - x,
+            (CoordinateMode::Edges, _) => height = value - y,
+            (CoordinateMode::Center, 0) => x = value - width / 2,
+            (CoordinateMode::Center, 1) => y = value - height / 2,
+            (_, 2) => (x, width) = (x + anchor_offset(width - value, anchor_x), value),
+            (_, _) => (y, height) = (y + anchor_offset(height - value, anchor_y), value),
+        }
+
+        (width >= 0 && height >= 0).then_some(InitializedImageCrop {
+            crop_x: x,
+            crop_y: y,
+            width,
+            height,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anchor::ANCHORS;
+    use crate::image_crop::FinalizedImageCrop;
+    use proptest::prelude::*;
+
+    const MODES: [CoordinateMode; 3] = [
+        CoordinateMode::Position,
+        CoordinateMode::Edges,
+        CoordinateMode::Center,
+    ];
+
+    fn is_valid(crop: &InitializedImageCrop, image_width: u32, image_height: u32) -> bool {
+        let (Ok(width), Ok(height)) = (u32::try_from(crop.width), u32::try_from(crop.height))
+        else {
+            return false;
+        };
+        FinalizedImageCrop {
+            crop_x: crop.crop_x,
+            crop_y: crop.crop_y,
+            width,
+            height,
+        }
+        .is_valid(image_width, image_height)
+    }
+
+    /// An image size and a valid crop of it
+    fn image_and_crop() -> impl Strategy<Value = (u32, u32, InitializedImageCrop)> {
+        (1u32..300, 1u32..300)
+            .prop_flat_map(|(image_width, image_height)| {
+                let (w, h) = (image_width as i32, image_height as i32);
+                (
+                    Just(image_width),
+                    Just(image_height),
+                    -w..w,
+                    -h..h,
+                    1..=3 * w,
+                    1..=3 * h,
+                )
+            })
+            .prop_map(
+                |(image_width, image_height, crop_x, crop_y, width, height)| {
+                    let crop = InitializedImageCrop {
+                        crop_x,
+                        crop_y,
+                        width,
+                        height,
+                    };
+                    (image_width, image_height, crop)
+                },
+            )
+            .prop_filter("crop must be valid", |(image_width, image_height, crop)| {
+                is_valid(crop, *image_width, *image_height)
+            })
+    }
+
+    #[test]
+    fn labels_map_back_to_their_modes() {
+        for mode in MODES {
+            assert_eq!(CoordinateMode::from_label(mode.label()), mode);
+        }
+        assert_eq!(
+            CoordinateMode::from_label("unknown"),
+            CoordinateMode::Position
+        );
+    }
+
+    #[test]
+    fn center_of_an_odd_size_is_left_of_the_middle() {
+        let crop = InitializedImageCrop {
+            crop_x: 10,
+            crop_y: 20,
+            width: 5,
+            height: 4,
+        };
+        assert_eq!(CoordinateMode::Center.field_values(&crop), [12, 22, 5, 4]);
+        assert_eq!(CoordinateMode::Edges.field_values(&crop), [10, 20, 15, 24]);
+    }
+
+    #[test]
+    fn values_past_the_bounds_are_clamped() {
+        let crop = InitializedImageCrop {
+            crop_x: 10,
+            crop_y: 10,
+            width: 50,
+            height: 50,
+        };
+        let position = CoordinateMode::Position;
+        assert_eq!(
+            position.clamp_field(0, 20, &crop, Anchor::TopLeft, 100, 100),
+            None
+        );
+        assert_eq!(
+            position.clamp_field(0, 1000, &crop, Anchor::TopLeft, 100, 100),
+            Some(99)
+        );
+        assert_eq!(
+            position.clamp_field(1, -1000, &crop, Anchor::TopLeft, 100, 100),
+            Some(-49)
+        );
+        assert_eq!(
+            CoordinateMode::Edges.clamp_field(2, 5, &crop, Anchor::TopLeft, 100, 100),
+            Some(11)
+        );
+        assert_eq!(
+            CoordinateMode::Position.clamp_field(2, 0, &crop, Anchor::TopLeft, 100, 100),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn edges_keep_the_opposite_edge() {
+        let crop = InitializedImageCrop {
+            crop_x: 10,
+            crop_y: 20,
+            width: 30,
+            height: 40,
+        };
+        let moved = CoordinateMode::Edges.apply(0, 5, &crop, Anchor::Center);
+        assert_eq!(
+            moved,
+            Some(InitializedImageCrop {
+                crop_x: 5,
+                width: 35,
+                ..crop.clone()
+            })
+        );
+        assert_eq!(
+            CoordinateMode::Edges.apply(3, 19, &crop, Anchor::Center),
+            None
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn current_values_leave_the_crop_unchanged(
+            (_, _, crop) in image_and_crop(),
+        ) {
+            for mode in MODES {
+                let values = mode.field_values(&crop);
+                for anchor in ANCHORS {
+                    for (field, value) in values.into_iter().enumerate() {
+                        prop_assert_eq!(mode.apply(field, value, &crop, anchor), Some(crop.clone()));
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn resizing_keeps_the_anchor_in_place(
+            (_, _, crop) in image_and_crop(),
+            half_change in -100i32..100,
+        ) {
+            for mode in [CoordinateMode::Position, CoordinateMode::Center] {
+                for anchor in ANCHORS {
+                    let (anchor_x, anchor_y) = anchor.fractions();
+                    let anchor_point = |crop: &InitializedImageCrop| {
+                        (
+                            crop.crop_x as f32 + crop.width as f32 * anchor_x,
+                            crop.crop_y as f32 + crop.height as f32 * anchor_y,
+                        )
+                    };
+                    let width = crop.width + 2 * half_change;
+                    let height = crop.height + 2 * half_change;
+                    let Some(resized) = mode
+                        .apply(2, width, &crop, anchor)
+                        .and_then(|resized| mode.apply(3, height, &resized, anchor))
+                    else {
+                        prop_assert!(width < 0 || height < 0);
+                        continue;
+                    };
+                    prop_assert_eq!((resized.width, resized.height), (width, height));
+                    prop_assert_eq!(anchor_point(&resized), anchor_point(&crop));
+                }
+            }
+        }
+
+        #[test]
+        fn field_range_is_exactly_the_valid_values(
+            (image_width, image_height, crop) in image_and_crop(),
+        ) {
+            for mode in MODES {
+                for anchor in ANCHORS {
+                    for field in 0..4 {
+                        let (min, max) =
+                            mode.field_range(field, &crop, anchor, image_width, image_height);
+                        let is_valid_value = |value: i32| {
+                            mode.apply(field, value, &crop, anchor)
+                                .is_some_and(|changed| is_valid(&changed, image_width, image_height))
+                        };
+                        prop_assert!(min <= max, "{mode:?} {anchor:?} field {field}");
+                        prop_assert!(is_valid_value(min), "{mode:?} {anchor:?} field {field} min {min}");
+                        prop_assert!(is_valid_value(max), "{mode:?} {anchor:?} field {field} max {max}");
+                        prop_assert!(!is_valid_value(min - 1), "{mode:?} {anchor:?} field {field} below {min}");
+                        prop_assert!(!is_valid_value(max + 1), "{mode:?} {anchor:?} field {field} above {max}");
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,33 +1,108 @@
-use gpui::{Context, Entity, Subscription, Window, div, prelude::*, px};
+use gpui::{
+    App, Context, Entity, EntityId, Focusable, Modifiers, Pixels, ScrollWheelEvent, SharedString,
+    Subscription, Window, div, prelude::*, px, rgb,
+};
 use gpui_component::Sizable;
+use gpui_component::button::{Button, ButtonVariants};
 use gpui_component::input::{InputEvent, InputState, NumberInput, NumberInputEvent, StepAction};
 
+use crate::coordinate_mode::CoordinateMode;
+use crate::crop_model::{CropChanged, CropModel, CropSource};
+use crate::field_units::{FieldScale, Unit};
 use crate::image_crop::{ImageCrop, InitializedImageCrop};
 
+/// Distance scrolled for one step with the mouse wheel
+const SCROLL_STEP: Pixels = px(20.);
+
+/// Distance dragged over a label for one step
+const SCRUB_STEP: Pixels = px(2.);
+
+/// Applies a value to the crop, or returns the nearest valid value without
+/// applying it when it's out of range
+type OnChange = Box<dyn FnMut(i32, InitializedImageCrop, &mut Context<CounterView>) -> Option<i32>>;
+
+/// One of the four crop fields, it shows the crop whenever it changes and
+/// writes what's typed back to it
 pub struct CounterView {
     pub counter_input: Entity<InputState>,
-    counter_value: i32,
+    /// Index of the field, what it shows depends on the coordinate mode
+    field: usize,
+    image_crop: Entity<CropModel>,
+    coordinate_mode: Entity<CoordinateMode>,
+    /// The text the field was last set to and the pixel value it stands for,
+    /// exact even when the unit rounds it
+    shown_value: (String, i32),
+    unit: Unit,
+    /// Whether the typed value is out of range
+    is_invalid: bool,
+    /// The whole image, the reference for percentages
+    image_bounds: Entity<ImageCrop>,
+    dpi: f32,
+    /// Scrolling left over from the last wheel step
+    scroll_remainder: Pixels,
+    /// Where dragging the label started and the value at that point
+    scrub_start: Option<(Pixels, i32)>,
+    on_change: Option<OnChange>,
     _subscriptions: Vec<Subscription>,
 }
 
+/// Dragging the label of a field to scrub its value
+struct ScrubDrag(EntityId);
+
+/// Steps are ten times larger with Shift and a hundred times with Ctrl
+fn step_multiplier(modifiers: Modifiers) -> i32 {
+    if modifiers.control {
+        100
+    } else if modifiers.shift {
+        10
+    } else {
+        1
+    }
+}
+
+/// The value a number of steps of a unit away, each step at least a pixel
+fn stepped_value(value: i32, steps: i32, pixels_per_unit: f32) -> i32 {
+    value + (steps as f32 * pixels_per_unit.max(1.)).round() as i32
+}
+
 impl CounterView {
-    pub fn new(window: &mut Window, cx: &mut Context<Self>, default_value: i32) -> Self {
-        let counter_input = cx.new(
-            |cx| {
-                InputState::new(window, cx)
-                    .placeholder("Count")
-                    .default_value(default_value.to_string())
-            }, // .pattern(Regex::new(r"^-?\d+$").unwrap()) // Allow negative integers
-        );
+    pub fn new(
+        window: &mut Window,
+        cx: &mut Context<Self>,
+        field: usize,
+        image_crop: Entity<CropModel>,
+        coordinate_mode: Entity<CoordinateMode>,
+        image_bounds: Entity<ImageCrop>,
+        dpi: f32,
+    ) -> Self {
+        let counter_input = cx.new(|cx| {
+            InputState::new(window, cx)
+                .placeholder("Count")
+                .default_value("0")
+        });
 
         let _subscriptions = vec![
             cx.subscribe_in(&counter_input, window, Self::on_number_event),
             cx.subscribe_in(&counter_input, window, Self::on_input_event),
+            cx.subscribe_in(&image_crop, window, Self::on_crop_changed),
+            cx.observe_in(&coordinate_mode, window, |this, _, window, cx| {
+                this.show_crop_value(window, cx)
+            }),
         ];
 
         Self {
             counter_input,
-            counter_value: default_value,
+            field,
+            image_crop,
+            coordinate_mode,
+            shown_value: ("0".to_string(), 0),
+            unit: Unit::Pixels,
+            is_invalid: false,
+            image_bounds,
+            dpi,
+            scroll_remainder: px(0.),
+            scrub_start: None,
+            on_change: None,
             _subscriptions,
         }
     }
@@ -36,83 +111,334 @@ impl CounterView {
         self.counter_input.clone()
     }
 
-    /// Subscribes to the number input and detaches the subscription. Should not be
-    /// used if inputs are created on-demand
-    pub fn subscribe<T: 'static>(
+    pub fn unit(&self) -> Unit {
+        self.unit
+    }
+
+    pub fn is_invalid(&self) -> bool {
+        self.is_invalid
+    }
+
+    /// Sets the handler writing the field's value to the crop
+    pub fn on_change(
         &mut self,
-        window: &mut Window,
-        cx: &mut Context<T>,
-        image_crop: Entity<ImageCrop>,
-        mut on_event: impl FnMut(u32, InitializedImageCrop, &mut Context<T>) + 'static,
+        on_change: impl FnMut(i32, InitializedImageCrop, &mut Context<Self>) -> Option<i32> + 'static,
     ) {
-        cx.subscribe_in(
-            &self.counter_input,
+        self.on_change = Some(Box::new(on_change));
+    }
+
+    /// Shows a pixel value in the field's unit
+    fn set_pixels(&mut self, value: i32, window: &mut Window, cx: &mut App) {
+        let text = self.scale(cx).format(value as f32, self.unit);
+        self.shown_value = (text.clone(), value);
+        self.is_invalid = false;
+        self.counter_input.update(cx, |input, cx| {
+            input.set_value(text, window, cx);
+        });
+    }
+
+    /// Switches to the next unit, showing the same value in it
+    pub fn cycle_unit(&mut self, window: &mut Window, cx: &mut App) {
+        self.unit = self.unit.next();
+        let value = self.crop_value(cx).unwrap_or(self.shown_value.1);
+        self.set_pixels(value, window, cx);
+    }
+
+    /// Shows the field's value in the crop, unless it's shown already
+    fn show_crop_value(&mut self, window: &mut Window, cx: &mut App) {
+        if let Some(value) = self.crop_value(cx)
+            && (value != self.shown_value.1 || self.is_invalid)
+        {
+            self.set_pixels(value, window, cx);
+        }
+    }
+
+    /// The field's value in the crop
+    fn crop_value(&self, cx: &App) -> Option<i32> {
+        let crop = self.image_crop.read(cx).crop().to_initialized()?;
+        Some(self.coordinate_mode.read(cx).field_values(&crop)[self.field])
+    }
+
+    fn scale(&self, cx: &App) -> FieldScale {
+        let (image_width, image_height) = match self.image_bounds.read(cx).to_initialized() {
+            Some(bounds) => (bounds.width as f32, bounds.height as f32),
+            None => (0., 0.),
+        };
+        FieldScale {
+            image_width,
+            image_height,
+            // The first and third fields are measured along the width
+            is_horizontal: self.field.is_multiple_of(2),
+            dpi: self.dpi,
+        }
+    }
+
+    /// The pixel value of what was typed. Plain numbers apply while typing,
+    /// expressions and relative values only once they're `finished`
+    fn resolve(&self, text: &str, finished: bool, cx: &App) -> Option<i32> {
+        if text == self.shown_value.0 {
+            return Some(self.shown_value.1);
+        }
+
+        let is_plain_number = !text.trim().starts_with('+') && text.trim().parse::<f32>().is_ok();
+        if !finished && !is_plain_number {
+            return None;
+        }
+
+        let current_value = self.crop_value(cx).unwrap_or(self.shown_value.1);
+        self.scale(cx)
+            .evaluate(text, self.unit, current_value as f32)
+            .map(|value| value.round() as i32)
+    }
+
+    /// Writes a value to the crop. Values out of range are shown as invalid
+    /// while typing and clamped once `finished`, which also replaces an
+    /// expression with the value it resolved to
+    fn apply(&mut self, value: i32, finished: bool, window: &mut Window, cx: &mut Context<Self>) {
+        let (Some(on_change), Some(crop)) = (
+            self.on_change.as_mut(),
+            self.image_crop.read(cx).crop().to_initialized(),
+        ) else {
+            return;
+        };
+
+        let clamped_value = on_change(value, crop.clone(), cx);
+        if finished && let Some(clamped_value) = clamped_value {
+            on_change(clamped_value, crop, cx);
+        }
+        self.is_invalid = clamped_value.is_some() && !finished;
+
+        let value = clamped_value.unwrap_or(value);
+        let text = self.counter_input.read(cx).value();
+        if finished && (value, text.as_ref()) != (self.shown_value.1, self.shown_value.0.as_str()) {
+            self.set_pixels(value, window, cx);
+        }
+        cx.notify();
+    }
+
+    /// Moves the crop's value by a number of steps of the field's unit
+    fn step(&mut self, steps: i32, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(value) = self.crop_value(cx) else {
+            return;
+        };
+        let pixels_per_unit = self.scale(cx).pixels_per_unit(self.unit);
+        self.apply(
+            stepped_value(value, steps, pixels_per_unit),
+            true,
             window,
-            move |_, input, evt: &InputEvent, _, cx| {
-                if let InputEvent::Change = evt {
-                    let value = input.read(cx).value().parse::<u32>();
-                    if let (Ok(value), Some(initialized_image_crop)) =
-                        (value, image_crop.read(cx).to_initialized())
-                    {
-                        on_event(value, initialized_image_crop, cx);
-                    }
-                }
-            },
-        )
-        .detach();
+            cx,
+        );
+    }
+
+    /// Shows the crop after it was changed by anything but this field, which
+    /// keeps showing what was typed in it
+    fn on_crop_changed(
+        &mut self,
+        _: &Entity<CropModel>,
+        event: &CropChanged,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if event.source != CropSource::Field(self.field) {
+            self.show_crop_value(window, cx);
+        }
     }
 
     fn on_input_event(
         &mut self,
         state: &Entity<InputState>,
         event: &InputEvent,
-        _: &mut Window,
+        window: &mut Window,
         cx: &mut Context<Self>,
     ) {
-        if let InputEvent::Change = event {
-            let text = state.read(cx).value();
-            if let Ok(value) = text.parse::<i32>() {
-                self.counter_value = value;
+        let text = state.read(cx).value();
+        let finished = match event {
+            // Values that were set rather than typed are already in the crop
+            InputEvent::Change if text == self.shown_value.0 => return,
+            InputEvent::Change => false,
+            InputEvent::PressEnter { .. } | InputEvent::Blur => true,
+            _ => return,
+        };
+
+        match self.resolve(&text, finished, cx) {
+            Some(value) => self.apply(value, finished, window, cx),
+            // Invalid expressions are replaced by the crop's value
+            None if finished => {
+                eprintln!("warn: invalid crop value {text}");
+                if let Some(value) = self.crop_value(cx) {
+                    self.set_pixels(value, window, cx);
+                }
             }
+            None => {}
         }
     }
 
     fn on_number_event(
         &mut self,
-        state: &Entity<InputState>,
+        _: &Entity<InputState>,
         event: &NumberInputEvent,
         window: &mut Window,
         cx: &mut Context<Self>,
     ) {
+        let steps = step_multiplier(window.modifiers());
         match event {
-            NumberInputEvent::Step(StepAction::Increment) => {
-                self.counter_value += 1;
-                state.update(cx, |input, cx| {
-                    input.set_value(self.counter_value.to_string(), window, cx);
-                });
-            }
-            NumberInputEvent::Step(StepAction::Decrement) => {
-                self.counter_value -= 1;
-                state.update(cx, |input, cx| {
-                    input.set_value(self.counter_value.to_string(), window, cx);
-                });
-            }
+            NumberInputEvent::Step(StepAction::Increment) => self.step(steps, window, cx),
+            NumberInputEvent::Step(StepAction::Decrement) => self.step(-steps, window, cx),
         }
     }
+
+    /// Steps with the mouse wheel while the field is focused, scrolling up
+    /// increases the value
+    fn on_scroll_wheel(
+        &mut self,
+        event: &ScrollWheelEvent,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if !self
+            .counter_input
+            .read(cx)
+            .focus_handle(cx)
+            .is_focused(window)
+        {
+            return;
+        }
+
+        // Shift turns the wheel sideways on some platforms
+        let delta = event.delta.pixel_delta(SCROLL_STEP);
+        self.scroll_remainder += if delta.y != px(0.) { delta.y } else { delta.x };
+        let steps = (self.scroll_remainder / SCROLL_STEP).trunc();
+        if steps != 0. {
+            self.scroll_remainder -= SCROLL_STEP * steps;
+            self.step(steps as i32 * step_multiplier(event.modifiers), window, cx);
+        }
+    }
+
+    /// Sets the value from how far the label was dragged, in steps of the
+    /// field's unit
+    fn scrub(
+        &mut self,
+        x: Pixels,
+        modifiers: Modifiers,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some((start_x, start_value)) = self.scrub_start else {
+            return;
+        };
+        let steps = ((x - start_x) / SCRUB_STEP).trunc() as i32 * step_multiplier(modifiers);
+        let pixels_per_unit = self.scale(cx).pixels_per_unit(self.unit);
+        self.apply(
+            stepped_value(start_value, steps, pixels_per_unit),
+            true,
+            window,
+            cx,
+        );
+    }
 }
 
-pub fn number_field(label: &str, state: Entity<InputState>) -> impl IntoElement {
+/// A crop field with its label, which scrubs the value when dragged, and a
+/// button cycling through its units
+pub fn number_field(label: &str, view: &Entity<CounterView>, cx: &App) -> impl IntoElement {
+    let state = view.read(cx).get_state();
+    let unit = view.read(cx).unit();
+    let is_invalid = view.read(cx).is_invalid();
     div()
         .flex()
         .flex_row()
         .gap_2()
         .items_center()
-        .child(label.to_string())
+        .child(
+            div()
+                .id(SharedString::from(format!("{label}-label")))
+                .cursor_ew_resize()
+                .child(label.to_string())
+                .on_drag(ScrubDrag(view.entity_id()), {
+                    let view = view.clone();
+                    move |_, offset, _, cx| {
+                        view.update(cx, |view, cx| {
+                            view.scrub_start = view.crop_value(cx).map(|value| (offset.x, value));
+                        });
+                        cx.new(|_| gpui::Empty)
+                    }
+                })
+                .on_drag_move::<ScrubDrag>({
+                    let view = view.clone();
+                    move |event, window, cx| {
+                        if event.drag(cx).0 == view.entity_id() {
+                            let x = event.event.position.x - event.bounds.origin.x;
+                            view.update(cx, |view, cx| {
+                                view.scrub(x, event.event.modifiers, window, cx)
+                            });
+                        }
+                    }
+                }),
+        )
         .child(
             div()
                 .flex()
                 .flex_row()
                 .w(px(100.))
+                .when(is_invalid, |this| {
+                    this.border_1().border_color(rgb(0xd03030)).rounded_md()
+                })
+                .on_scroll_wheel({
+                    let view = view.clone();
+                    move |event, window, cx| {
+                        view.update(cx, |view, cx| view.on_scroll_wheel(event, window, cx));
+                    }
+                })
                 .child(NumberInput::new(&state).small()),
         )
+        .child(
+            Button::new(SharedString::from(format!("{label}-unit")))
+                .label(unit.label())
+                .ghost()
+                .xsmall()
+                .on_click({
+                    let view = view.clone();
+                    move |_, window, cx| {
+                        view.update(cx, |view, cx| view.cycle_unit(window, cx));
+                    }
+                }),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modifiers_make_steps_larger() {
+        assert_eq!(step_multiplier(Modifiers::default()), 1);
+        let shift = Modifiers {
+            shift: true,
+            ..Modifiers::default()
+        };
+        assert_eq!(step_multiplier(shift), 10);
+        let control = Modifiers {
+            control: true,
+            ..Modifiers::default()
+        };
+        assert_eq!(step_multiplier(control), 100);
+        assert_eq!(
+            step_multiplier(Modifiers {
+                shift: true,
+                ..control
+            }),
+            100
+        );
+    }
+
+    #[test]
+    fn steps_are_in_the_field_unit() {
+        assert_eq!(stepped_value(100, 1, 1.), 101);
+        assert_eq!(stepped_value(100, -10, 1.), 90);
+        // 1% of a 2000 pixel wide image
+        assert_eq!(stepped_value(100, 1, 20.), 120);
+        // 1mm at 300 DPI rounds to 12 pixels
+        assert_eq!(stepped_value(100, 1, 300. / 25.4), 112);
+        // Units smaller than a pixel still move by one
+        assert_eq!(stepped_value(100, -100, 0.5), 0);
+    }
 }
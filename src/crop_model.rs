@@ -0,0 +1,226 @@
+use crate::image_crop::ImageCrop;
+use gpui::{Context, EventEmitter, Pixels, Point};
+
+/// What changed the crop, so views can skip changes they made themselves
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CropSource {
+    /// Selecting on the canvas
+    Canvas,
+    /// Editing one of the four crop fields, by its index
+    Field(usize),
+    /// Loading the image or resetting the crop
+    Reset,
+    /// Rotating, flipping or straightening the image
+    Transform,
+    /// Choosing a preset, which fits its aspect ratio in the image
+    Preset,
+    /// Selecting one of the regions
+    Region,
+    /// Closing a polygon, which crops to its bounding box
+    Polygon,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CropChanged {
+    pub source: CropSource,
+}
+
+/// A drag across the canvas, in window coordinates like the canvas view
+/// maps them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CanvasDrag {
+    pub start: Point<Pixels>,
+    pub position: Point<Pixels>,
+}
+
+/// Sent as a drag across the canvas starts, moves and ends
+#[derive(Clone, Copy, Debug)]
+pub enum DragEvent {
+    Started(CanvasDrag),
+    Moved(CanvasDrag),
+    /// Released over the canvas
+    Dropped,
+    /// Released outside the canvas
+    Cancelled,
+}
+
+/// The crop being edited, the canvas and the fields show it and change it
+/// only through [`CropModel::set`]
+#[derive(Clone, Debug)]
+pub struct CropModel {
+    crop: ImageCrop,
+    drag: Option<CanvasDrag>,
+    /// What last changed the crop
+    source: Option<CropSource>,
+}
+
+impl EventEmitter<CropChanged> for CropModel {}
+impl EventEmitter<DragEvent> for CropModel {}
+
+impl Default for CropModel {
+    fn default() -> Self {
+        CropModel {
+            crop: ImageCrop::Uninitialized,
+            drag: None,
+            source: None,
+        }
+    }
+}
+
+impl CropModel {
+    pub fn crop(&self) -> &ImageCrop {
+        &self.crop
+    }
+
+    /// Replaces the crop, notifying views when it changed
+    pub fn set(&mut self, crop: impl Into<ImageCrop>, source: CropSource, cx: &mut Context<Self>) {
+        let crop = crop.into();
+        if crop != self.crop {
+            self.crop = crop;
+            self.source = Some(source);
+            cx.emit(CropChanged { source });
+            cx.notify();
+        }
+    }
+
+    /// Whether the crop was changed since the image was loaded or the crop
+    /// reset
+    pub fn is_edited(&self) -> bool {
+        self.source
+            .is_some_and(|source| source != CropSource::Reset)
+    }
+
+    /// The drag across the canvas, while the mouse button is held
+    pub fn drag(&self) -> Option<CanvasDrag> {
+        self.drag
+    }
+
+    pub fn start_drag(&mut self, position: Point<Pixels>, cx: &mut Context<Self>) {
+        let drag = CanvasDrag {
+            start: position,
+            position,
+        };
+        self.drag = Some(drag);
+        cx.emit(DragEvent::Started(drag));
+        cx.notify();
+    }
+
+    pub fn drag_to(&mut self, position: Point<Pixels>, cx: &mut Context<Self>) {
+        let Some(drag) = &mut self.drag else {
+            return;
+        };
+        drag.position = position;
+        cx.emit(DragEvent::Moved(*drag));
+        cx.notify();
+    }
+
+    /// Ends the drag, `dropped` when it's released over the canvas
+    pub fn end_drag(&mut self, dropped: bool, cx: &mut Context<Self>) {
+        if self.drag.take().is_none() {
+            return;
+        }
+        if dropped {
+            cx.emit(DragEvent::Dropped);
+        } else {
+            cx.emit(DragEvent::Cancelled);
+        }
+        cx.notify();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_crop::InitializedImageCrop;
+    use gpui::{AppContext, Entity, TestAppContext, point, px};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn crop(width: i32) -> InitializedImageCrop {
+        InitializedImageCrop {
+            crop_x: 0,
+            crop_y: 0,
+            width,
+            height: 10,
+        }
+    }
+
+    /// The events of a type the model sends from now on
+    fn record<E: Clone + 'static>(
+        cx: &mut TestAppContext,
+        model: &Entity<CropModel>,
+    ) -> Rc<RefCell<Vec<E>>>
+    where
+        CropModel: EventEmitter<E>,
+    {
+        let events = Rc::new(RefCell::new(vec![]));
+        cx.update(|cx| {
+            let events = events.clone();
+            cx.subscribe(model, move |_, event: &E, _| {
+                events.borrow_mut().push(event.clone())
+            })
+            .detach();
+        });
+        events
+    }
+
+    #[gpui::test]
+    fn changes_are_sent_with_their_source(cx: &mut TestAppContext) {
+        let model = cx.new(|_| CropModel::default());
+        let events = record::<CropChanged>(cx, &model);
+
+        model.update(cx, |model, cx| model.set(crop(10), CropSource::Reset, cx));
+        assert!(!model.read_with(cx, |model, _| model.is_edited()));
+        model.update(cx, |model, cx| {
+            model.set(crop(20), CropSource::Field(2), cx)
+        });
+        // Setting the crop it already has changes nothing
+        model.update(cx, |model, cx| model.set(crop(20), CropSource::Canvas, cx));
+
+        let sources: Vec<_> = events.borrow().iter().map(|event| event.source).collect();
+        assert_eq!(sources, vec![CropSource::Reset, CropSource::Field(2)]);
+        model.read_with(cx, |model, _| {
+            assert!(model.is_edited());
+            assert_eq!(model.crop(), &ImageCrop::from(crop(20)));
+        });
+    }
+
+    #[gpui::test]
+    fn drags_are_sent_as_they_start_move_and_end(cx: &mut TestAppContext) {
+        let model = cx.new(|_| CropModel::default());
+        let events = record::<DragEvent>(cx, &model);
+        let (start, end) = (point(px(1.), px(2.)), point(px(30.), px(40.)));
+
+        // Moving or ending without a drag does nothing
+        model.update(cx, |model, cx| {
+            model.drag_to(end, cx);
+            model.end_drag(true, cx);
+        });
+        assert!(events.borrow().is_empty());
+
+        model.update(cx, |model, cx| {
+            model.start_drag(start, cx);
+            model.drag_to(end, cx);
+        });
+        let drag = CanvasDrag {
+            start,
+            position: end,
+        };
+        assert_eq!(model.read_with(cx, |model, _| model.drag()), Some(drag));
+        model.update(cx, |model, cx| model.end_drag(true, cx));
+        assert_eq!(model.read_with(cx, |model, _| model.drag()), None);
+
+        model.update(cx, |model, cx| {
+            model.start_drag(start, cx);
+            model.end_drag(false, cx);
+        });
+
+        let events = events.borrow();
+        assert_eq!(events.len(), 5);
+        assert!(matches!(events[0], DragEvent::Started(drag) if drag.position == start));
+        assert!(matches!(events[1], DragEvent::Moved(moved) if moved == drag));
+        assert!(matches!(events[2], DragEvent::Dropped));
+        assert!(matches!(events[3], DragEvent::Started(_)));
+        assert!(matches!(events[4], DragEvent::Cancelled));
+    }
+}
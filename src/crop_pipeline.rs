@@ -0,0 +1,283 @@
+use crate::canvas_fill::CanvasFill;
+use crate::crop_shape::CropShape;
+use crate::image_crop::{FinalizedImageCrop, PolygonCrop};
+use crate::image_transform::ImageTransform;
+use crate::output_format::{OutputFormat, OutputOptions};
+use crate::output_size::OutputResize;
+use gpui::RenderImage;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{FilterType as PngFilterType, PngEncoder};
+use image::error::{ParameterError, ParameterErrorKind};
+use image::{
+    Frame, ImageEncoder, ImageError, ImageFormat, ImageResult, Rgb, RgbImage, RgbaImage, imageops,
+};
+use std::fs::File;
+use std::io::{BufWriter, Cursor};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Copies the pixels of a decoded image as RGBA, GPUI stores them as BGRA
+pub fn rgba_image(image: &RenderImage) -> Option<RgbaImage> {
+    let image_size = image.size(0);
+    let mut image_buf = RgbaImage::from_raw(
+        image_size.width.into(),
+        image_size.height.into(),
+        image.as_bytes(0)?.to_vec(),
+    )?;
+
+    // Convert from BGRA to RGBA.
+    for pixel in image_buf.as_chunks_mut::<4>().0 {
+        pixel.swap(0, 2);
+    }
+
+    Some(image_buf)
+}
+
+/// Creates an image GPUI can render from RGBA pixels
+pub fn render_image(mut image_buf: RgbaImage) -> Arc<RenderImage> {
+    // Convert from RGBA to BGRA.
+    for pixel in image_buf.as_chunks_mut::<4>().0 {
+        pixel.swap(0, 2);
+    }
+
+    Arc::new(RenderImage::new(vec![Frame::new(image_buf)]))
+}
+
+pub fn decode_image(bytes: &[u8]) -> ImageResult<Arc<RenderImage>> {
+    Ok(render_image(image::load_from_memory(bytes)?.into_rgba8()))
+}
+
+/// Crops the image, filling any part of the crop outside it with
+/// `canvas_fill`
+pub fn crop_image(
+    image_buf: &RgbaImage,
+    final_crop: &FinalizedImageCrop,
+    canvas_fill: &CanvasFill,
+) -> RgbaImage {
+    if final_crop.is_within(image_buf.width(), image_buf.height()) {
+        return imageops::crop_imm(
+            image_buf,
+            final_crop.crop_x as u32,
+            final_crop.crop_y as u32,
+            final_crop.width,
+            final_crop.height,
+        )
+        .to_image();
+    }
+
+    RgbaImage::from_fn(final_crop.width, final_crop.height, |x, y| {
+        canvas_fill.pixel(
+            image_buf,
+            final_crop.crop_x as i64 + x as i64,
+            final_crop.crop_y as i64 + y as i64,
+        )
+    })
+}
+
+/// Whether every pixel of the image is fully transparent
+pub fn is_transparent(image_buf: &RgbaImage) -> bool {
+    image_buf.pixels().all(|pixel| pixel.0[3] == 0)
+}
+
+/// Applies the rotation and flips to the source image, then crops, masks
+/// and resizes it. A polygon replaces the crop's shape. Fails for crops
+/// that aren't valid for the transformed image, see
+/// [`FinalizedImageCrop::is_valid`], wherever they come from
+pub fn process_image(
+    image_buf: &RgbaImage,
+    image_transform: &ImageTransform,
+    final_crop: &FinalizedImageCrop,
+    canvas_fill: &CanvasFill,
+    crop_shape: &CropShape,
+    polygon_crop: Option<&PolygonCrop>,
+    output_resize: &OutputResize,
+) -> ImageResult<RgbaImage> {
+    let (width, height) = image_transform.transformed_size(image_buf.width(), image_buf.height());
+    if !final_crop.is_valid(width, height) {
+        return Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::Generic("the crop is empty or out of range".to_string()),
+        )));
+    }
+
+    let image_buf = image_transform.apply(image_buf);
+    let mut cropped_image_buf = crop_image(&image_buf, final_crop, canvas_fill);
+    match polygon_crop {
+        Some(polygon_crop) => {
+            polygon_crop.apply_mask(&mut cropped_image_buf, final_crop.crop_x, final_crop.crop_y)
+        }
+        None => crop_shape.apply_mask(&mut cropped_image_buf),
+    }
+    Ok(output_resize.apply(cropped_image_buf))
+}
+
+/// Warns when a shaped crop is saved to a format that can't keep its
+/// transparent corners
+pub fn warn_mask_without_alpha(
+    crop_shape: &CropShape,
+    polygon_crop: Option<&PolygonCrop>,
+    dest_path: &Path,
+) {
+    let shape = match polygon_crop {
+        Some(_) => "polygon",
+        None if crop_shape.has_mask() => crop_shape.label(),
+        None => return,
+    };
+
+    if !OutputFormat::from_path(dest_path).supports_alpha() {
+        eprintln!(
+            "warn: {} has no transparency, pixels outside the {} will be black",
+            OutputFormat::from_path(dest_path).label(),
+            shape.to_lowercase(),
+        );
+    }
+}
+
+/// Composites the image onto black, so pixels masked by a shape or outside a
+/// transparent canvas come out black rather than with their hidden color
+fn flatten_alpha(image_buf: &RgbaImage) -> RgbImage {
+    RgbImage::from_fn(image_buf.width(), image_buf.height(), |x, y| {
+        let [red, green, blue, alpha] = image_buf.get_pixel(x, y).0;
+        let blend = |channel: u8| ((channel as u16 * alpha as u16 + 127) / 255) as u8;
+        Rgb([blend(red), blend(green), blend(blue)])
+    })
+}
+
+/// Saves the image in the format of the destination's file extension with
+/// the options of that format, flattening the alpha channel for formats that
+/// don't support it
+pub fn save_image(
+    image_buf: RgbaImage,
+    dest_path: &Path,
+    output_options: &OutputOptions,
+) -> ImageResult<()> {
+    let (width, height) = image_buf.dimensions();
+    let output_format = OutputFormat::from_path(dest_path);
+
+    // Output templates can point into directories that don't exist yet, as
+    // in {dir}/{date}/{stem}{ext}
+    if let Some(dir) = dest_path.parent()
+        && !dir.as_os_str().is_empty()
+    {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    if output_format.has_compression() {
+        let file = BufWriter::new(File::create(dest_path)?);
+        PngEncoder::new_with_quality(
+            file,
+            output_options.png_compression,
+            PngFilterType::Adaptive,
+        )
+        .write_image(
+            image_buf.as_raw(),
+            width,
+            height,
+            image::ExtendedColorType::Rgba8,
+        )
+    } else if output_format.supports_alpha() {
+        image::save_buffer(
+            dest_path,
+            image_buf.into_raw().as_slice(),
+            width,
+            height,
+            image::ExtendedColorType::Rgba8,
+        )
+    } else {
+        let rgb_image_buf = flatten_alpha(&image_buf);
+        if output_format.has_quality() {
+            let file = BufWriter::new(File::create(dest_path)?);
+            JpegEncoder::new_with_quality(file, output_options.jpeg_quality).write_image(
+                rgb_image_buf.as_raw(),
+                width,
+                height,
+                image::ExtendedColorType::Rgb8,
+            )
+        } else {
+            image::save_buffer(
+                dest_path,
+                rgb_image_buf.as_raw(),
+                width,
+                height,
+                image::ExtendedColorType::Rgb8,
+            )
+        }
+    }
+}
+
+pub fn encode_png(image_buf: &RgbaImage) -> ImageResult<Vec<u8>> {
+    let mut bytes = Cursor::new(vec![]);
+    image_buf.write_to(&mut bytes, ImageFormat::Png)?;
+    Ok(bytes.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_transform::TransformOp;
+    use image::Rgba;
+
+    #[test]
+    fn flatten_alpha_blackens_masked_pixels() {
+        let image_buf = RgbaImage::from_vec(
+            3,
+            1,
+            vec![255, 0, 0, 0, 200, 100, 50, 255, 200, 100, 50, 128],
+        )
+        .unwrap();
+
+        let flattened = flatten_alpha(&image_buf);
+        assert_eq!(flattened.get_pixel(0, 0), &Rgb([0, 0, 0]));
+        assert_eq!(flattened.get_pixel(1, 0), &Rgb([200, 100, 50]));
+        assert_eq!(flattened.get_pixel(2, 0), &Rgb([100, 50, 25]));
+    }
+
+    #[test]
+    fn invalid_crops_are_rejected() {
+        let image_buf = RgbaImage::new(10, 20);
+        let process = |crop_x, crop_y, width, height, image_transform: ImageTransform| {
+            let final_crop = FinalizedImageCrop {
+                crop_x,
+                crop_y,
+                width,
+                height,
+            };
+            process_image(
+                &image_buf,
+                &image_transform,
+                &final_crop,
+                &CanvasFill::default(),
+                &CropShape::default(),
+                None,
+                &OutputResize::default(),
+            )
+            .map(|image_buf| image_buf.dimensions())
+        };
+        let turned = ImageTransform::default().then(TransformOp::RotateClockwise);
+
+        assert_eq!(
+            process(-10, -20, 30, 60, ImageTransform::default()).ok(),
+            Some((30, 60))
+        );
+        assert!(process(0, 0, 0, 10, ImageTransform::default()).is_err());
+        assert!(process(10, 0, 5, 5, ImageTransform::default()).is_err());
+        assert!(process(-11, 0, 20, 5, ImageTransform::default()).is_err());
+        assert!(process(0, 0, 31, 5, ImageTransform::default()).is_err());
+        // The crop is checked against the rotated image
+        assert!(process(15, 0, 5, 5, ImageTransform::default()).is_err());
+        assert_eq!(process(15, 0, 5, 5, turned).ok(), Some((5, 5)));
+    }
+
+    #[test]
+    fn masked_corner_is_saved_black() {
+        let mut image_buf = RgbaImage::from_pixel(8, 8, Rgba([255, 255, 255, 255]));
+        CropShape::Ellipse.apply_mask(&mut image_buf);
+        assert_eq!(image_buf.get_pixel(0, 0).0[3], 0);
+
+        let dest_path = std::env::temp_dir().join("basicrop_masked_corner.bmp");
+        save_image(image_buf, &dest_path, &OutputOptions::new(75)).unwrap();
+        let saved = image::open(&dest_path).unwrap().into_rgb8();
+        std::fs::remove_file(&dest_path).unwrap();
+        assert_eq!(saved.get_pixel(0, 0), &Rgb([0, 0, 0]));
+        assert_eq!(saved.get_pixel(4, 4), &Rgb([255, 255, 255]));
+    }
+}
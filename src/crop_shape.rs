@@ -0,0 +1,146 @@
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+
+/// Labels of the shapes in the window, in the order of the variants of
+/// [`CropShape`]
+pub const CROP_SHAPES: [&str; 2] = ["Rectangle", "Ellipse"];
+
+/// Outline of the cropped image, pixels outside it are made transparent
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CropShape {
+    Rectangle { corner_radius: u32 },
+    Ellipse,
+}
+
+impl Default for CropShape {
+    fn default() -> Self {
+        CropShape::Rectangle { corner_radius: 0 }
+    }
+}
+
+impl CropShape {
+    /// Parses the value of `--shape`, `rectangle` or `ellipse`
+    pub fn parse(value: &str) -> Option<CropShape> {
+        match value {
+            "rectangle" | "rect" => Some(CropShape::default()),
+            "ellipse" | "circle" => Some(CropShape::Ellipse),
+            _ => None,
+        }
+    }
+
+    /// Builds the shape from the shape and corner radius controls in the
+    /// window, an invalid radius means square corners
+    pub fn from_fields(shape: &str, corner_radius: &str) -> CropShape {
+        match CROP_SHAPES.iter().position(|label| *label == shape) {
+            Some(1) => CropShape::Ellipse,
+            _ => CropShape::Rectangle {
+                corner_radius: corner_radius.trim().parse().unwrap_or(0),
+            },
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CropShape::Rectangle { .. } => CROP_SHAPES[0],
+            CropShape::Ellipse => CROP_SHAPES[1],
+        }
+    }
+
+    /// Whether the shape leaves transparent corners
+    pub fn has_mask(&self) -> bool {
+        *self != CropShape::Rectangle { corner_radius: 0 }
+    }
+
+    /// Makes the pixels outside the shape transparent, with anti-aliased
+    /// edges
+    pub fn apply_mask(&self, image_buf: &mut RgbaImage) {
+        if !self.has_mask() {
+            return;
+        }
+
+        let (width, height) = image_buf.dimensions();
+        let (width, height) = (width as f32, height as f32);
+        for (x, y, pixel) in image_buf.enumerate_pixels_mut() {
+            let distance = self.signed_distance(x as f32 + 0.5, y as f32 + 0.5, width, height);
+            let coverage = (0.5 - distance).clamp(0., 1.);
+            pixel.0[3] = (pixel.0[3] as f32 * coverage).round() as u8;
+        }
+    }
+
+    /// Points along the outline of the shape fitting a `width` by `height`
+    /// box at the origin, with the corner radius multiplied by `scale`
+    pub fn outline(&self, width: f32, height: f32, scale: f32) -> Vec<(f32, f32)> {
+        match *self {
+            CropShape::Ellipse => {
+                let (radius_x, radius_y) = (width / 2., height / 2.);
+                (0..64)
+                    .map(|index| {
+                        let angle = index as f32 / 64. * TAU;
+                        (
+                            radius_x + radius_x * angle.cos(),
+                            radius_y + radius_y * angle.sin(),
+                        )
+                    })
+                    .collect()
+            }
+            CropShape::Rectangle { corner_radius } => {
+                let radius = (corner_radius as f32 * scale)
+                    .min(width / 2.)
+                    .min(height / 2.);
+                let corners = [
+                    (width - radius, height - radius),
+                    (radius, height - radius),
+                    (radius, radius),
+                    (width - radius, radius),
+                ];
+                corners
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(corner, (center_x, center_y))| {
+                        (0..=8).map(move |index| {
+                            let angle = (corner as f32 + index as f32 / 8.) * TAU / 4.;
+                            (
+                                center_x + radius * angle.cos(),
+                                center_y + radius * angle.sin(),
+                            )
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Approximate distance from a point to the outline of the shape
+    /// fitting a `width` by `height` box, negative inside it
+    fn signed_distance(&self, x: f32, y: f32, width: f32, height: f32) -> f32 {
+        let (half_width, half_height) = (width / 2., height / 2.);
+        let (x, y) = ((x - half_width).abs(), (y - half_height).abs());
+
+        match *self {
+            CropShape::Ellipse => {
+                // First order approximation, the implicit function divided
+                // by the length of its gradient
+                let (radius_x, radius_y) = (half_width.max(0.5), half_height.max(0.5));
+                let value = (x / radius_x).powi(2) + (y / radius_y).powi(2) - 1.;
+                let gradient_x = 2. * x / (radius_x * radius_x);
+                let gradient_y = 2. * y / (radius_y * radius_y);
+                let gradient = (gradient_x * gradient_x + gradient_y * gradient_y).sqrt();
+                if gradient == 0. {
+                    -radius_x.min(radius_y)
+                } else {
+                    value / gradient
+                }
+            }
+            CropShape::Rectangle { corner_radius } => {
+                let radius = (corner_radius as f32).min(half_width).min(half_height);
+                let offset_x = x - (half_width - radius);
+                let offset_y = y - (half_height - radius);
+                let outside = offset_x.max(0.).hypot(offset_y.max(0.));
+                let inside = offset_x.max(offset_y).min(0.);
+                outside + inside - radius
+            }
+        }
+    }
+}
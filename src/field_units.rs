@@ -0,0 +1,427 @@
+use std::io::Read;
+use std::path::Path;
+
+/// Resolution assumed for images that don't store one
+pub const DEFAULT_DPI: f32 = 72.;
+
+const MILLIMETERS_PER_INCH: f32 = 25.4;
+
+/// Unit a crop field is shown and typed in
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Unit {
+    #[default]
+    Pixels,
+    /// Of the image's width or height, depending on the field
+    Percent,
+    Millimeters,
+    Inches,
+}
+
+/// What the units of a crop field are relative to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FieldScale {
+    pub image_width: f32,
+    pub image_height: f32,
+    /// Whether percentages are of the width rather than the height
+    pub is_horizontal: bool,
+    pub dpi: f32,
+}
+
+/// A value while evaluating an expression, numbers without a unit are in
+/// the field's unit unless they scale a length
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Quantity {
+    Number(f32),
+    Pixels(f32),
+}
+
+impl Unit {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Unit::Pixels => "px",
+            Unit::Percent => "%",
+            Unit::Millimeters => "mm",
+            Unit::Inches => "in",
+        }
+    }
+
+    /// The unit after this one when cycling through them
+    pub fn next(&self) -> Unit {
+        match self {
+            Unit::Pixels => Unit::Percent,
+            Unit::Percent => Unit::Millimeters,
+            Unit::Millimeters => Unit::Inches,
+            Unit::Inches => Unit::Pixels,
+        }
+    }
+
+    fn from_suffix(suffix: &str) -> Option<Unit> {
+        match suffix {
+            "px" => Some(Unit::Pixels),
+            "%" => Some(Unit::Percent),
+            "mm" => Some(Unit::Millimeters),
+            "in" => Some(Unit::Inches),
+            _ => None,
+        }
+    }
+}
+
+impl FieldScale {
+    pub fn pixels_per_unit(&self, unit: Unit) -> f32 {
+        match unit {
+            Unit::Pixels => 1.,
+            Unit::Percent if self.is_horizontal => self.image_width / 100.,
+            Unit::Percent => self.image_height / 100.,
+            Unit::Millimeters => self.dpi / MILLIMETERS_PER_INCH,
+            Unit::Inches => self.dpi,
+        }
+    }
+
+    /// A pixel value in a unit, whole pixels and otherwise up to two
+    /// decimals
+    pub fn format(&self, pixels: f32, unit: Unit) -> String {
+        // Percentages of an image that isn't loaded yet are shown in pixels
+        let pixels_per_unit = self.pixels_per_unit(unit);
+        if unit == Unit::Pixels || pixels_per_unit <= 0. {
+            return (pixels.round() as i32).to_string();
+        }
+
+        let value = format!("{:.2}", pixels / pixels_per_unit);
+        let value = value.trim_end_matches('0').trim_end_matches('.');
+        match value {
+            "-0" => "0".to_string(),
+            value => value.to_string(),
+        }
+    }
+
+    /// Evaluates what was typed in a field to pixels. Numbers are in the
+    /// field's unit or followed by px, %, mm or in, `w` and `h` are the
+    /// image's size, and a leading `+` adds to the current value, as in
+    /// `50%`, `w/2`, `1920-100` or `+20`
+    pub fn evaluate(&self, text: &str, unit: Unit, current_pixels: f32) -> Option<f32> {
+        let text = text.trim();
+        let (text, base) = match text.strip_prefix('+') {
+            Some(rest) => (rest, current_pixels),
+            None => (text, 0.),
+        };
+
+        let mut parser = Parser {
+            scale: self,
+            unit,
+            tokens: tokenize(text)?,
+            position: 0,
+        };
+        let quantity = parser.expression()?;
+        if parser.position != parser.tokens.len() {
+            return None;
+        }
+
+        let pixels = parser.to_pixels(quantity);
+        pixels.is_finite().then_some(base + pixels)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f32, Option<Unit>),
+    Variable(char),
+    Operator(char),
+}
+
+fn tokenize(text: &str) -> Option<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+    while let Some(&char) = chars.peek() {
+        match char {
+            ' ' => {
+                chars.next();
+            }
+            '+' | '-' | '*' | '/' | '(' | ')' => {
+                tokens.push(Token::Operator(char));
+                chars.next();
+            }
+            'w' | 'h' => {
+                tokens.push(Token::Variable(char));
+                chars.next();
+            }
+            '0'..='9' | '.' => {
+                let mut number = String::new();
+                while let Some(&digit) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.') {
+                    number.push(digit);
+                    chars.next();
+                }
+                let mut suffix = String::new();
+                while let Some(&letter) = chars
+                    .peek()
+                    .filter(|c| c.is_ascii_alphabetic() || **c == '%')
+                {
+                    suffix.push(letter);
+                    chars.next();
+                }
+                let unit = match suffix.as_str() {
+                    "" => None,
+                    suffix => Some(Unit::from_suffix(suffix)?),
+                };
+                tokens.push(Token::Number(number.parse().ok()?, unit));
+            }
+            _ => return None,
+        }
+    }
+
+    Some(tokens)
+}
+
+/// Recursive descent over `expression = term (('+' | '-') term)*`,
+/// `term = factor (('*' | '/') factor)*` and
+/// `factor = '-' factor | '(' expression ')' | number | variable`
+struct Parser<'a> {
+    scale: &'a FieldScale,
+    unit: Unit,
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn next_operator(&mut self, operators: &[char]) -> Option<char> {
+        match self.tokens.get(self.position) {
+            Some(Token::Operator(operator)) if operators.contains(operator) => {
+                self.position += 1;
+                Some(*operator)
+            }
+            _ => None,
+        }
+    }
+
+    fn to_pixels(&self, quantity: Quantity) -> f32 {
+        match quantity {
+            Quantity::Number(number) => number * self.scale.pixels_per_unit(self.unit),
+            Quantity::Pixels(pixels) => pixels,
+        }
+    }
+
+    fn expression(&mut self) -> Option<Quantity> {
+        let mut left = self.term()?;
+        while let Some(operator) = self.next_operator(&['+', '-']) {
+            let right = self.term()?;
+            let sign = if operator == '+' { 1. } else { -1. };
+            left = match (left, right) {
+                (Quantity::Number(left), Quantity::Number(right)) => {
+                    Quantity::Number(left + sign * right)
+                }
+                _ => Quantity::Pixels(self.to_pixels(left) + sign * self.to_pixels(right)),
+            };
+        }
+        Some(left)
+    }
+
+    fn term(&mut self) -> Option<Quantity> {
+        let mut left = self.factor()?;
+        while let Some(operator) = self.next_operator(&['*', '/']) {
+            let right = self.factor()?;
+            left = match (operator, left, right) {
+                ('*', Quantity::Number(left), Quantity::Number(right)) => {
+                    Quantity::Number(left * right)
+                }
+                ('*', Quantity::Pixels(pixels), Quantity::Number(number))
+                | ('*', Quantity::Number(number), Quantity::Pixels(pixels)) => {
+                    Quantity::Pixels(pixels * number)
+                }
+                ('/', Quantity::Number(left), Quantity::Number(right)) => {
+                    Quantity::Number(left / right)
+                }
+                ('/', Quantity::Pixels(left), Quantity::Number(right)) => {
+                    Quantity::Pixels(left / right)
+                }
+                ('/', Quantity::Pixels(left), Quantity::Pixels(right)) => {
+                    Quantity::Number(left / right)
+                }
+                // Lengths multiplied together or dividing a number aren't
+                // lengths anymore
+                _ => return None,
+            };
+        }
+        Some(left)
+    }
+
+    fn factor(&mut self) -> Option<Quantity> {
+        if self.next_operator(&['-']).is_some() {
+            return match self.factor()? {
+                Quantity::Number(number) => Some(Quantity::Number(-number)),
+                Quantity::Pixels(pixels) => Some(Quantity::Pixels(-pixels)),
+            };
+        }
+        if self.next_operator(&['(']).is_some() {
+            let quantity = self.expression()?;
+            self.next_operator(&[')'])?;
+            return Some(quantity);
+        }
+
+        let token = self.tokens.get(self.position)?.clone();
+        self.position += 1;
+        match token {
+            Token::Number(number, None) => Some(Quantity::Number(number)),
+            Token::Number(number, Some(unit)) => {
+                Some(Quantity::Pixels(number * self.scale.pixels_per_unit(unit)))
+            }
+            Token::Variable('w') => Some(Quantity::Pixels(self.scale.image_width)),
+            Token::Variable(_) => Some(Quantity::Pixels(self.scale.image_height)),
+            Token::Operator(_) => None,
+        }
+    }
+}
+
+/// The resolution stored in a PNG's pHYs chunk or a JPEG's JFIF header
+pub fn read_dpi(image_path: &Path) -> Option<f32> {
+    // Both are near the start of the file
+    let mut bytes = vec![];
+    std::fs::File::open(image_path)
+        .ok()?
+        .take(64 * 1024)
+        .read_to_end(&mut bytes)
+        .ok()?;
+    let read_u16 = |offset: usize| {
+        Some(u16::from_be_bytes(
+            bytes.get(offset..offset + 2)?.try_into().ok()?,
+        ))
+    };
+    let read_u32 = |offset: usize| {
+        Some(u32::from_be_bytes(
+            bytes.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        // Chunks are a length, a type, the data and a checksum
+        let mut offset = 8;
+        while let Some(length) = read_u32(offset) {
+            let chunk_type = bytes.get(offset + 4..offset + 8)?;
+            if chunk_type == b"pHYs" {
+                let pixels_per_unit = read_u32(offset + 8)?;
+                let is_meters = *bytes.get(offset + 16)? == 1;
+                return (is_meters && pixels_per_unit > 0)
+                    .then(|| pixels_per_unit as f32 * MILLIMETERS_PER_INCH / 1000.);
+            }
+            if chunk_type == b"IDAT" {
+                return None;
+            }
+            offset += length as usize + 12;
+        }
+    } else if bytes.starts_with(&[0xff, 0xd8]) && bytes.get(6..11) == Some(b"JFIF\0") {
+        // Density units of 1 are dots per inch and 2 dots per centimeter
+        let density = read_u16(14)? as f32;
+        return match bytes.get(13)? {
+            1 if density > 0. => Some(density),
+            2 if density > 0. => Some(density * 2.54),
+            _ => None,
+        };
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2000 by 1000 image at 300 DPI
+    fn scale(is_horizontal: bool) -> FieldScale {
+        FieldScale {
+            image_width: 2000.,
+            image_height: 1000.,
+            is_horizontal,
+            dpi: 300.,
+        }
+    }
+
+    fn evaluate(text: &str, unit: Unit) -> Option<f32> {
+        scale(true).evaluate(text, unit, 100.)
+    }
+
+    #[test]
+    fn numbers_are_in_the_field_unit() {
+        assert_eq!(evaluate("20", Unit::Pixels), Some(20.));
+        assert_eq!(evaluate("25", Unit::Percent), Some(500.));
+        assert_eq!(scale(false).evaluate("25", Unit::Percent, 0.), Some(250.));
+        assert_eq!(evaluate("2", Unit::Inches), Some(600.));
+        assert_eq!(evaluate("25.4", Unit::Millimeters), Some(300.));
+    }
+
+    #[test]
+    fn suffixes_override_the_field_unit() {
+        assert_eq!(evaluate("20px", Unit::Inches), Some(20.));
+        assert_eq!(evaluate("50%", Unit::Pixels), Some(1000.));
+        assert_eq!(evaluate("1in", Unit::Percent), Some(300.));
+        assert_eq!(evaluate("25.4mm", Unit::Pixels), Some(300.));
+    }
+
+    #[test]
+    fn arithmetic_follows_precedence() {
+        assert_eq!(evaluate("1920-100", Unit::Pixels), Some(1820.));
+        assert_eq!(evaluate("w/2", Unit::Pixels), Some(1000.));
+        assert_eq!(evaluate("h - 10%", Unit::Pixels), Some(800.));
+        assert_eq!(evaluate("2 + 3 * 4", Unit::Pixels), Some(14.));
+        assert_eq!(evaluate("(2 + 3) * 4", Unit::Pixels), Some(20.));
+        assert_eq!(evaluate("-(w - h)", Unit::Pixels), Some(-1000.));
+        assert_eq!(evaluate("w / h", Unit::Percent), Some(40.));
+        // Numbers added to a length are in the field's unit
+        assert_eq!(evaluate("1in + 1", Unit::Inches), Some(600.));
+    }
+
+    #[test]
+    fn leading_plus_adds_to_the_current_value() {
+        assert_eq!(evaluate("+20", Unit::Pixels), Some(120.));
+        assert_eq!(evaluate("+1%", Unit::Pixels), Some(120.));
+        assert_eq!(evaluate("+-50", Unit::Pixels), Some(50.));
+    }
+
+    #[test]
+    fn invalid_expressions_are_rejected() {
+        for text in [
+            "", "abc", "10 kg", "w * h", "10 / w", "(10", "10)", "1..2", "5 5", "x",
+        ] {
+            assert_eq!(evaluate(text, Unit::Pixels), None, "{text}");
+        }
+        assert_eq!(evaluate("1/0", Unit::Pixels), None);
+    }
+
+    #[test]
+    fn formatting_rounds_to_the_unit() {
+        let scale = scale(true);
+        assert_eq!(scale.format(1234.6, Unit::Pixels), "1235");
+        assert_eq!(scale.format(500., Unit::Percent), "25");
+        assert_eq!(scale.format(333., Unit::Percent), "16.65");
+        assert_eq!(scale.format(100., Unit::Inches), "0.33");
+        assert_eq!(scale.format(150., Unit::Inches), "0.5");
+        assert_eq!(scale.format(300., Unit::Millimeters), "25.4");
+        assert_eq!(scale.format(-0.001, Unit::Inches), "0");
+    }
+
+    #[test]
+    fn percentages_without_an_image_are_shown_in_pixels() {
+        let scale = FieldScale {
+            image_width: 0.,
+            image_height: 0.,
+            is_horizontal: true,
+            dpi: DEFAULT_DPI,
+        };
+        assert_eq!(scale.format(42.4, Unit::Percent), "42");
+    }
+
+    #[test]
+    fn units_cycle_through_all_of_them() {
+        let mut unit = Unit::default();
+        let mut labels = vec![];
+        for _ in 0..4 {
+            labels.push(unit.label());
+            unit = unit.next();
+        }
+        assert_eq!(unit, Unit::Pixels);
+        assert_eq!(labels, ["px", "%", "mm", "in"]);
+        for label in labels {
+            assert_eq!(
+                Unit::from_suffix(label).map(|unit| unit.label()),
+                Some(label)
+            );
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use crate::image_crop::FinalizedImageCrop;
+
+/// Labels of the grid modes in the window, in the order of the variants of
+/// [`GridCells`]
+pub const GRID_MODES: [&str; 2] = ["Columns × rows", "Cell size"];
+
+/// How the crop is divided into cells
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GridCells {
+    Count {
+        columns: u32,
+        rows: u32,
+    },
+    /// Fixed size cells, as many as fit in the crop
+    Size {
+        width: u32,
+        height: u32,
+    },
+}
+
+/// A grid over the crop with every cell saved to its own file, for cutting
+/// up sprite sheets
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GridSlice {
+    pub cells: GridCells,
+    /// Space between the edges of the crop and the outer cells
+    pub margin: u32,
+    /// Space between neighbouring cells
+    pub spacing: u32,
+    /// Leaves out cells without any visible pixels
+    pub skip_empty: bool,
+}
+
+impl Default for GridSlice {
+    fn default() -> Self {
+        GridSlice {
+            cells: GridCells::Count {
+                columns: 2,
+                rows: 2,
+            },
+            margin: 0,
+            spacing: 0,
+            skip_empty: false,
+        }
+    }
+}
+
+impl GridCells {
+    /// Parses the value of `--grid`, `COLUMNSxROWS`
+    pub fn parse_count(value: &str) -> Option<GridCells> {
+        let (columns, rows) = parse_pair(value)?;
+        Some(GridCells::Count { columns, rows })
+    }
+
+    /// Parses the value of `--grid-cell`, `WIDTHxHEIGHT`
+    pub fn parse_size(value: &str) -> Option<GridCells> {
+        let (width, height) = parse_pair(value)?;
+        Some(GridCells::Size { width, height })
+    }
+
+    /// Builds the cells from the mode and the two number fields in the
+    /// window
+    pub fn from_fields(mode: &str, first: &str, second: &str) -> Option<GridCells> {
+        let value = format!("{}x{}", first.trim(), second.trim());
+        match GRID_MODES.iter().position(|label| *label == mode) {
+            Some(1) => GridCells::parse_size(&value),
+            _ => GridCells::parse_count(&value),
+        }
+    }
+
+    /// Starting values when switching to a mode in the window
+    pub fn default_for_mode(mode: &str) -> GridCells {
+        match GRID_MODES.iter().position(|label| *label == mode) {
+            Some(1) => GridCells::Size {
+                width: 64,
+                height: 64,
+            },
+            _ => GridSlice::default().cells,
+        }
+    }
+
+    pub fn mode(&self) -> &'static str {
+        match self {
+            GridCells::Count { .. } => GRID_MODES[0],
+            GridCells::Size { .. } => GRID_MODES[1],
+        }
+    }
+
+    /// The two values to show in the window's fields
+    pub fn field_values(&self) -> (String, String) {
+        match *self {
+            GridCells::Count { columns, rows } => (columns.to_string(), rows.to_string()),
+            GridCells::Size { width, height } => (width.to_string(), height.to_string()),
+        }
+    }
+}
+
+impl GridSlice {
+    /// The number of columns and rows of cells within a crop and the width
+    /// and height of each, leaving out a partial last row or column of fixed
+    /// size cells. Margins and spacing too large for the crop leave no cells
+    pub fn dimensions(&self, crop: &FinalizedImageCrop) -> (u32, u32, u32, u32) {
+        let inner_width = crop.width.saturating_sub(self.margin.saturating_mul(2));
+        let inner_height = crop.height.saturating_sub(self.margin.saturating_mul(2));
+        let spacing = self.spacing;
+        let (columns, rows, cell_width, cell_height) = match self.cells {
+            GridCells::Count { columns, rows } => {
+                let cell_side = |inner: u32, count: u32| {
+                    let spacings = spacing.saturating_mul(count.saturating_sub(1));
+                    inner
+                        .saturating_sub(spacings)
+                        .checked_div(count)
+                        .unwrap_or(0)
+                };
+                (
+                    columns,
+                    rows,
+                    cell_side(inner_width, columns),
+                    cell_side(inner_height, rows),
+                )
+            }
+            GridCells::Size { width, height } => {
+                let count = |inner: u32, side: u32| {
+                    (inner as u64 + spacing as u64)
+                        .checked_div(side as u64 + spacing as u64)
+                        .unwrap_or(0) as u32
+                };
+                (
+                    count(inner_width, width),
+                    count(inner_height, height),
+                    width,
+                    height,
+                )
+            }
+        };
+        if columns == 0 || rows == 0 || cell_width == 0 || cell_height == 0 {
+            return (0, 0, 0, 0);
+        }
+
+        (columns, rows, cell_width, cell_height)
+    }
+
+    /// The cells within a crop by row and column, see [`Self::dimensions`]
+    pub fn cells(&self, crop: &FinalizedImageCrop) -> Vec<(u32, u32, FinalizedImageCrop)> {
+        let (columns, rows, cell_width, cell_height) = self.dimensions(crop);
+
+        // Cells lie within the crop, the offsets only overflow for crops at
+        // the very end of the coordinate range
+        let offset = |start: i32, index: u32, side: u32| {
+            let offset = self.margin as i64 + index as i64 * (side as i64 + self.spacing as i64);
+            i32::try_from(start as i64 + offset).ok()
+        };
+        (0..rows)
+            .flat_map(|row| {
+                (0..columns).filter_map(move |column| {
+                    let cell = FinalizedImageCrop {
+                        crop_x: offset(crop.crop_x, column, cell_width)?,
+                        crop_y: offset(crop.crop_y, row, cell_height)?,
+                        width: cell_width,
+                        height: cell_height,
+                    };
+                    Some((row, column, cell))
+                })
+            })
+            .collect()
+    }
+}
+
+/// Parses `AxB` where both are positive
+fn parse_pair(value: &str) -> Option<(u32, u32)> {
+    let (first, second) = value.split_once(['x', 'X'])?;
+    let parse_side = |side: &str| side.parse::<u32>().ok().filter(|side| *side > 0);
+
+    Some((parse_side(first)?, parse_side(second)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crop(crop_x: i32, crop_y: i32, width: u32, height: u32) -> FinalizedImageCrop {
+        FinalizedImageCrop {
+            crop_x,
+            crop_y,
+            width,
+            height,
+        }
+    }
+
+    fn grid(cells: GridCells, margin: u32, spacing: u32) -> GridSlice {
+        GridSlice {
+            cells,
+            margin,
+            spacing,
+            skip_empty: false,
+        }
+    }
+
+    #[test]
+    fn count_grid_divides_the_crop() {
+        let cells = grid(
+            GridCells::Count {
+                columns: 3,
+                rows: 2,
+            },
+            0,
+            0,
+        )
+        .cells(&crop(10, 20, 90, 40));
+
+        assert_eq!(cells.len(), 6);
+        assert_eq!(cells[0], (0, 0, crop(10, 20, 30, 20)));
+        assert_eq!(cells[2], (0, 2, crop(70, 20, 30, 20)));
+        assert_eq!(cells[5], (1, 2, crop(70, 40, 30, 20)));
+    }
+
+    #[test]
+    fn size_grid_leaves_out_partial_rows_and_columns() {
+        let cells = grid(
+            GridCells::Size {
+                width: 32,
+                height: 32,
+            },
+            0,
+            0,
+        )
+        .cells(&crop(0, 0, 100, 70));
+
+        // 100 fits three columns and 70 two rows, the rest is left out
+        assert_eq!(cells.len(), 6);
+        assert_eq!(cells.last(), Some(&(1, 2, crop(64, 32, 32, 32))));
+    }
+
+    #[test]
+    fn margin_and_spacing_offset_the_cells() {
+        let cells = grid(
+            GridCells::Count {
+                columns: 2,
+                rows: 2,
+            },
+            5,
+            10,
+        )
+        .cells(&crop(0, 0, 110, 60));
+
+        // 100 wide inside the margin, less 10 of spacing, is two 45 wide cells
+        assert_eq!(cells[0], (0, 0, crop(5, 5, 45, 20)));
+        assert_eq!(cells[3], (1, 1, crop(60, 35, 45, 20)));
+
+        let cells = grid(
+            GridCells::Size {
+                width: 20,
+                height: 20,
+            },
+            5,
+            10,
+        )
+        .cells(&crop(0, 0, 110, 60));
+        assert_eq!(cells.len(), 6);
+        assert_eq!(cells.last(), Some(&(1, 2, crop(65, 35, 20, 20))));
+    }
+
+    #[test]
+    fn dimensions_match_the_cells() {
+        let crop = crop(0, 0, 110, 60);
+        let count = grid(
+            GridCells::Count {
+                columns: 2,
+                rows: 3,
+            },
+            5,
+            10,
+        );
+        let size = grid(
+            GridCells::Size {
+                width: 20,
+                height: 20,
+            },
+            5,
+            10,
+        );
+
+        assert_eq!(count.dimensions(&crop), (2, 3, 45, 10));
+        assert_eq!(size.dimensions(&crop), (3, 2, 20, 20));
+        for grid_slice in [count, size] {
+            let (columns, rows, _, _) = grid_slice.dimensions(&crop);
+            assert_eq!(grid_slice.cells(&crop).len(), (columns * rows) as usize);
+        }
+        // A grid finer than the crop's pixels has no cells at all
+        let too_fine = grid(
+            GridCells::Count {
+                columns: 1000,
+                rows: 1,
+            },
+            0,
+            0,
+        );
+        assert_eq!(too_fine.dimensions(&crop), (0, 0, 0, 0));
+    }
+
+    #[test]
+    fn oversized_margins_and_spacing_leave_no_cells() {
+        let crop = crop(0, 0, 100, 100);
+        let count = GridCells::Count {
+            columns: 4,
+            rows: 4,
+        };
+        let size = GridCells::Size {
+            width: 10,
+            height: 10,
+        };
+
+        assert!(grid(count, u32::MAX, 0).cells(&crop).is_empty());
+        assert!(grid(count, 0, u32::MAX).cells(&crop).is_empty());
+        assert!(grid(size, u32::MAX, 0).cells(&crop).is_empty());
+        // A single cell needs no spacing
+        assert_eq!(grid(size, 0, u32::MAX).cells(&crop).len(), 1);
+        assert!(
+            grid(
+                GridCells::Count {
+                    columns: u32::MAX,
+                    rows: u32::MAX,
+                },
+                0,
+                0
+            )
+            .cells(&crop)
+            .is_empty()
+        );
+    }
+}
@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+/// Labels of the guides in the window, in the order of the variants of
+/// [`Guides`]
+pub const GUIDES: [&str; 3] = ["No guides", "Thirds", "Center"];
+
+/// Lines drawn over the crop to help with composition
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Guides {
+    #[default]
+    None,
+    /// Rule of thirds
+    Thirds,
+    Center,
+}
+
+impl Guides {
+    /// Parses the value of `--guides`, `none`, `thirds` or `center`
+    pub fn parse(value: &str) -> Option<Guides> {
+        match value {
+            "none" => Some(Guides::None),
+            "thirds" => Some(Guides::Thirds),
+            "center" => Some(Guides::Center),
+            _ => None,
+        }
+    }
+
+    pub fn from_label(label: &str) -> Guides {
+        match GUIDES.iter().position(|guides| *guides == label) {
+            Some(1) => Guides::Thirds,
+            Some(2) => Guides::Center,
+            _ => Guides::None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Guides::None => GUIDES[0],
+            Guides::Thirds => GUIDES[1],
+            Guides::Center => GUIDES[2],
+        }
+    }
+
+    /// Positions of the lines across the crop, as fractions of its width
+    /// and height
+    pub fn fractions(&self) -> &'static [f32] {
+        match self {
+            Guides::None => &[],
+            Guides::Thirds => &[1. / 3., 2. / 3.],
+            Guides::Center => &[0.5],
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use crate::args::Args;
+use crate::config::Config;
+use crate::crop_pipeline::{is_transparent, process_image, save_image, warn_mask_without_alpha};
+use crate::image_crop::{CropRegion, FinalizedImageCrop, InitializedImageCrop};
+use crate::image_transform::ImageTransform;
+use crate::misc::region_dest_path;
+use crate::output_format::OutputOptions;
+use crate::photo_detect::{DEFAULT_DETECT_TOLERANCE, detect_photos};
+use crate::preset::Preset;
+use crate::recipe::Recipe;
+use crate::sidecar::{Sidecar, SidecarOutput};
+use std::path::{Path, PathBuf};
+
+/// Crops the source-image without opening a window, returns the exit code
+pub fn run(args: &Args, config: &Config, preset: Option<&Preset>) -> i32 {
+    let Some(image_path) = &args.image_path else {
+        eprintln!("error: missing source-image for --headless");
+        return 1;
+    };
+    // Without an output-image every crop gets its own path from the
+    // output template
+    let dest_path = |crop: &FinalizedImageCrop| match &args.dest_image_path {
+        Some(dest_path) => dest_path.clone(),
+        None => config.output.template.expand(image_path, crop),
+    };
+
+    let image_buf = match image::open(image_path) {
+        Ok(image) => image.into_rgba8(),
+        Err(error) => {
+            eprintln!("error: failed to open source image: {:?}", error);
+            return 1;
+        }
+    };
+
+    let image_transform = ImageTransform::from_ops(&args.transform_ops, args.straighten);
+    let (width, height) = image_transform.transformed_size(image_buf.width(), image_buf.height());
+    let (crop_x, crop_y, width, height) =
+        image_transform.straightened_bounds(width as f32, height as f32);
+    let (crop_x, crop_y, width, height) = match preset {
+        Some(preset) => preset.crop(crop_x, crop_y, width, height),
+        None => (crop_x, crop_y, width, height),
+    };
+    // Whole pixels inside the straightened image, as the window does
+    let default_crop =
+        InitializedImageCrop::inside_edges(crop_x, crop_y, crop_x + width, crop_y + height);
+    let default_crop = FinalizedImageCrop {
+        crop_x: default_crop.crop_x,
+        crop_y: default_crop.crop_y,
+        width: u32::try_from(default_crop.width).unwrap_or(0),
+        height: u32::try_from(default_crop.height).unwrap_or(0),
+    };
+
+    let crop_regions = if args.detect_photos {
+        let tolerance = args.detect_tolerance.unwrap_or(DEFAULT_DETECT_TOLERANCE);
+        let photos = detect_photos(&image_transform.apply(&image_buf), tolerance);
+        println!("info: detected {} photos", photos.len());
+        if photos.is_empty() {
+            eprintln!("error: no photos found, try a different --detect-tolerance");
+            return 1;
+        }
+
+        photos
+            .into_iter()
+            .map(|photo| CropRegion {
+                name: String::new(),
+                image_crop: photo.into(),
+            })
+            .collect()
+    } else {
+        args.crop_regions.clone()
+    };
+
+    warn_mask_without_alpha(&args.crop_shape, None, &dest_path(&default_crop));
+
+    if let Some(recipe_path) = &args.save_recipe_path {
+        let (image_width, image_height) =
+            image_transform.transformed_size(image_buf.width(), image_buf.height());
+        let recipe = Recipe {
+            image_width,
+            image_height,
+            crop: match crop_regions.first() {
+                Some(region) => region.image_crop.to_final().unwrap(),
+                None => default_crop.clone(),
+            },
+            transform: image_transform,
+            shape: args.crop_shape,
+            fill: args.canvas_fill,
+            resize: args.output_resize,
+            on_size_mismatch: args.size_mismatch.unwrap_or_default(),
+            anchor: args.anchor.unwrap_or_default(),
+        };
+        match recipe.save(recipe_path) {
+            Ok(_) => println!("info: saved recipe to: {}", recipe_path.display()),
+            Err(error) => {
+                eprintln!("error: failed to save recipe: {error}");
+                return 1;
+            }
+        }
+    }
+
+    // A single crop is saved to the destination, several crops or the
+    // cells of a grid are each saved next to it
+    let jobs: Vec<_> = match crop_regions.as_slice() {
+        regions if args.is_slicing => {
+            let grid_crop = match regions.first() {
+                Some(region) => region.image_crop.to_final().unwrap(),
+                None => default_crop,
+            };
+            // Cells in the canvas around the image have nothing to crop
+            let (image_width, image_height) =
+                image_transform.transformed_size(image_buf.width(), image_buf.height());
+            args.grid_slice
+                .cells(&grid_crop)
+                .into_iter()
+                .filter(|(_, _, cell)| cell.is_valid(image_width, image_height))
+                .map(|(row, column, cell)| {
+                    let name = format!("{}_{}", row + 1, column + 1);
+                    (cell, region_dest_path(&dest_path(&grid_crop), 0, &name))
+                })
+                .collect()
+        }
+        [] => vec![(default_crop.clone(), dest_path(&default_crop))],
+        [region] => {
+            let final_crop = region.image_crop.to_final().unwrap();
+            let region_path = dest_path(&final_crop);
+            vec![(final_crop, region_path)]
+        }
+        regions => regions
+            .iter()
+            .enumerate()
+            .map(|(index, region)| {
+                let final_crop = region.image_crop.to_final().unwrap();
+                let region_path =
+                    region_dest_path(&dest_path(&final_crop), index + 1, &region.name);
+                (final_crop, region_path)
+            })
+            .collect(),
+    };
+
+    // Sidecars are only written for a single crop, so re-exporting from one
+    // doesn't overwrite the other crops of the image
+    let is_single_crop = jobs.len() == 1 && !args.is_slicing;
+    let output_options = OutputOptions::new(config.output.jpeg_quality);
+    let mut exit_code = 0;
+    for (final_crop, dest_path) in jobs {
+        println!(
+            "info: cropping image with inputs: x: {}, y: {}, dimensions: {}x{}",
+            final_crop.crop_x, final_crop.crop_y, final_crop.width, final_crop.height,
+        );
+
+        let cropped_image_buf = match process_image(
+            &image_buf,
+            &image_transform,
+            &final_crop,
+            &args.canvas_fill,
+            &args.crop_shape,
+            None,
+            &args.output_resize,
+        ) {
+            Ok(cropped_image_buf) => cropped_image_buf,
+            Err(error) => {
+                eprintln!("error: failed to crop image: {:?}", error);
+                exit_code = 1;
+                continue;
+            }
+        };
+        if args.grid_slice.skip_empty && is_transparent(&cropped_image_buf) {
+            println!("info: skipped empty cell");
+            continue;
+        }
+
+        match save_image(cropped_image_buf, &dest_path, &output_options) {
+            Ok(_) => {
+                println!(
+                    "info: cropped and saved image successfully to: {}",
+                    dest_path.to_str().unwrap_or("[invalid_str]")
+                );
+                if config.output.sidecar && is_single_crop {
+                    let output = SidecarOutput {
+                        path: dest_path,
+                        shape: args.crop_shape,
+                        fill: args.canvas_fill,
+                        resize: args.output_resize,
+                        jpeg_quality: config.output.jpeg_quality,
+                    };
+                    match Sidecar::save(image_path, final_crop, image_transform, output) {
+                        Ok(path) => println!("info: saved sidecar to: {}", path.display()),
+                        Err(error) => eprintln!("warn: failed to save sidecar: {error}"),
+                    }
+                }
+            }
+            Err(error) => {
+                eprintln!("error: failed to save cropped image: {:?}", error);
+                exit_code = 1;
+            }
+        }
+    }
+
+    exit_code
+}
+
+/// Crops every image again with the settings in its sidecar, returns the
+/// exit code
+pub fn apply_sidecars(image_paths: &[PathBuf]) -> i32 {
+    let mut exit_code = 0;
+    for image_path in image_paths {
+        let sidecar = match Sidecar::load(image_path) {
+            Ok(Some(sidecar)) => sidecar,
+            Ok(None) => {
+                eprintln!("error: {} has no sidecar", image_path.display());
+                exit_code = 1;
+                continue;
+            }
+            Err(error) => {
+                eprintln!("error: {error}");
+                exit_code = 1;
+                continue;
+            }
+        };
+
+        let image_buf = match image::open(image_path) {
+            Ok(image) => image.into_rgba8(),
+            Err(error) => {
+                eprintln!("error: failed to open source image: {:?}", error);
+                exit_code = 1;
+                continue;
+            }
+        };
+
+        let output = sidecar.output;
+        let cropped_image_buf = match process_image(
+            &image_buf,
+            &sidecar.transform,
+            &sidecar.crop,
+            &output.fill,
+            &output.shape,
+            None,
+            &output.resize,
+        ) {
+            Ok(cropped_image_buf) => cropped_image_buf,
+            Err(error) => {
+                eprintln!("error: failed to crop image: {:?}", error);
+                exit_code = 1;
+                continue;
+            }
+        };
+        match save_image(
+            cropped_image_buf,
+            &output.path,
+            &OutputOptions::new(output.jpeg_quality),
+        ) {
+            Ok(_) => {
+                println!(
+                    "info: cropped {} again to: {}",
+                    image_path.display(),
+                    output.path.display()
+                );
+            }
+            Err(error) => {
+                eprintln!("error: failed to save cropped image: {:?}", error);
+                exit_code = 1;
+            }
+        }
+    }
+
+    exit_code
+}
+
+/// Crops every source image with the crop and settings of a recipe, returns
+/// the exit code
+pub fn apply_recipe(args: &Args, config: &Config, recipe_path: &Path) -> i32 {
+    let mut recipe = match Recipe::load(recipe_path) {
+        Ok(recipe) => recipe,
+        Err(error) => {
+            eprintln!("error: {error}");
+            return 1;
+        }
+    };
+    if let Some(size_mismatch) = args.size_mismatch {
+        recipe.on_size_mismatch = size_mismatch;
+    }
+    if let Some(anchor) = args.anchor {
+        recipe.anchor = anchor;
+    }
+
+    let output_options = OutputOptions::new(config.output.jpeg_quality);
+    let mut exit_code = 0;
+    for image_path in &args.image_paths {
+        let image_buf = match image::open(image_path) {
+            Ok(image) => image.into_rgba8(),
+            Err(error) => {
+                eprintln!(
+                    "error: failed to open source image {}: {:?}",
+                    image_path.display(),
+                    error
+                );
+                exit_code = 1;
+                continue;
+            }
+        };
+
+        let (width, height) = recipe
+            .transform
+            .transformed_size(image_buf.width(), image_buf.height());
+        let Some(final_crop) = recipe.crop_for(width, height) else {
+            println!(
+                "info: skipped {}, its size {}x{} differs from the recipe's {}x{}",
+                image_path.display(),
+                width,
+                height,
+                recipe.image_width,
+                recipe.image_height
+            );
+            continue;
+        };
+
+        let dest_path = config.output.template.expand(image_path, &final_crop);
+        let cropped_image_buf = match process_image(
+            &image_buf,
+            &recipe.transform,
+            &final_crop,
+            &recipe.fill,
+            &recipe.shape,
+            None,
+            &recipe.resize,
+        ) {
+            Ok(cropped_image_buf) => cropped_image_buf,
+            Err(error) => {
+                eprintln!("error: failed to crop image: {:?}", error);
+                exit_code = 1;
+                continue;
+            }
+        };
+        match save_image(cropped_image_buf, &dest_path, &output_options) {
+            Ok(_) => {
+                println!(
+                    "info: cropped and saved image successfully to: {}",
+                    dest_path.display()
+                );
+            }
+            Err(error) => {
+                eprintln!("error: failed to save cropped image: {:?}", error);
+                exit_code = 1;
+            }
+        }
+    }
+
+    exit_code
+}
@@ -1,32 +1,90 @@
-use gpui::Pixels;
+use gpui::{Pixels, Point};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
 
+/// A crop in whole image pixels, see [`snap_to_pixel`] for how positions
+/// between pixels are rounded
 #[derive(Clone, Debug)]
 pub enum ImageCrop {
     Cropped {
-        crop_x: Pixels,
-        crop_y: Pixels,
-        width: Pixels,
-        height: Pixels,
+        crop_x: i32,
+        crop_y: i32,
+        width: i32,
+        height: i32,
     },
     Uninitialized,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct InitializedImageCrop {
-    pub crop_x: Pixels,
-    pub crop_y: Pixels,
-    pub width: Pixels,
-    pub height: Pixels,
+    pub crop_x: i32,
+    pub crop_y: i32,
+    pub width: i32,
+    pub height: i32,
 }
 
-#[derive(Clone, Debug)]
+/// A crop in whole pixels, negative offsets and sizes past the image extend
+/// the canvas
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct FinalizedImageCrop {
-    pub crop_x: u32,
-    pub crop_y: u32,
+    pub crop_x: i32,
+    pub crop_y: i32,
     pub width: u32,
     pub height: u32,
 }
 
+/// One of several crops exported from the same image, each to its own file
+#[derive(Clone, Debug, PartialEq)]
+pub struct CropRegion {
+    pub name: String,
+    pub image_crop: ImageCrop,
+}
+
+/// A free-form crop outlined by vertices in image coordinates, saved as its
+/// bounding box with the pixels outside it transparent
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PolygonCrop {
+    pub points: Vec<Point<Pixels>>,
+    pub closed: bool,
+}
+
+/// The pixel boundary nearest to a position in image coordinates, halfway
+/// positions round up so that a span keeps its size wherever it's moved
+pub fn snap_to_pixel(position: f32) -> i32 {
+    (position + 0.5).floor() as i32
+}
+
+impl InitializedImageCrop {
+    /// The largest whole-pixel crop inside a left, top, right and bottom
+    /// edge, for regions it mustn't exceed like the straightened image
+    pub fn inside_edges(left: f32, top: f32, right: f32, bottom: f32) -> InitializedImageCrop {
+        let (left, top) = (left.ceil() as i32, top.ceil() as i32);
+        InitializedImageCrop {
+            crop_x: left,
+            crop_y: top,
+            width: (right.floor() as i32 - left).max(0),
+            height: (bottom.floor() as i32 - top).max(0),
+        }
+    }
+}
+
+impl FinalizedImageCrop {
+    /// Whether the crop isn't empty, covers at least a pixel of the image and
+    /// extends the canvas by no more than the image's size on each side
+    pub fn is_valid(&self, image_width: u32, image_height: u32) -> bool {
+        let is_valid_span = |position: i32, size: u32, extent: u32| {
+            let (end, extent) = (position as i64 + size as i64, extent as i64);
+            size >= 1
+                && position as i64 >= -extent
+                && (position as i64) < extent
+                && end >= 1
+                && end <= 2 * extent
+        };
+        is_valid_span(self.crop_x, self.width, image_width)
+            && is_valid_span(self.crop_y, self.height, image_height)
+    }
+}
+
 impl ImageCrop {
     pub fn to_final(&self) -> Option<FinalizedImageCrop> {
         match self {
@@ -36,10 +94,10 @@ impl ImageCrop {
                 width,
                 height,
             } => Some(FinalizedImageCrop {
-                crop_x: crop_x.into(),
-                crop_y: crop_y.into(),
-                width: width.into(),
-                height: height.into(),
+                crop_x: *crop_x,
+                crop_y: *crop_y,
+                width: u32::try_from(*width).unwrap_or(0),
+                height: u32::try_from(*height).unwrap_or(0),
             }),
             ImageCrop::Uninitialized => None,
         }
@@ -63,6 +121,190 @@ impl ImageCrop {
     }
 }
 
+impl From<InitializedImageCrop> for ImageCrop {
+    fn from(crop: InitializedImageCrop) -> Self {
+        ImageCrop::Cropped {
+            crop_x: crop.crop_x,
+            crop_y: crop.crop_y,
+            width: crop.width,
+            height: crop.height,
+        }
+    }
+}
+
+impl From<FinalizedImageCrop> for InitializedImageCrop {
+    fn from(crop: FinalizedImageCrop) -> Self {
+        InitializedImageCrop {
+            crop_x: crop.crop_x,
+            crop_y: crop.crop_y,
+            width: i32::try_from(crop.width).unwrap_or(i32::MAX),
+            height: i32::try_from(crop.height).unwrap_or(i32::MAX),
+        }
+    }
+}
+
+impl From<FinalizedImageCrop> for ImageCrop {
+    fn from(crop: FinalizedImageCrop) -> Self {
+        ImageCrop::Cropped {
+            crop_x: crop.crop_x,
+            crop_y: crop.crop_y,
+            width: i32::try_from(crop.width).unwrap_or(i32::MAX),
+            height: i32::try_from(crop.height).unwrap_or(i32::MAX),
+        }
+    }
+}
+
+impl CropRegion {
+    /// Parses a region in the form `[NAME=]GEOMETRY`
+    pub fn parse(region: &str) -> Option<CropRegion> {
+        let (name, geometry) = region.split_once('=').unwrap_or(("", region));
+
+        Some(CropRegion {
+            name: name.to_string(),
+            image_crop: FinalizedImageCrop::parse_geometry(geometry)?.into(),
+        })
+    }
+}
+
+impl FinalizedImageCrop {
+    /// Formats the crop as `WIDTHxHEIGHT+X+Y`, the form parsed by
+    /// [`FinalizedImageCrop::parse_geometry`]
+    pub fn geometry(&self) -> String {
+        format!(
+            "{}x{}{:+}{:+}",
+            self.width, self.height, self.crop_x, self.crop_y
+        )
+    }
+
+    /// Parses a crop geometry in the form `WIDTHxHEIGHT+X+Y`, where either
+    /// offset can be negative as in `WIDTHxHEIGHT-X-Y`
+    pub fn parse_geometry(geometry: &str) -> Option<FinalizedImageCrop> {
+        let offset_start = geometry.find(['+', '-'])?;
+        let (size, offset) = geometry.split_at(offset_start);
+        let (width, height) = size.split_once('x')?;
+        let crop_y_start = offset[1..].find(['+', '-'])? + 1;
+        let (crop_x, crop_y) = offset.split_at(crop_y_start);
+
+        let parse_offset = |offset: &str| offset.strip_prefix('+').unwrap_or(offset).parse().ok();
+        Some(FinalizedImageCrop {
+            crop_x: parse_offset(crop_x)?,
+            crop_y: parse_offset(crop_y)?,
+            width: width.parse().ok()?,
+            height: height.parse().ok()?,
+        })
+    }
+
+    /// Whether the crop lies entirely within an image of the given size
+    pub fn is_within(&self, image_width: u32, image_height: u32) -> bool {
+        self.crop_x >= 0
+            && self.crop_y >= 0
+            && self.crop_x as i64 + self.width as i64 <= image_width as i64
+            && self.crop_y as i64 + self.height as i64 <= image_height as i64
+    }
+}
+
+impl PolygonCrop {
+    /// Whether the polygon outlines a region that can be cropped
+    pub fn is_complete(&self) -> bool {
+        self.closed && self.points.len() >= 3 && self.area() > 0.
+    }
+
+    /// Area enclosed by the outline, zero when all points are on a line
+    fn area(&self) -> f32 {
+        let twice_area: f32 = self
+            .points
+            .iter()
+            .zip(self.points.iter().cycle().skip(1))
+            .map(|(start, end)| {
+                f32::from(start.x) * f32::from(end.y) - f32::from(end.x) * f32::from(start.y)
+            })
+            .sum();
+        twice_area.abs() / 2.
+    }
+
+    /// The smallest whole-pixel crop containing the polygon
+    pub fn bounding_box(&self) -> Option<InitializedImageCrop> {
+        let first = self.points.first()?;
+        let (mut left, mut top) = (f32::from(first.x), f32::from(first.y));
+        let (mut right, mut bottom) = (left, top);
+        for point in &self.points {
+            left = left.min(f32::from(point.x));
+            top = top.min(f32::from(point.y));
+            right = right.max(f32::from(point.x));
+            bottom = bottom.max(f32::from(point.y));
+        }
+
+        let (left, top) = (left.floor() as i32, top.floor() as i32);
+        Some(InitializedImageCrop {
+            crop_x: left,
+            crop_y: top,
+            width: right.ceil() as i32 - left,
+            height: bottom.ceil() as i32 - top,
+        })
+    }
+
+    /// Makes the pixels of a crop at `crop_x`, `crop_y` outside the polygon
+    /// transparent, with anti-aliased edges
+    pub fn apply_mask(&self, image_buf: &mut RgbaImage, crop_x: i32, crop_y: i32) {
+        // Coverage is exact horizontally and sampled on a few lines per row
+        const SUBROWS: u32 = 4;
+
+        let (width, height) = image_buf.dimensions();
+        let edges: Vec<((f32, f32), (f32, f32))> = self
+            .points
+            .iter()
+            .zip(self.points.iter().cycle().skip(1))
+            .map(|(start, end)| {
+                (
+                    (
+                        f32::from(start.x) - crop_x as f32,
+                        f32::from(start.y) - crop_y as f32,
+                    ),
+                    (
+                        f32::from(end.x) - crop_x as f32,
+                        f32::from(end.y) - crop_y as f32,
+                    ),
+                )
+            })
+            .collect();
+
+        let mut coverage = vec![0f32; width as usize];
+        let mut crossings = vec![];
+        for y in 0..height {
+            coverage.fill(0.);
+
+            for subrow in 0..SUBROWS {
+                let sample_y = y as f32 + (subrow as f32 + 0.5) / SUBROWS as f32;
+                crossings.clear();
+                crossings.extend(
+                    edges
+                        .iter()
+                        .filter(|((_, y0), (_, y1))| (*y0 <= sample_y) != (*y1 <= sample_y))
+                        .map(|((x0, y0), (x1, y1))| x0 + (sample_y - y0) / (y1 - y0) * (x1 - x0)),
+                );
+                crossings.sort_by(f32::total_cmp);
+
+                // Spans between pairs of crossings are inside the polygon
+                for span in crossings.chunks_exact(2) {
+                    let (start, end) = (span[0].max(0.), span[1].min(width as f32));
+                    if start >= end {
+                        continue;
+                    }
+                    for x in start.floor() as u32..(end.ceil() as u32).min(width) {
+                        let overlap = end.min(x as f32 + 1.) - start.max(x as f32);
+                        coverage[x as usize] += overlap.max(0.) / SUBROWS as f32;
+                    }
+                }
+            }
+
+            for (x, coverage) in coverage.iter().enumerate() {
+                let pixel = image_buf.get_pixel_mut(x as u32, y);
+                pixel.0[3] = (pixel.0[3] as f32 * coverage.min(1.)).round() as u8;
+            }
+        }
+    }
+}
+
 impl PartialEq for ImageCrop {
     fn eq(&self, rhs: &ImageCrop) -> bool {
         match (&self, rhs) {
@@ -91,3 +333,75 @@ impl PartialEq for ImageCrop {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gpui::{point, px};
+    use image::Rgba;
+
+    fn polygon(points: &[(f32, f32)]) -> PolygonCrop {
+        PolygonCrop {
+            points: points.iter().map(|(x, y)| point(px(*x), px(*y))).collect(),
+            closed: true,
+        }
+    }
+
+    fn masked(polygon: &PolygonCrop, width: u32, height: u32) -> RgbaImage {
+        let mut image_buf = RgbaImage::from_pixel(width, height, Rgba([255; 4]));
+        polygon.apply_mask(&mut image_buf, 0, 0);
+        image_buf
+    }
+
+    #[test]
+    fn bounding_box_covers_partial_pixels() {
+        let bounding_box = polygon(&[(2.5, 1.), (7.2, 3.5), (4., 6.9)])
+            .bounding_box()
+            .unwrap();
+        assert_eq!(
+            (
+                bounding_box.crop_x,
+                bounding_box.crop_y,
+                bounding_box.width,
+                bounding_box.height
+            ),
+            (2, 1, 6, 6)
+        );
+        assert!(polygon(&[]).bounding_box().is_none());
+    }
+
+    #[test]
+    fn triangle_mask_keeps_the_inside() {
+        // Right triangle with its hypotenuse on the diagonal of a 10x10 image
+        let image_buf = masked(&polygon(&[(0., 0.), (10., 0.), (0., 10.)]), 10, 10);
+        assert_eq!(image_buf.get_pixel(1, 1).0[3], 255);
+        assert_eq!(image_buf.get_pixel(8, 8).0[3], 0);
+        // Pixels the diagonal runs through are half covered
+        let edge_alpha = image_buf.get_pixel(4, 5).0[3];
+        assert!((96..=160).contains(&edge_alpha), "{edge_alpha}");
+        // The image is uncovered outside the crop's offset polygon
+        let mut offset = RgbaImage::from_pixel(10, 10, Rgba([255; 4]));
+        polygon(&[(0., 0.), (10., 0.), (0., 10.)]).apply_mask(&mut offset, 5, 0);
+        assert_eq!(offset.get_pixel(0, 1).0[3], 255);
+        assert_eq!(offset.get_pixel(5, 1).0[3], 0);
+    }
+
+    #[test]
+    fn degenerate_polygons_are_incomplete() {
+        let line = polygon(&[(0., 0.), (10., 10.)]);
+        let flat = polygon(&[(0., 0.), (5., 5.), (10., 10.)]);
+        let open = PolygonCrop {
+            closed: false,
+            ..polygon(&[(0., 0.), (10., 0.), (0., 10.)])
+        };
+        assert!(!line.is_complete());
+        assert!(!flat.is_complete());
+        assert!(!open.is_complete());
+        assert!(polygon(&[(0., 0.), (10., 0.), (0., 10.)]).is_complete());
+
+        for degenerate in [line, flat, polygon(&[(3., 3.)])] {
+            let image_buf = masked(&degenerate, 10, 10);
+            assert!(image_buf.pixels().all(|pixel| pixel.0[3] == 0));
+        }
+    }
+}
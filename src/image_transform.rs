@@ -0,0 +1,444 @@
+use crate::image_crop::InitializedImageCrop;
+use gpui::{Pixels, Point, point};
+use image::{Rgba, RgbaImage, imageops};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransformOp {
+    RotateClockwise,
+    RotateCounterClockwise,
+    FlipHorizontal,
+    FlipVertical,
+}
+
+/// The largest straighten angle in either direction, in degrees
+pub const MAX_STRAIGHTEN_ANGLE: f32 = 45.;
+
+/// A combination of rotations and flips, stored as a horizontal flip
+/// followed by a number of clockwise quarter turns, and finally a clockwise
+/// straighten angle in degrees
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ImageTransform {
+    pub quarter_turns: u8,
+    pub flipped: bool,
+    pub straighten: f32,
+}
+
+impl TransformOp {
+    /// Parses the value of `--rotate`, a clockwise angle in degrees
+    pub fn parse_rotation(degrees: &str) -> Option<Vec<TransformOp>> {
+        match degrees {
+            "0" | "360" => Some(vec![]),
+            "90" | "-270" => Some(vec![TransformOp::RotateClockwise]),
+            "180" | "-180" => Some(vec![TransformOp::RotateClockwise; 2]),
+            "270" | "-90" => Some(vec![TransformOp::RotateCounterClockwise]),
+            _ => None,
+        }
+    }
+
+    /// Parses the value of `--flip`, `h` for horizontal or `v` for vertical
+    pub fn parse_flip(direction: &str) -> Option<TransformOp> {
+        match direction {
+            "h" | "horizontal" => Some(TransformOp::FlipHorizontal),
+            "v" | "vertical" => Some(TransformOp::FlipVertical),
+            _ => None,
+        }
+    }
+
+    /// Maps a crop of an image with the given dimensions to the same region
+    /// of the image after this operation
+    pub fn apply_to_crop(
+        &self,
+        crop: &InitializedImageCrop,
+        image_width: i32,
+        image_height: i32,
+    ) -> InitializedImageCrop {
+        let (crop_x, crop_y, width, height) = (crop.crop_x, crop.crop_y, crop.width, crop.height);
+
+        match self {
+            TransformOp::RotateClockwise => InitializedImageCrop {
+                crop_x: image_height - crop_y - height,
+                crop_y: crop_x,
+                width: height,
+                height: width,
+            },
+            TransformOp::RotateCounterClockwise => InitializedImageCrop {
+                crop_x: crop_y,
+                crop_y: image_width - crop_x - width,
+                width: height,
+                height: width,
+            },
+            TransformOp::FlipHorizontal => InitializedImageCrop {
+                crop_x: image_width - crop_x - width,
+                ..crop.clone()
+            },
+            TransformOp::FlipVertical => InitializedImageCrop {
+                crop_y: image_height - crop_y - height,
+                ..crop.clone()
+            },
+        }
+    }
+
+    /// Maps a point of an image with the given dimensions to the same spot
+    /// of the image after this operation
+    pub fn apply_to_point(
+        &self,
+        position: Point<Pixels>,
+        image_width: Pixels,
+        image_height: Pixels,
+    ) -> Point<Pixels> {
+        match self {
+            TransformOp::RotateClockwise => point(image_height - position.y, position.x),
+            TransformOp::RotateCounterClockwise => point(position.y, image_width - position.x),
+            TransformOp::FlipHorizontal => point(image_width - position.x, position.y),
+            TransformOp::FlipVertical => point(position.x, image_height - position.y),
+        }
+    }
+}
+
+impl ImageTransform {
+    pub fn from_ops(ops: &[TransformOp], straighten: f32) -> Self {
+        ops.iter()
+            .fold(ImageTransform::default(), |transform, op| {
+                transform.then(*op)
+            })
+            .with_straighten(straighten)
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.quarter_turns == 0 && !self.flipped && self.straighten == 0.
+    }
+
+    pub fn with_straighten(&self, degrees: f32) -> Self {
+        ImageTransform {
+            straighten: degrees.clamp(-MAX_STRAIGHTEN_ANGLE, MAX_STRAIGHTEN_ANGLE),
+            ..*self
+        }
+    }
+
+    /// Returns the transform with `op` applied after it
+    pub fn then(&self, op: TransformOp) -> Self {
+        match op {
+            TransformOp::RotateClockwise => ImageTransform {
+                quarter_turns: (self.quarter_turns + 1) % 4,
+                ..*self
+            },
+            TransformOp::RotateCounterClockwise => ImageTransform {
+                quarter_turns: (self.quarter_turns + 3) % 4,
+                ..*self
+            },
+            // Flipping after a rotation is the same as flipping before the
+            // opposite rotation
+            TransformOp::FlipHorizontal => ImageTransform {
+                quarter_turns: (4 - self.quarter_turns) % 4,
+                flipped: !self.flipped,
+                straighten: -self.straighten,
+            },
+            // A vertical flip is a horizontal flip followed by a half turn
+            TransformOp::FlipVertical => ImageTransform {
+                quarter_turns: (6 - self.quarter_turns) % 4,
+                flipped: !self.flipped,
+                straighten: -self.straighten,
+            },
+        }
+    }
+
+    /// Dimensions of an image after this transform
+    pub fn transformed_size(&self, width: u32, height: u32) -> (u32, u32) {
+        if self.quarter_turns % 2 == 1 {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    pub fn apply(&self, image_buf: &RgbaImage) -> RgbaImage {
+        let flipped_buf;
+        let image_buf = if self.flipped {
+            flipped_buf = imageops::flip_horizontal(image_buf);
+            &flipped_buf
+        } else {
+            image_buf
+        };
+
+        let image_buf = match self.quarter_turns {
+            1 => imageops::rotate90(image_buf),
+            2 => imageops::rotate180(image_buf),
+            3 => imageops::rotate270(image_buf),
+            _ => image_buf.clone(),
+        };
+
+        if self.straighten == 0. {
+            image_buf
+        } else {
+            rotate_bilinear(&image_buf, self.straighten)
+        }
+    }
+
+    /// The largest axis-aligned region of the straightened image that has no
+    /// empty corners, as x, y, width and height
+    pub fn straightened_bounds(&self, image_width: f32, image_height: f32) -> (f32, f32, f32, f32) {
+        let (width, height) = max_inscribed_size(image_width, image_height, self.straighten);
+
+        (
+            (image_width - width) / 2.,
+            (image_height - height) / 2.,
+            width,
+            height,
+        )
+    }
+}
+
+/// Rotates the image clockwise around its center by an arbitrary angle,
+/// keeping its dimensions and leaving uncovered areas transparent
+fn rotate_bilinear(image_buf: &RgbaImage, degrees: f32) -> RgbaImage {
+    let (width, height) = image_buf.dimensions();
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let center_x = width as f32 / 2.;
+    let center_y = height as f32 / 2.;
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        // Map the center of each destination pixel back onto the source
+        let dx = x as f32 + 0.5 - center_x;
+        let dy = y as f32 + 0.5 - center_y;
+        let src_x = cos * dx + sin * dy + center_x - 0.5;
+        let src_y = -sin * dx + cos * dy + center_y - 0.5;
+
+        sample_bilinear(image_buf, src_x, src_y)
+    })
+}
+
+fn sample_bilinear(image_buf: &RgbaImage, x: f32, y: f32) -> Rgba<u8> {
+    let (width, height) = image_buf.dimensions();
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let fx = x - x0;
+    let fy = y - y0;
+
+    let pixel = |px: f32, py: f32| -> [f32; 4] {
+        if px < 0. || py < 0. || px >= width as f32 || py >= height as f32 {
+            return [0.; 4];
+        }
+        image_buf.get_pixel(px as u32, py as u32).0.map(f32::from)
+    };
+
+    let top_left = pixel(x0, y0);
+    let top_right = pixel(x0 + 1., y0);
+    let bottom_left = pixel(x0, y0 + 1.);
+    let bottom_right = pixel(x0 + 1., y0 + 1.);
+
+    // Interpolate with premultiplied alpha so transparent samples outside
+    // the image don't darken the edges
+    let mut sum = [0f32; 4];
+    for (sample, weight) in [
+        (top_left, (1. - fx) * (1. - fy)),
+        (top_right, fx * (1. - fy)),
+        (bottom_left, (1. - fx) * fy),
+        (bottom_right, fx * fy),
+    ] {
+        let alpha = sample[3] * weight;
+        sum[0] += sample[0] * alpha;
+        sum[1] += sample[1] * alpha;
+        sum[2] += sample[2] * alpha;
+        sum[3] += alpha;
+    }
+
+    if sum[3] <= 0. {
+        return Rgba([0; 4]);
+    }
+
+    Rgba([
+        (sum[0] / sum[3]).round() as u8,
+        (sum[1] / sum[3]).round() as u8,
+        (sum[2] / sum[3]).round() as u8,
+        sum[3].round() as u8,
+    ])
+}
+
+/// Size of the largest axis-aligned rectangle that fits inside a rectangle
+/// of the given size rotated by `degrees`
+fn max_inscribed_size(width: f32, height: f32, degrees: f32) -> (f32, f32) {
+    if width <= 0. || height <= 0. {
+        return (0., 0.);
+    }
+
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (sin, cos) = (sin.abs(), cos.abs());
+    let width_is_longer = width >= height;
+    let (side_long, side_short) = if width_is_longer {
+        (width, height)
+    } else {
+        (height, width)
+    };
+
+    // When the short side limits the rectangle two of its corners touch the
+    // long sides, otherwise all four corners touch the rotated rectangle
+    let (inscribed_width, inscribed_height) =
+        if side_short <= 2. * sin * cos * side_long || (sin - cos).abs() < 1e-6 {
+            let half_short = side_short / 2.;
+            if width_is_longer {
+                (half_short / sin, half_short / cos)
+            } else {
+                (half_short / cos, half_short / sin)
+            }
+        } else {
+            let cos_2a = cos * cos - sin * sin;
+            (
+                (width * cos - height * sin) / cos_2a,
+                (height * cos - width * sin) / cos_2a,
+            )
+        };
+
+    (
+        inscribed_width.min(width).floor(),
+        inscribed_height.min(height).floor(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gpui::px;
+    use image::imageops;
+
+    const OPS: [TransformOp; 4] = [
+        TransformOp::RotateClockwise,
+        TransformOp::RotateCounterClockwise,
+        TransformOp::FlipHorizontal,
+        TransformOp::FlipVertical,
+    ];
+
+    /// A 3x2 image where every pixel is different
+    fn test_image() -> RgbaImage {
+        RgbaImage::from_fn(3, 2, |x, y| Rgba([x as u8, y as u8, 0, 255]))
+    }
+
+    fn apply_op(op: TransformOp, image_buf: &RgbaImage) -> RgbaImage {
+        match op {
+            TransformOp::RotateClockwise => imageops::rotate90(image_buf),
+            TransformOp::RotateCounterClockwise => imageops::rotate270(image_buf),
+            TransformOp::FlipHorizontal => imageops::flip_horizontal(image_buf),
+            TransformOp::FlipVertical => imageops::flip_vertical(image_buf),
+        }
+    }
+
+    #[test]
+    fn combined_ops_match_applying_them_in_turn() {
+        let image_buf = test_image();
+        for first in OPS {
+            for second in OPS {
+                for third in OPS {
+                    let ops = [first, second, third];
+                    let transform = ImageTransform::from_ops(&ops, 0.);
+                    let expected = ops
+                        .iter()
+                        .fold(image_buf.clone(), |image_buf, op| apply_op(*op, &image_buf));
+
+                    assert_eq!(transform.apply(&image_buf), expected, "{ops:?}");
+                    assert_eq!(
+                        transform.transformed_size(3, 2),
+                        expected.dimensions(),
+                        "{ops:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn opposite_ops_cancel_out() {
+        let identity = ImageTransform::default();
+        let turned = identity.then(TransformOp::RotateClockwise);
+        assert!(!turned.is_identity());
+        assert!(
+            turned
+                .then(TransformOp::RotateCounterClockwise)
+                .is_identity()
+        );
+        assert!(
+            identity
+                .then(TransformOp::FlipVertical)
+                .then(TransformOp::FlipVertical)
+                .is_identity()
+        );
+        assert_eq!(
+            ImageTransform::from_ops(&[TransformOp::RotateClockwise; 4], 0.),
+            identity
+        );
+    }
+
+    #[test]
+    fn crops_and_points_follow_the_image() {
+        let image_buf = test_image();
+        let crop = InitializedImageCrop {
+            crop_x: 1,
+            crop_y: 0,
+            width: 2,
+            height: 1,
+        };
+        let cropped = imageops::crop_imm(&image_buf, 1, 0, 2, 1).to_image();
+
+        for op in OPS {
+            let transformed = apply_op(op, &image_buf);
+            let moved = op.apply_to_crop(&crop, 3, 2);
+            let moved_crop = imageops::crop_imm(
+                &transformed,
+                moved.crop_x as u32,
+                moved.crop_y as u32,
+                moved.width as u32,
+                moved.height as u32,
+            )
+            .to_image();
+            assert_eq!(moved_crop, apply_op(op, &cropped), "{op:?}");
+
+            // The corner of the image at the origin ends up at one of the
+            // transformed image's corners
+            let corner = op.apply_to_point(point(px(0.), px(0.)), px(3.), px(2.));
+            let (width, height) = transformed.dimensions();
+            let corner_pixel = transformed.get_pixel(
+                (f32::from(corner.x) as u32).min(width - 1),
+                (f32::from(corner.y) as u32).min(height - 1),
+            );
+            assert_eq!(corner_pixel, image_buf.get_pixel(0, 0), "{op:?}");
+        }
+    }
+
+    #[test]
+    fn rotation_and_flip_values_are_parsed() {
+        assert_eq!(
+            TransformOp::parse_rotation("-90"),
+            Some(vec![TransformOp::RotateCounterClockwise])
+        );
+        assert_eq!(TransformOp::parse_rotation("360"), Some(vec![]));
+        assert_eq!(TransformOp::parse_rotation("45"), None);
+        assert_eq!(
+            TransformOp::parse_flip("h"),
+            Some(TransformOp::FlipHorizontal)
+        );
+        assert_eq!(TransformOp::parse_flip("diagonal"), None);
+    }
+
+    #[test]
+    fn straightening_is_clamped_and_keeps_the_size() {
+        let transform = ImageTransform::default().with_straighten(60.);
+        assert_eq!(transform.straighten, MAX_STRAIGHTEN_ANGLE);
+        assert_eq!(
+            transform.then(TransformOp::FlipHorizontal).straighten,
+            -MAX_STRAIGHTEN_ANGLE
+        );
+
+        let image_buf = RgbaImage::from_pixel(20, 10, Rgba([255; 4]));
+        let straightened = ImageTransform::default()
+            .with_straighten(10.)
+            .apply(&image_buf);
+        assert_eq!(straightened.dimensions(), (20, 10));
+        assert_eq!(straightened.get_pixel(19, 0).0[3], 0);
+        assert_eq!(straightened.get_pixel(10, 5), &Rgba([255; 4]));
+
+        let (x, y, width, height) = ImageTransform::default()
+            .with_straighten(10.)
+            .straightened_bounds(20., 10.);
+        assert!(x > 0. && y > 0. && width < 20. && height < 10.);
+        assert_eq!((x * 2. + width, y * 2. + height), (20., 10.));
+    }
+}
@@ -1,14 +1,40 @@
 mod actions;
+mod anchor;
+mod args;
 mod basicrop;
 mod basicrop_state;
+mod canvas_fill;
+mod config;
+mod coordinate_mode;
 mod counter_input;
+mod crop_model;
+mod crop_pipeline;
+mod crop_shape;
+mod field_units;
+mod grid_slice;
+mod guides;
+mod headless;
 mod image_crop;
+mod image_transform;
 mod main_view;
 mod misc;
+mod output_format;
+mod output_size;
+mod output_template;
+mod photo_detect;
+mod preset;
+mod recent_files;
+mod recipe;
 mod selection_canvas;
+mod sidecar;
+mod viewport_background;
 
+use args::Args;
 use basicrop::Basicrop;
-use std::path::PathBuf;
+use config::{Config, ThemeConfig};
+use misc::{SourceImage, read_clipboard_image};
+use preset::find_preset;
+use std::rc::Rc;
 // use std::time::{SystemTime, UNIX_EPOCH};
 use gpui::{
     App, Application, Bounds, Size, TitlebarOptions, WindowBounds, WindowDecorations,
@@ -16,18 +42,86 @@ use gpui::{
 };
 use gpui_component::*;
 
-const USAGE: &str = r#"USAGE
-    basicrop [-h|--help]
+const USAGE: &str = r##"USAGE
+    basicrop [-h|--help] [--to-clipboard] [--preset NAME]
+             [--output-template TEMPLATE]
+             [TRANSFORM...] [RESIZE...] [source-image [output-image]]
+    basicrop [-h|--help] [--to-clipboard] [--preset NAME]
+             [TRANSFORM...] [RESIZE...] --from-clipboard [output-image]
+    basicrop --headless [--crop REGIONS...|--detect-photos|--preset NAME]
+             [--fill FILL] [--quality QUALITY] [--output-template TEMPLATE]
+             [SHAPE] [TRANSFORM...] [RESIZE...] [GRID...]
              source-image [output-image]
+    basicrop --apply-sidecar source-image...
+    basicrop apply-recipe [--on-size-mismatch MODE] [--anchor ANCHOR]
+             [--output-template TEMPLATE] recipe source-image...
+    basicrop [--guides GUIDES] [--quality QUALITY] --print-config
 
 DESCRIPTION
     basicrop is a basic program to crop images. It will open
     the source-image in a window that allows cropping by
     clicking and dragging anywhere on the image. After clicking
     the "Ok" button it will save the cropped image to
-    output-image if provided, or to the same path as
-    source-image with .cropped appended to the file name before
-    the file extension.
+    output-image if provided, or to the path built from the
+    output template, by default the same path as source-image
+    with .cropped appended to the file name before the file
+    extension.
+
+    Without a source-image it starts empty, images can then be
+    opened with the "Open" button, Ctrl+O, or by dropping them
+    onto the window.
+
+    The X, Y, Width and Height fields take expressions such as
+    50%, w/2, 1920-100 or +20, where w and h are the image's size
+    and a leading + adds to the current value. The button next to
+    each field switches it between pixels, percent of the image,
+    and millimeters or inches at the image's DPI, 72 when it has
+    none. Pressing Enter or leaving the field shows the value the
+    expression resolves to. The - and + buttons, the Up and Down
+    keys and the mouse wheel over a focused field step its value by
+    one of its unit. Clicking or scrolling with Shift steps by ten
+    and with Ctrl by a hundred, and dragging a field's label
+    sideways scrubs through its values.
+
+    The select before the fields switches them to the crop's Left,
+    Top, Right and Bottom edges, or to its Center X and Center Y
+    with Width and Height. The 3x3 grid after them chooses the
+    point of the crop that stays in place when its Width or Height
+    is edited, the top left corner by default.
+
+    The crop has to cover at least a pixel of the image and can
+    extend the canvas by up to the image's size on each side. A
+    value out of range is outlined in red while typing and clamped
+    to the nearest valid one on Enter, and "Ok" is disabled while
+    the crop is empty or out of range.
+
+    The "Polygon" tool in the toolbar crops to a free-form
+    outline instead: click to place points and double-click to
+    close it. The image is saved as the outline's bounding box
+    with the pixels outside it transparent.
+
+    Several regions can be exported at once by adding each crop
+    to the "Regions" panel, optionally with a name. "Ok" then
+    saves every region next to output-image, as
+    {stem}_{name}.{ext}, or {stem}_{index}.{ext} for regions
+    without a name. Clicking a region selects it for editing with
+    the crop. "Detect Photos" fills the panel with the photos
+    found in a scan of several photos, those differing from the
+    scanner background by more than the tolerance.
+
+    With --sidecar the settings of the crop are saved next to
+    source-image as {file name}.basicrop.json. Opening the image
+    again restores the crop, rotation, shape, fill, output size and
+    output-image from it, as long as the image hasn't changed, so
+    the crop can be adjusted and saved again from the original.
+    Options given on the command line take precedence over it.
+
+    "Save Recipe..." saves the crop with its rotation, shape, fill
+    and output size to a file, to crop a series of images the same
+    way with apply-recipe, see RECIPES.
+
+    The "Slice" tool cuts the crop into a grid for sprite sheets,
+    saving every cell as {stem}_{row}_{column}.{ext}.
 
     Supported image formats:
       AVIF  BMP      Farbfeld
@@ -35,41 +129,319 @@ DESCRIPTION
       JPEG  OpenEXR  PNG
       PNM   QOI      TGA
       TIFF  WebP
-"#;
+
+OPTIONS
+    --from-clipboard
+        Crop the image on the clipboard instead of a file. Ctrl+V
+        also replaces the current image with the clipboard's.
+
+    --to-clipboard
+        Copy the cropped image to the clipboard as a PNG instead of
+        saving it when clicking "Ok". On X11 and Wayland the window is
+        minimized and basicrop keeps running until something else is
+        copied, so the image can still be pasted. The "Copy" button and
+        Ctrl+C always copy the cropped image.
+
+    --headless
+        Crop and save source-image without opening a window.
+
+    --crop REGIONS
+        The regions to crop with --headless, as a comma separated
+        list of [NAME=]WIDTHxHEIGHT+X+Y in the rotated and flipped
+        image, and can be repeated. Defaults to the whole image.
+        Negative offsets, as in WIDTHxHEIGHT-X-Y, and sizes past the
+        image extend the canvas. A single region is saved to
+        output-image, several are named like in the window.
+
+    --detect-photos
+        Crop every photo found in a scan of several photos, as if
+        they were given with --crop.
+
+    --detect-tolerance PERCENT
+        How much a photo has to differ from the scanner background
+        to be found, 12% by default.
+
+    --preset NAME
+        Crop to the largest region of the preset's aspect ratio in
+        the center of the image, and resize it to the preset's size.
+        It can't be combined with --crop, --resize, --scale or --fit.
+        Presets can also be chosen next to the crop fields in the
+        window, a preset without a size resets the output size.
+
+    --output-template TEMPLATE
+        Where to save the cropped image without an output-image,
+        {dir}/{stem}.cropped{ext} by default. The variables are:
+
+          {dir}      directory of source-image
+          {stem}     file name of source-image without extension
+          {ext}      extension of source-image, with the dot
+          {x} {y}    offset of the crop
+          {w} {h}    size of the crop
+          {date}     today's date, as 2024-01-31
+          {counter}  lowest number from 1 that makes the path new
+
+        Missing directories are created.
+
+    --quality QUALITY
+        JPEG quality from 1 to 100, 75 by default.
+
+    --guides none|thirds|center
+        Lines drawn over the crop in the window, a rule of thirds
+        grid or a center cross. They can also be chosen in the
+        toolbar.
+
+    --sidecar
+        Save the settings of the crop next to source-image, for a
+        single crop.
+
+    --apply-sidecar
+        Crop every source-image again with the settings in its
+        sidecar, overwriting the output-image it was saved to.
+
+    --print-config
+        Print the configuration in effect, the config file with the
+        options above applied, and exit.
+
+    --fill FILL
+        How to fill the extended canvas: transparent (the default),
+        a #RRGGBB or #RRGGBBAA color, edge to repeat the edge pixels,
+        or mirror to reflect the image. In the window the canvas is
+        extended by dragging past the image or entering the size.
+
+SHAPE
+    --shape rectangle|ellipse
+        Crop to a rectangle (the default) or the ellipse that fits
+        in it.
+
+    --corner-radius PIXELS
+        Round the corners of a rectangular crop.
+
+    Pixels outside the shape are made transparent, formats without
+    transparency get black corners instead. The shape can also be
+    chosen in the toolbar.
+
+TRANSFORMS
+    --rotate DEGREES
+        Rotate the image clockwise by 90, 180 or 270 degrees.
+
+    --flip h|v
+        Flip the image horizontally or vertically.
+
+    --straighten DEGREES
+        Rotate the image clockwise by an angle between -45 and 45
+        degrees after the other transforms. The crop is limited to
+        the region without empty corners.
+
+    Transforms are applied in the order they're given, before
+    cropping. In the window they can also be changed with the
+    toolbar, Ctrl+R and Ctrl+Shift+R to rotate, and Ctrl+Shift+H
+    and Ctrl+Shift+V to flip. Straighten mode in the toolbar
+    levels the image along a line dragged over it.
+
+RESIZE
+    --resize WIDTHxHEIGHT
+        Resize the cropped image to an exact size. Either side can
+        be left out, as in 1200x or x630, to keep the aspect ratio.
+        The resized image has at most 268435456 pixels.
+
+    --scale PERCENT
+        Resize the cropped image by a percentage up to 1000%, as in
+        50%.
+
+    --fit WIDTHxHEIGHT
+        Shrink the cropped image to fit within a box, keeping the
+        aspect ratio. Smaller crops are left as they are.
+
+    --filter NAME
+        The resampling filter for resizing, one of nearest,
+        triangle, catmullrom, gaussian or lanczos3 (the default).
+
+    The output size can also be changed next to the crop fields in
+    the window.
+
+RECIPES
+    basicrop apply-recipe recipe source-image... crops every
+    source-image like the recipe, saving them to the output
+    template.
+
+    --save-recipe FILE
+        Save the crop of --headless as a recipe, as the "Save
+        Recipe..." button in the window does.
+
+    --on-size-mismatch skip|scale|anchor
+        What to do with images of another size than the one the
+        recipe was made on: leave them out (the default), scale
+        the crop along with the image keeping its aspect ratio, or
+        keep the size of the crop and its offset from the anchor.
+
+    --anchor ANCHOR
+        The point the crop keeps its offset from, one of top-left
+        (the default), top, top-right, left, center, right,
+        bottom-left, bottom or bottom-right.
+
+    Both can also be saved in the recipe, as on_size_mismatch and
+    anchor.
+
+GRID
+    --grid COLUMNSxROWS
+        Cut the crop into a grid of equally sized cells and save
+        each of them.
+
+    --grid-cell WIDTHxHEIGHT
+        Cut the crop into as many cells of a fixed size as fit.
+
+    --grid-margin PIXELS
+        Space between the edges of the crop and the outer cells.
+
+    --grid-spacing PIXELS
+        Space between neighbouring cells.
+
+    --skip-empty
+        Leave out cells that are fully transparent.
+
+    The grid covers the --crop region, or the whole image without
+    one.
+
+CONFIG
+    Defaults are read from basicrop/config.toml in the config
+    directory, ~/.config on Linux. Every setting is optional, and
+    --print-config shows all of them:
+
+        [output]
+        template = "{dir}/{stem}_{w}x{h}{ext}"
+        clipboard_path = "clipboard.cropped.png"
+        jpeg_quality = 75
+        sidecar = true
+
+        [window]
+        width = 500.0
+        height = 500.0
+        theme = "system"
+
+        [overlay]
+        selection = "#709ebe7f"
+        occlusion = "#000000c8"
+        guides = "thirds"
+        background = "gray"
+
+        [keys]
+        crop = "enter"
+        rotate_clockwise = "secondary-r"
+
+    Colors are #RRGGBB or #RRGGBBAA, the alpha setting their
+    opacity. The background behind the image, showing through
+    transparent pixels, is checkerboard, white, gray or black, and
+    can also be chosen in the toolbar. The theme is system, light
+    or dark. Keys are written like secondary-shift-r, secondary
+    being Cmd on macOS and Ctrl elsewhere.
+
+    Presets listed in the config file replace the built-in ones:
+
+        [[presets]]
+        name = "YouTube thumbnail"
+        size = "1280x720"
+
+        [[presets]]
+        name = "Avatar"
+        ratio = "1:1"
+        size = "512x"
+
+    A preset needs a ratio as WIDTH:HEIGHT, a size as WIDTHxHEIGHT,
+    or both. Either side of the size can be left out to keep the
+    aspect ratio. Options on the command line take precedence over
+    the config file.
+"##;
 
 fn main() {
-    let mut args: Vec<String> = std::env::args().skip(1).take(2).collect();
+    let mut args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("error: {error}\n");
+            eprint!("{USAGE}");
+            std::process::exit(1);
+        }
+    };
 
-    if args.is_empty() {
-        eprintln!("error: missing source-image\n");
+    if args.help {
         eprint!("{USAGE}");
-        std::process::exit(1);
+        std::process::exit(0);
     }
 
-    if args[0] == "-h" || args[0] == "--help" {
-        eprint!("{USAGE}");
+    let mut config = Config::load();
+    config.override_with(&args);
+    if args.print_config {
+        print!("{}", config.to_toml());
         std::process::exit(0);
     }
 
-    let image_path = PathBuf::from(args.remove(0));
-    let dest_image_path = args
-        .into_iter()
-        .next()
-        .map(PathBuf::from)
-        .unwrap_or_else(|| {
-            let mut orig_path = image_path.to_str().unwrap().to_owned();
-            let ext_index = orig_path.rfind('.').unwrap_or(orig_path.len());
-            orig_path.insert_str(ext_index, ".cropped");
-            PathBuf::from(orig_path)
-        });
+    let preset = match &args.preset {
+        Some(name) => match find_preset(&config.presets, name) {
+            Some(preset) => Some(preset.clone()),
+            None => {
+                let names: Vec<_> = config
+                    .presets
+                    .iter()
+                    .map(|preset| preset.name.as_str())
+                    .collect();
+                eprintln!(
+                    "error: unknown preset {name}, expected one of: {}",
+                    names.join(", ")
+                );
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    // Resize options can't be given along with a preset
+    if let Some(size) = preset.as_ref().and_then(|preset| preset.size) {
+        args.output_resize.size = size;
+        args.explicit.resize = true;
+    }
+
+    if let Some(recipe_path) = &args.recipe_path {
+        std::process::exit(headless::apply_recipe(&args, &config, recipe_path));
+    }
+
+    if args.apply_sidecar {
+        std::process::exit(headless::apply_sidecars(&args.image_paths));
+    }
+
+    if args.headless {
+        std::process::exit(headless::run(&args, &config, preset.as_ref()));
+    }
 
     let app = Application::new().with_assets(gpui_component_assets::Assets);
 
-    app.run(|cx: &mut App| {
+    let config = Rc::new(config);
+    app.run(move |cx: &mut App| {
         gpui_component::init(cx);
+        actions::bind_keys(cx, &config.keys);
+        match config.window.theme {
+            ThemeConfig::System => {}
+            ThemeConfig::Light => Theme::change(ThemeMode::Light, None, cx),
+            ThemeConfig::Dark => Theme::change(ThemeMode::Dark, None, cx),
+        }
         Theme::global_mut(cx).window_border = hsla(0., 0., 0., 0.6);
 
-        let bounds = Bounds::centered(None, size(px(500.), px(500.)), cx);
+        let image_source = if args.from_clipboard {
+            match read_clipboard_image(cx) {
+                Some(image_source) => Some(image_source),
+                None => {
+                    eprintln!("error: no image to crop on the clipboard");
+                    std::process::exit(1);
+                }
+            }
+        } else {
+            args.image_path.clone().map(SourceImage::Path)
+        };
+
+        let window_config = &config.window;
+        let bounds = Bounds::centered(
+            None,
+            size(px(window_config.width), px(window_config.height)),
+            cx,
+        );
         cx.open_window(
             WindowOptions {
                 window_bounds: Some(WindowBounds::Windowed(bounds)),
@@ -77,8 +449,8 @@ fn main() {
                 is_minimizable: true,
                 window_decorations: Some(WindowDecorations::Server),
                 window_min_size: Some(Size {
-                    width: px(750.),
-                    height: px(500.),
+                    width: px(window_config.min_width),
+                    height: px(window_config.min_height),
                 }),
                 titlebar: Some(TitlebarOptions {
                     title: Some("Basicrop".into()),
@@ -88,7 +460,9 @@ fn main() {
                 ..Default::default()
             },
             |window, cx| {
-                let view = cx.new(|cx| Basicrop::new(window, cx, image_path, dest_image_path));
+                let config = config.clone();
+                let view =
+                    cx.new(|cx| Basicrop::new(window, cx, image_source, &args, config, preset));
                 cx.new(|cx| Root::new(view, window, cx))
             },
         )
@@ -1,17 +1,48 @@
-use crate::actions::{CancelCrop, CropImage};
+use crate::actions::{
+    CancelCrop, CopyCrop, CropImage, FlipHorizontal, FlipVertical, OpenImage, PasteImage,
+    RotateClockwise, RotateCounterClockwise,
+};
+use crate::anchor::{ANCHORS, Anchor};
+use crate::basicrop_state::BasicropState;
+use crate::canvas_fill::{CANVAS_FILL_MODES, CanvasFill};
 use crate::counter_input::number_field;
-use crate::misc::LoadingImage;
+use crate::crop_model::{CropSource, DragEvent};
+use crate::crop_pipeline::{
+    encode_png, is_transparent, process_image, rgba_image, save_image, warn_mask_without_alpha,
+};
+use crate::crop_shape::{CROP_SHAPES, CropShape};
+use crate::grid_slice::GridCells;
+use crate::image_crop::{CropRegion, FinalizedImageCrop, InitializedImageCrop, PolygonCrop};
+use crate::image_transform::TransformOp;
+use crate::misc::{LoadingImage, SourceImage, read_clipboard_image, region_dest_path};
+use crate::output_format::{OutputFormat, OutputOptions, PNG_COMPRESSIONS};
+use crate::output_size::{OUTPUT_SIZE_MODES, OutputResize, OutputSize};
+use crate::photo_detect::detect_photos;
+use crate::preset::Preset;
+use crate::recipe::{Recipe, SizeMismatch};
 use crate::selection_canvas::selection_canvas;
-use crate::{basicrop_state::BasicropState, misc::CroppingMousePosition};
+use crate::sidecar::{Sidecar, SidecarOutput};
 use gpui::{
-    Context, Edges, IntoElement, KeyBinding, ObjectFit, Styled, div, img, prelude::*, px, rgb,
+    ClipboardItem, Context, Edges, Entity, ExternalPaths, IntoElement, PathPromptOptions, Styled,
+    div, prelude::*, px, rgb,
 };
-use gpui_component::IconName;
-use gpui_component::{StyledExt, button::Button};
+use gpui_component::checkbox::Checkbox;
+use gpui_component::input::{Input, InputState};
+use gpui_component::notification::Notification;
+use gpui_component::select::Select;
+use gpui_component::slider::Slider;
+use gpui_component::{Disableable, IconName, IndexPath, Selectable, Sizable, WindowExt};
+use gpui_component::{
+    StyledExt,
+    button::{Button, ButtonVariants},
+};
+use std::path::PathBuf;
+use std::time::Duration;
 
 pub fn render_main_view<T>(
     state: &mut BasicropState,
     image_asset: LoadingImage,
+    source_asset: LoadingImage,
     cx: &mut Context<T>,
 ) -> impl IntoElement {
     let fields = div()
@@ -27,13 +58,213 @@ pub fn render_main_view<T>(
             left: px(0.),
             right: px(0.),
         })
-        .child(number_field("X:", state.crop_x.read(cx).get_state()))
-        .child(number_field("Y:", state.crop_y.read(cx).get_state()))
-        .child(number_field("Width:", state.width.read(cx).get_state()))
-        .child(number_field("Height:", state.height.read(cx).get_state()));
+        .child(
+            div()
+                .w(px(100.))
+                .child(Select::new(&state.coordinate_mode_select).small()),
+        )
+        .children(
+            state
+                .coordinate_mode
+                .read(cx)
+                .field_labels()
+                .into_iter()
+                .zip([&state.crop_x, &state.crop_y, &state.width, &state.height])
+                .map(|(label, field)| number_field(label, field, cx)),
+        )
+        .child(anchor_selector(state, cx))
+        .child(output_size_fields(state, cx))
+        .child("Preset:")
+        .child(
+            div().w(px(160.)).child(
+                Select::new(&state.preset_select)
+                    .small()
+                    .placeholder("None"),
+            ),
+        );
 
-    cx.bind_keys([KeyBinding::new("enter", CropImage, None)]);
-    cx.bind_keys([KeyBinding::new("escape", CancelCrop, None)]);
+    let is_straightening = *state.is_straightening.read(cx);
+    let straighten_angle = state.image_transform.read(cx).straighten;
+    let is_drawing_polygon = *state.is_drawing_polygon.read(cx);
+    let is_slicing = *state.is_slicing.read(cx);
+    let crop_shape = *state.crop_shape.read(cx);
+    let is_crop_invalid = has_invalid_crop(cx, state);
+    let output_format = OutputFormat::from_path(state.dest_image_path.read(cx));
+    let shape = state.crop_shape_select.read(cx).selected_value().copied();
+    let is_rectangle = shape.is_none_or(|shape| shape == CROP_SHAPES[0]);
+    let fill_mode = state.canvas_fill_select.read(cx).selected_value().copied();
+    let is_color_fill = fill_mode == Some(CANVAS_FILL_MODES[1]);
+
+    let toolbar = div()
+        .flex()
+        .flex_row()
+        .w_full()
+        .gap_2()
+        .items_center()
+        .paddings(Edges {
+            top: px(8.),
+            bottom: px(8.),
+            left: px(16.),
+            right: px(16.),
+        })
+        .border_b(px(1.))
+        .border_color(rgb(0xd0d0d0))
+        .children(
+            [
+                (
+                    "rotate-ccw-btn",
+                    "Rotate Left",
+                    TransformOp::RotateCounterClockwise,
+                ),
+                (
+                    "rotate-cw-btn",
+                    "Rotate Right",
+                    TransformOp::RotateClockwise,
+                ),
+                ("flip-h-btn", "Flip Horizontal", TransformOp::FlipHorizontal),
+                ("flip-v-btn", "Flip Vertical", TransformOp::FlipVertical),
+            ]
+            .map(|(id, label, op)| {
+                Button::new(id)
+                    .label(label)
+                    .small()
+                    .border_1()
+                    .border_color(rgb(0xd0d0d0))
+                    .on_click({
+                        let image_asset = image_asset.clone();
+                        let state = state.clone();
+                        move |_, window, cx| {
+                            apply_transform_op(window, cx, &state, &image_asset, op)
+                        }
+                    })
+            }),
+        )
+        .child(
+            Button::new("straighten-btn")
+                .label("Straighten")
+                .small()
+                .border_1()
+                .border_color(rgb(0xd0d0d0))
+                .selected(is_straightening)
+                .on_click({
+                    let state = state.clone();
+                    move |_, _, cx| {
+                        let value = !*state.is_straightening.read(cx);
+                        state.is_straightening.write(cx, value);
+                        if value {
+                            state.is_drawing_polygon.write(cx, false);
+                            state.is_slicing.write(cx, false);
+                        }
+                    }
+                }),
+        )
+        .child(
+            Button::new("polygon-btn")
+                .label("Polygon")
+                .small()
+                .border_1()
+                .border_color(rgb(0xd0d0d0))
+                .selected(is_drawing_polygon)
+                .on_click({
+                    let state = state.clone();
+                    move |_, _, cx| {
+                        let value = !*state.is_drawing_polygon.read(cx);
+                        state.is_drawing_polygon.write(cx, value);
+                        state.polygon_crop.write(cx, PolygonCrop::default());
+                        if value {
+                            state.is_straightening.write(cx, false);
+                            state.is_slicing.write(cx, false);
+                        }
+                    }
+                }),
+        )
+        .child(
+            Button::new("slice-btn")
+                .label("Slice")
+                .small()
+                .border_1()
+                .border_color(rgb(0xd0d0d0))
+                .selected(is_slicing)
+                .on_click({
+                    let state = state.clone();
+                    move |_, _, cx| {
+                        let value = !*state.is_slicing.read(cx);
+                        state.is_slicing.write(cx, value);
+                        if value {
+                            state.is_straightening.write(cx, false);
+                            state.is_drawing_polygon.write(cx, false);
+                        }
+                    }
+                }),
+        )
+        .when(is_slicing, |this| this.child(grid_slice_fields(state, cx)))
+        .when(is_drawing_polygon, |this| {
+            this.child(
+                Button::new("clear-polygon-btn")
+                    .label("Clear")
+                    .small()
+                    .ghost()
+                    .on_click({
+                        let polygon_crop = state.polygon_crop.clone();
+                        move |_, _, cx| polygon_crop.write(cx, PolygonCrop::default())
+                    }),
+            )
+            .child(
+                div()
+                    .text_color(rgb(0x606060))
+                    .child("Click to add points, double-click to close, drag points to move"),
+            )
+        })
+        .when(is_straightening, |this| {
+            this.child(
+                div()
+                    .w(px(200.))
+                    .child(Slider::new(&state.straighten_slider)),
+            )
+            .child(format!("{straighten_angle:.1}°"))
+            .child(
+                div()
+                    .text_color(rgb(0x606060))
+                    .child("Drag along a line that should be level"),
+            )
+        })
+        .child(div().flex_1())
+        .child(
+            div()
+                .w(px(110.))
+                .child(Select::new(&state.guides_select).small()),
+        )
+        .child(
+            div()
+                .w(px(120.))
+                .child(Select::new(&state.background_select).small()),
+        )
+        .child("Shape:")
+        .child(
+            div()
+                .w(px(110.))
+                .child(Select::new(&state.crop_shape_select).small()),
+        )
+        .when(is_rectangle, |this| {
+            this.child("Radius:").child(
+                div()
+                    .w(px(60.))
+                    .child(Input::new(&state.corner_radius_input).small()),
+            )
+        })
+        .child("Fill:")
+        .child(
+            div()
+                .w(px(110.))
+                .child(Select::new(&state.canvas_fill_select).small()),
+        )
+        .when(is_color_fill, |this| {
+            this.child(
+                div()
+                    .w(px(90.))
+                    .child(Input::new(&state.canvas_fill_color_input).small()),
+            )
+        });
 
     // Main window root element
     div()
@@ -50,6 +281,15 @@ pub fn render_main_view<T>(
         .shadow_lg()
         .text_sm()
         .text_color(rgb(0x202020))
+        .drag_over::<ExternalPaths>(|style, _, _, _| style.bg(rgb(0xeef3f7)))
+        .on_drop({
+            let open_image_request = state.open_image_request.clone();
+            move |paths: &ExternalPaths, _, cx| {
+                if let Some(image_path) = paths.paths().first() {
+                    open_image_request.write(cx, Some(SourceImage::Path(image_path.clone())));
+                }
+            }
+        })
         .child(
             div()
                 .flex()
@@ -68,105 +308,146 @@ pub fn render_main_view<T>(
                 .border_color(rgb(0xd0d0d0))
                 .child(fields)
                 .child(
-                    div().flex().flex_row().justify_end().w(px(100.)).child(
-                        Button::new("reset-btn")
-                            .label("Reset")
-                            .border_1()
-                            .border_color(rgb(0xd0d0d0))
-                            .on_click({
-                                let fields = [
-                                    state.crop_x.clone(),
-                                    state.crop_y.clone(),
-                                    state.width.clone(),
-                                    state.height.clone(),
-                                ];
-                                let new_values = match &image_asset {
-                                    LoadingImage::Image(image) => {
-                                        let size = image.size(0);
-                                        [0u32, 0u32, size.width.into(), size.height.into()]
-                                    }
-                                    _ => [0u32, 0u32, 0u32, 0u32],
-                                };
-                                move |_, window, cx| {
-                                    for (field, value) in fields.iter().zip(new_values) {
-                                        field.update(cx, |input, cx| {
-                                            input.get_state().update(cx, |input, cx| {
-                                                input.set_value(value.to_string(), window, cx);
-                                            });
+                    div()
+                        .flex()
+                        .flex_row()
+                        .justify_end()
+                        .gap_2()
+                        .w(px(180.))
+                        .child(
+                            Button::new("open-btn")
+                                .icon(IconName::FolderOpen)
+                                .label("Open")
+                                .border_1()
+                                .border_color(rgb(0xd0d0d0))
+                                .on_click({
+                                    let open_image_request = state.open_image_request.clone();
+                                    move |_, _, cx| prompt_for_image(cx, &open_image_request)
+                                }),
+                        )
+                        .child(
+                            Button::new("reset-btn")
+                                .label("Reset")
+                                .border_1()
+                                .border_color(rgb(0xd0d0d0))
+                                .on_click({
+                                    let state = state.clone();
+                                    let crop = match &image_asset {
+                                        LoadingImage::Image(image) => {
+                                            let size = image.size(0);
+                                            let (crop_x, crop_y, width, height) =
+                                                state.image_transform.read(cx).straightened_bounds(
+                                                    u32::from(size.width) as f32,
+                                                    u32::from(size.height) as f32,
+                                                );
+                                            InitializedImageCrop::inside_edges(
+                                                crop_x,
+                                                crop_y,
+                                                crop_x + width,
+                                                crop_y + height,
+                                            )
+                                        }
+                                        _ => InitializedImageCrop::inside_edges(0., 0., 0., 0.),
+                                    };
+                                    move |_, _, cx| {
+                                        let crop = crop.clone();
+                                        state.image_crop.update(cx, |model, cx| {
+                                            model.set(crop, CropSource::Reset, cx)
                                         });
                                     }
-                                }
-                            }),
-                    ),
+                                }),
+                        ),
                 ),
         )
+        .child(toolbar)
         .child(
             div()
-                .id("image_viewport")
                 .flex()
-                .flex_col()
-                .justify_center()
-                .items_center()
-                .relative()
-                .h_full()
+                .flex_row()
+                .flex_1()
+                .min_h_0()
                 .w_full()
-                .map({
-                    let image_asset = image_asset.clone();
-                    move |this| match image_asset.clone() {
-                        LoadingImage::Image(image) => this.child(
-                            img(image)
-                                .absolute()
-                                .size_full()
-                                .object_fit(ObjectFit::Contain),
-                        ),
-                        LoadingImage::Failed => this.child("Failed to load image"),
-                        LoadingImage::Loading => this.child("Loading image..."),
-                    }
-                })
                 .child(
-                    selection_canvas(
-                        state.crop_x.clone(),
-                        state.crop_y.clone(),
-                        state.width.clone(),
-                        state.height.clone(),
-                        image_asset.clone(),
-                        state.image_crop.clone(),
-                        state.is_selecting.clone(),
-                        state.mouse_pos.clone(),
-                        state.mouse_initial_pos.clone(),
-                    )
-                    .absolute()
-                    .size_full(),
+                    div()
+                        .id("image_viewport")
+                        .flex()
+                        .flex_col()
+                        .justify_center()
+                        .items_center()
+                        .relative()
+                        .h_full()
+                        .w_full()
+                        .map({
+                            let image_asset = image_asset.clone();
+                            let open_image_request = state.open_image_request.clone();
+                            let recent_files = state.recent_files.clone();
+                            move |this| match image_asset.clone() {
+                                // Painted by the selection canvas along with the
+                                // extended canvas around it
+                                LoadingImage::Image(_) => this,
+                                LoadingImage::Failed => this.child("Failed to load image"),
+                                LoadingImage::Loading => this.child("Loading image..."),
+                                LoadingImage::Empty => this
+                                    .child(render_empty_state(&open_image_request, &recent_files)),
+                            }
+                        })
+                        .child(
+                            selection_canvas(
+                                image_asset.clone(),
+                                state.image_crop.clone(),
+                                state.is_straightening.clone(),
+                                state.crop_shape.clone(),
+                                state.polygon_crop.clone(),
+                                state.is_drawing_polygon.clone(),
+                                state.canvas_view.clone(),
+                                state.crop_regions.clone(),
+                                state.is_slicing.clone(),
+                                state.grid_slice.clone(),
+                                state.guides.clone(),
+                                state.background.clone(),
+                                state.config.overlay,
+                            )
+                            .absolute()
+                            .size_full(),
+                        )
+                        .on_mouse_down(gpui::MouseButton::Left, {
+                            let state = state.clone();
+                            move |evt, _, cx| {
+                                if *state.is_drawing_polygon.read(cx) {
+                                    add_polygon_vertex(cx, &state, evt.position, evt.click_count);
+                                }
+                            }
+                        })
+                        .on_drag((), {
+                            let image_crop = state.image_crop.clone();
+                            move |_, _, window, cx| {
+                                let position = window.mouse_position();
+                                image_crop.update(cx, |model, cx| model.start_drag(position, cx));
+                                cx.new(|_| gpui::Empty)
+                            }
+                        })
+                        .on_drag_move::<()>({
+                            let image_crop = state.image_crop.clone();
+                            move |evt, _, cx| {
+                                let position = evt.event.position;
+                                image_crop.update(cx, |model, cx| model.drag_to(position, cx));
+                            }
+                        })
+                        .on_drop::<()>({
+                            let image_crop = state.image_crop.clone();
+                            move |_, _, cx| {
+                                image_crop.update(cx, |model, cx| model.end_drag(true, cx));
+                            }
+                        })
+                        .on_mouse_up_out(gpui::MouseButton::Left, {
+                            let image_crop = state.image_crop.clone();
+                            move |_, _, cx| {
+                                image_crop.update(cx, |model, cx| model.end_drag(false, cx));
+                            }
+                        }),
                 )
-                .on_drag((), {
-                    let is_selecting = state.is_selecting.clone();
-                    let mouse_pos = state.mouse_pos.clone();
-                    let mouse_initial_pos = state.mouse_initial_pos.clone();
-                    move |_, point, _window, cx| {
-                        // `point` is relative to this element's bounds
-                        mouse_pos.write(cx, CroppingMousePosition::Initial(point));
-                        mouse_initial_pos.write(cx, point);
-                        is_selecting.write(cx, true);
-
-                        cx.new(|_| gpui::Empty)
-                    }
-                })
-                .on_drag_move::<()>({
-                    let mouse_pos = state.mouse_pos.clone();
-                    move |evt, _window, cx| {
-                        let position = evt.event.position;
-                        mouse_pos.write(cx, CroppingMousePosition::Moved(position));
-                    }
-                })
-                .on_drop::<()>({
-                    let is_selecting = state.is_selecting.clone();
-                    move |_, _window, cx| {
-                        is_selecting.write(cx, false);
-                    }
-                })
-                .on_mouse_up_out(gpui::MouseButton::Left, {
-                    let is_selecting = state.is_selecting.clone();
-                    move |_, _, cx| is_selecting.write(cx, false)
+                .when(image_asset.get_image().is_some(), |this| {
+                    this.child(render_regions_panel(state, &source_asset, cx))
                 }),
         )
         .child(
@@ -182,6 +463,87 @@ pub fn render_main_view<T>(
                     right: px(16.),
                 })
                 .gap_4()
+                .child(
+                    div()
+                        .flex()
+                        .flex_row()
+                        .flex_1()
+                        .gap_2()
+                        .items_center()
+                        .child("Save to:")
+                        .child(div().flex_1().child(Input::new(&state.dest_input).small()))
+                        .child(
+                            Button::new("save-as-btn")
+                                .label("Save As...")
+                                .border_1()
+                                .border_color(rgb(0xd0d0d0))
+                                .on_click({
+                                    let dest_image_path = state.dest_image_path.clone();
+                                    let dest_input = state.dest_input.clone();
+                                    move |_, window, cx| {
+                                        prompt_for_dest_path(
+                                            window,
+                                            cx,
+                                            &dest_image_path,
+                                            &dest_input,
+                                        )
+                                    }
+                                }),
+                        )
+                        .child(div().text_color(rgb(0x606060)).child(output_format.label()))
+                        .when(output_format.has_quality(), |this| {
+                            this.child("Quality:").child(
+                                div()
+                                    .w(px(52.))
+                                    .child(Input::new(&state.jpeg_quality_input).small()),
+                            )
+                        })
+                        .when(output_format.has_compression(), |this| {
+                            this.child("Compression:").child(
+                                div()
+                                    .w(px(110.))
+                                    .child(Select::new(&state.png_compression_select).small()),
+                            )
+                        })
+                        .when(
+                            output_format == OutputFormat::Known(image::ImageFormat::WebP),
+                            |this| this.child(div().text_color(rgb(0x606060)).child("Lossless")),
+                        )
+                        .when(
+                            crop_shape.has_mask() && !output_format.supports_alpha(),
+                            |this| {
+                                this.child(
+                                    div()
+                                        .text_color(rgb(0xb26a00))
+                                        .child("No transparency, corners will be black"),
+                                )
+                            },
+                        ),
+                )
+                .child(
+                    Button::new("save-recipe-btn")
+                        .label("Save Recipe...")
+                        .border_1()
+                        .border_color(rgb(0xd0d0d0))
+                        .on_click({
+                            let source_asset = source_asset.clone();
+                            let state = state.clone();
+                            move |_, window, cx| save_recipe(window, cx, &state, &source_asset)
+                        }),
+                )
+                .child(
+                    Button::new("copy-btn")
+                        .icon(IconName::Copy)
+                        .label("Copy")
+                        .border_1()
+                        .border_color(rgb(0xd0d0d0))
+                        .disabled(is_crop_invalid)
+                        .on_click({
+                            let source_asset = source_asset.clone();
+                            let state = state.clone();
+                            move |_, _, cx| copy_crop(cx, &state, &source_asset, false)
+                        }),
+                )
                 .child(
                     Button::new("cancel-btn")
                         .icon(IconName::Close)
@@ -199,17 +561,81 @@ pub fn render_main_view<T>(
                         .label("Ok")
                         .border_1()
                         .border_color(rgb(0xd0d0d0))
+                        .disabled(is_crop_invalid && state.crop_regions.read(cx).is_empty())
                         .on_click({
-                            let image_asset = image_asset.clone();
+                            let source_asset = source_asset.clone();
                             let state = state.clone();
-                            move |_, _, cx| finalize_crop(cx, &state, &image_asset)
+                            move |_, window, cx| finalize_crop(window, cx, &state, &source_asset)
                         }),
                 ),
         )
+        .on_action({
+            let source_asset = source_asset.clone();
+            let state = state.clone();
+            move |_: &CropImage, window, cx| finalize_crop(window, cx, &state, &source_asset)
+        })
+        .on_action({
+            let open_image_request = state.open_image_request.clone();
+            move |_: &OpenImage, _, cx| prompt_for_image(cx, &open_image_request)
+        })
+        .on_action({
+            let open_image_request = state.open_image_request.clone();
+            move |_: &PasteImage, _, cx| {
+                if let Some(image_source) = read_clipboard_image(cx) {
+                    open_image_request.write(cx, Some(image_source));
+                }
+            }
+        })
+        .on_action({
+            let source_asset = source_asset.clone();
+            let state = state.clone();
+            move |_: &CopyCrop, _, cx| copy_crop(cx, &state, &source_asset, false)
+        })
+        .on_action({
+            let image_asset = image_asset.clone();
+            let state = state.clone();
+            move |_: &RotateClockwise, window, cx| {
+                apply_transform_op(
+                    window,
+                    cx,
+                    &state,
+                    &image_asset,
+                    TransformOp::RotateClockwise,
+                )
+            }
+        })
+        .on_action({
+            let image_asset = image_asset.clone();
+            let state = state.clone();
+            move |_: &RotateCounterClockwise, window, cx| {
+                apply_transform_op(
+                    window,
+                    cx,
+                    &state,
+                    &image_asset,
+                    TransformOp::RotateCounterClockwise,
+                )
+            }
+        })
+        .on_action({
+            let image_asset = image_asset.clone();
+            let state = state.clone();
+            move |_: &FlipHorizontal, window, cx| {
+                apply_transform_op(
+                    window,
+                    cx,
+                    &state,
+                    &image_asset,
+                    TransformOp::FlipHorizontal,
+                )
+            }
+        })
         .on_action({
             let image_asset = image_asset.clone();
             let state = state.clone();
-            move |_: &CropImage, _, cx| finalize_crop(cx, &state, &image_asset)
+            move |_: &FlipVertical, window, cx| {
+                apply_transform_op(window, cx, &state, &image_asset, TransformOp::FlipVertical)
+            }
         })
         .on_action(|_: &CancelCrop, _, cx| {
             println!("info: image crop canceled via Escape");
@@ -217,20 +643,792 @@ pub fn render_main_view<T>(
         })
 }
 
-fn finalize_crop(cx: &mut gpui::App, state: &BasicropState, image_asset: &LoadingImage) {
+/// A 3x3 grid for choosing the point kept in place when the width or
+/// height is edited
+fn anchor_selector<T>(state: &BasicropState, cx: &mut Context<T>) -> impl IntoElement {
+    let selected_anchor = *state.anchor.read(cx);
+    div()
+        .flex()
+        .flex_col()
+        .gap(px(2.))
+        .children(ANCHORS.chunks(3).map(|row| {
+            div()
+                .flex()
+                .flex_row()
+                .gap(px(2.))
+                .children(row.iter().map(|anchor| {
+                    div()
+                        .id(anchor.name())
+                        .size(px(7.))
+                        .border_1()
+                        .border_color(rgb(0x808080))
+                        .cursor_pointer()
+                        .when(*anchor == selected_anchor, |cell| cell.bg(rgb(0x404040)))
+                        .on_click({
+                            let state = state.clone();
+                            let anchor = *anchor;
+                            move |_, _, cx| state.anchor.write(cx, anchor)
+                        })
+                }))
+        }))
+}
+
+/// Mode, size and filter controls for resizing the cropped image
+fn output_size_fields<T>(state: &BasicropState, cx: &mut Context<T>) -> impl IntoElement {
+    let output_resize = *state.output_resize.read(cx);
+    let crop_size = state
+        .image_crop
+        .read(cx)
+        .crop()
+        .to_final()
+        .map(|crop| output_resize.size.target_size(crop.width, crop.height));
+
+    div()
+        .flex()
+        .flex_row()
+        .gap_2()
+        .items_center()
+        .child("Output:")
+        .child(
+            div()
+                .w(px(120.))
+                .child(Select::new(&state.output_mode_select).small()),
+        )
+        .map(|this| match output_resize.size {
+            OutputSize::Original => this,
+            OutputSize::Percent(_) => this
+                .child(
+                    div()
+                        .w(px(60.))
+                        .child(Input::new(&state.output_percent_input).small()),
+                )
+                .child("%"),
+            OutputSize::Exact { .. } | OutputSize::Fit { .. } => this
+                .child(
+                    div()
+                        .w(px(60.))
+                        .child(Input::new(&state.output_width_input).small()),
+                )
+                .child("×")
+                .child(
+                    div()
+                        .w(px(60.))
+                        .child(Input::new(&state.output_height_input).small()),
+                ),
+        })
+        .when(output_resize.size != OutputSize::Original, |this| {
+            this.child(
+                div()
+                    .w(px(110.))
+                    .child(Select::new(&state.output_filter_select).small()),
+            )
+            .when_some(crop_size, |this, (width, height)| {
+                this.child(
+                    div()
+                        .text_color(rgb(0x606060))
+                        .child(format!("{width}×{height}")),
+                )
+            })
+        })
+}
+
+/// Mode, cell and spacing controls for slicing the crop into a grid
+fn grid_slice_fields<T>(state: &BasicropState, cx: &mut Context<T>) -> impl IntoElement {
+    let grid_slice = *state.grid_slice.read(cx);
+    let cell_count = state
+        .image_crop
+        .read(cx)
+        .crop()
+        .to_final()
+        .map(|crop| {
+            let (columns, rows, _, _) = grid_slice.dimensions(&crop);
+            columns as u64 * rows as u64
+        })
+        .unwrap_or_default();
+
+    div()
+        .flex()
+        .flex_row()
+        .gap_2()
+        .items_center()
+        .child(
+            div()
+                .w(px(130.))
+                .child(Select::new(&state.grid_mode_select).small()),
+        )
+        .child(
+            div()
+                .w(px(50.))
+                .child(Input::new(&state.grid_horizontal_input).small()),
+        )
+        .child("×")
+        .child(
+            div()
+                .w(px(50.))
+                .child(Input::new(&state.grid_vertical_input).small()),
+        )
+        .child("Margin:")
+        .child(
+            div()
+                .w(px(45.))
+                .child(Input::new(&state.grid_margin_input).small()),
+        )
+        .child("Spacing:")
+        .child(
+            div()
+                .w(px(45.))
+                .child(Input::new(&state.grid_spacing_input).small()),
+        )
+        .child(
+            Checkbox::new("skip-empty-checkbox")
+                .label("Skip empty")
+                .checked(grid_slice.skip_empty)
+                .on_click({
+                    let grid_slice_entity = state.grid_slice.clone();
+                    move |checked, _, cx| {
+                        let mut grid_slice = *grid_slice_entity.read(cx);
+                        grid_slice.skip_empty = *checked;
+                        grid_slice_entity.write(cx, grid_slice);
+                    }
+                }),
+        )
+        .child(
+            div()
+                .text_color(rgb(0x606060))
+                .child(format!("{cell_count} cells")),
+        )
+}
+
+/// Rebuilds the grid from the grid controls, invalid fields keep their
+/// previous value
+pub fn update_grid_slice(cx: &mut gpui::App, state: &BasicropState) {
+    let mode = state
+        .grid_mode_select
+        .read(cx)
+        .selected_value()
+        .copied()
+        .unwrap_or_default();
+    let cells = GridCells::from_fields(
+        mode,
+        &state.grid_horizontal_input.read(cx).value(),
+        &state.grid_vertical_input.read(cx).value(),
+    );
+    let margin = state.grid_margin_input.read(cx).value().trim().parse().ok();
+    let spacing = state
+        .grid_spacing_input
+        .read(cx)
+        .value()
+        .trim()
+        .parse()
+        .ok();
+
+    let mut grid_slice = *state.grid_slice.read(cx);
+    grid_slice.cells = cells.unwrap_or(grid_slice.cells);
+    grid_slice.margin = margin.unwrap_or(grid_slice.margin);
+    grid_slice.spacing = spacing.unwrap_or(grid_slice.spacing);
+    state.grid_slice.write(cx, grid_slice);
+}
+
+/// Centers the largest crop with the preset's aspect ratio on the image, and
+/// resizes the output to the preset's size
+pub fn apply_preset(
+    window: &mut gpui::Window,
+    cx: &mut gpui::App,
+    state: &BasicropState,
+    preset: Preset,
+) {
+    if let Some(image) = state.image_crop_initial.read(cx).to_initialized() {
+        let (bounds_x, bounds_y, bounds_width, bounds_height) = state
+            .image_transform
+            .read(cx)
+            .straightened_bounds(image.width as f32, image.height as f32);
+        let (crop_x, crop_y, width, height) =
+            preset.crop(bounds_x, bounds_y, bounds_width, bounds_height);
+        let crop =
+            InitializedImageCrop::inside_edges(crop_x, crop_y, crop_x + width, crop_y + height);
+        state
+            .image_crop
+            .update(cx, |model, cx| model.set(crop, CropSource::Preset, cx));
+    }
+
+    // A preset without a size keeps the crop's size
+    let size = preset.size.unwrap_or_default();
+    let mode = size.mode();
+    let selected_index = OUTPUT_SIZE_MODES.iter().position(|label| *label == mode);
+    state.output_mode_select.update(cx, |select, cx| {
+        select.set_selected_index(selected_index.map(IndexPath::new), window, cx);
+    });
+    let (width, height, percent) = size.field_values();
+    let inputs = [
+        (&state.output_width_input, width),
+        (&state.output_height_input, height),
+        (&state.output_percent_input, percent),
+    ];
+    for (input, value) in inputs {
+        input.update(cx, |input, cx| input.set_value(value, window, cx));
+    }
+    update_output_resize(cx, state);
+
+    println!("info: applied preset {}", preset.name);
+    state.preset.write(cx, Some(preset));
+}
+
+/// Rebuilds the crop shape from the shape and corner radius controls
+pub fn update_crop_shape(cx: &mut gpui::App, state: &BasicropState) {
+    let shape = state
+        .crop_shape_select
+        .read(cx)
+        .selected_value()
+        .copied()
+        .unwrap_or_default();
+    let corner_radius = state.corner_radius_input.read(cx).value();
+
+    let crop_shape = CropShape::from_fields(shape, &corner_radius);
+    state.crop_shape.write(cx, crop_shape);
+}
+
+/// Rebuilds the canvas fill from the mode and color controls
+pub fn update_canvas_fill(cx: &mut gpui::App, state: &BasicropState) {
+    let mode = state
+        .canvas_fill_select
+        .read(cx)
+        .selected_value()
+        .copied()
+        .unwrap_or_default();
+    let color = state.canvas_fill_color_input.read(cx).value();
+
+    let canvas_fill = CanvasFill::from_fields(mode, &color);
+    state.canvas_fill.write(cx, canvas_fill);
+}
+
+/// Rebuilds the output resize from the mode, size and filter controls
+pub fn update_output_resize(cx: &mut gpui::App, state: &BasicropState) {
+    let mode = state
+        .output_mode_select
+        .read(cx)
+        .selected_value()
+        .copied()
+        .unwrap_or_default();
+    let filter = state
+        .output_filter_select
+        .read(cx)
+        .selected_value()
+        .and_then(|name| OutputResize::parse_filter(name));
+    let size = OutputSize::from_fields(
+        mode,
+        &state.output_width_input.read(cx).value(),
+        &state.output_height_input.read(cx).value(),
+        &state.output_percent_input.read(cx).value(),
+    );
+
+    let mut output_resize = *state.output_resize.read(cx);
+    output_resize.size = size;
+    if let Some(filter) = filter {
+        output_resize.filter = filter;
+    }
+    state.output_resize.write(cx, output_resize);
+}
+
+pub fn update_output_options(cx: &mut gpui::App, state: &BasicropState) {
+    let mut output_options = *state.output_options.read(cx);
+    if let Some(jpeg_quality) =
+        OutputOptions::parse_jpeg_quality(&state.jpeg_quality_input.read(cx).value())
+    {
+        output_options.jpeg_quality = jpeg_quality;
+    }
+    if let Some(png_compression) = state
+        .png_compression_select
+        .read(cx)
+        .selected_value()
+        .and_then(|label| OutputOptions::parse_png_compression(label))
+    {
+        output_options.png_compression = png_compression;
+    }
+    state.output_options.write(cx, output_options);
+}
+
+/// Resets the options that don't apply to a new destination format and shows
+/// the options in effect
+pub fn reset_output_options(
+    window: &mut gpui::Window,
+    cx: &mut gpui::App,
+    state: &BasicropState,
+    output_format: OutputFormat,
+) {
+    let defaults = OutputOptions::new(state.config.output.jpeg_quality);
+    let output_options = state
+        .output_options
+        .read(cx)
+        .for_format(output_format, &defaults);
+    state.output_options.write(cx, output_options);
+
+    state.jpeg_quality_input.update(cx, |input, cx| {
+        input.set_value(output_options.jpeg_quality.to_string(), window, cx);
+    });
+    let compression = output_options.png_compression_label();
+    let selected_index = PNG_COMPRESSIONS
+        .iter()
+        .position(|(label, _)| *label == compression);
+    state.png_compression_select.update(cx, |select, cx| {
+        select.set_selected_index(selected_index.map(IndexPath::new), window, cx);
+    });
+}
+
+/// Side panel listing the regions exported together, each to its own file
+fn render_regions_panel<T>(
+    state: &BasicropState,
+    source_asset: &LoadingImage,
+    cx: &mut Context<T>,
+) -> impl IntoElement {
+    let crop_regions = state.crop_regions.read(cx).clone();
+    let selected_region = *state.selected_region.read(cx);
+    let detect_tolerance = state.detect_tolerance_slider.read(cx).value().start();
+
+    div()
+        .flex()
+        .flex_col()
+        .flex_none()
+        .w(px(220.))
+        .h_full()
+        .gap_2()
+        .p_3()
+        .border_l(px(1.))
+        .border_color(rgb(0xd0d0d0))
+        .child("Regions")
+        .child(
+            div()
+                .flex()
+                .flex_row()
+                .gap_2()
+                .items_center()
+                .child(
+                    div()
+                        .flex_1()
+                        .child(Input::new(&state.region_name_input).small()),
+                )
+                .child(
+                    Button::new("add-region-btn")
+                        .icon(IconName::Plus)
+                        .small()
+                        .border_1()
+                        .border_color(rgb(0xd0d0d0))
+                        .on_click({
+                            let state = state.clone();
+                            move |_, window, cx| add_crop_region(window, cx, &state)
+                        }),
+                ),
+        )
+        .child(
+            div()
+                .flex()
+                .flex_row()
+                .gap_2()
+                .items_center()
+                .child(
+                    Button::new("detect-photos-btn")
+                        .label("Detect Photos")
+                        .small()
+                        .border_1()
+                        .border_color(rgb(0xd0d0d0))
+                        .on_click({
+                            let state = state.clone();
+                            let source_asset = source_asset.clone();
+                            move |_, window, cx| {
+                                detect_crop_regions(window, cx, &state, &source_asset)
+                            }
+                        }),
+                )
+                .child(
+                    div()
+                        .text_color(rgb(0x606060))
+                        .child(format!("Tolerance {detect_tolerance:.0}%")),
+                ),
+        )
+        .child(Slider::new(&state.detect_tolerance_slider))
+        .children(crop_regions.iter().enumerate().map(|(index, region)| {
+            let label = match region.name.as_str() {
+                "" => format!("#{}", index + 1),
+                name => name.to_string(),
+            };
+            let geometry = region
+                .image_crop
+                .to_final()
+                .map(|crop| crop.geometry())
+                .unwrap_or_default();
+
+            div()
+                .flex()
+                .flex_row()
+                .items_center()
+                .child(
+                    Button::new(("region-btn", index))
+                        .ghost()
+                        .small()
+                        .flex_1()
+                        .label(format!("{label}  {geometry}"))
+                        .selected(selected_region == Some(index))
+                        .on_click({
+                            let state = state.clone();
+                            let region = region.clone();
+                            move |_, _, cx| {
+                                // The selected region follows edits to the
+                                // crop until it's clicked again
+                                if *state.selected_region.read(cx) == Some(index) {
+                                    state.selected_region.write(cx, None);
+                                    return;
+                                }
+                                if let Some(crop) = region.image_crop.to_initialized() {
+                                    state.image_crop.update(cx, |model, cx| {
+                                        model.set(crop, CropSource::Region, cx)
+                                    });
+                                }
+                                state.selected_region.write(cx, Some(index));
+                            }
+                        }),
+                )
+                .child(
+                    Button::new(("remove-region-btn", index))
+                        .icon(IconName::Close)
+                        .ghost()
+                        .xsmall()
+                        .on_click({
+                            let crop_regions = state.crop_regions.clone();
+                            let selected_region = state.selected_region.clone();
+                            move |_, _, cx| {
+                                let mut regions = crop_regions.read(cx).clone();
+                                if index < regions.len() {
+                                    regions.remove(index);
+                                }
+                                crop_regions.write(cx, regions);
+                                selected_region.write(cx, None);
+                            }
+                        }),
+                )
+        }))
+        .child(
+            div()
+                .text_color(rgb(0x606060))
+                .child(match (crop_regions.len(), selected_region) {
+                    (0, _) => "Add the current crop or detect photos to export several regions",
+                    (_, Some(_)) => "Changing the crop edits the selected region",
+                    _ => "Click a region to edit it, Ok exports each region",
+                }),
+        )
+}
+
+/// Adds the current crop as a region with the name typed in the panel
+fn add_crop_region(window: &mut gpui::Window, cx: &mut gpui::App, state: &BasicropState) {
+    let image_crop = state.image_crop.read(cx).crop().clone();
+    if image_crop.to_final().is_none() {
+        return;
+    }
+
+    let name = state.region_name_input.read(cx).value().trim().to_string();
+    let mut crop_regions = state.crop_regions.read(cx).clone();
+    crop_regions.push(CropRegion { name, image_crop });
+    state.crop_regions.write(cx, crop_regions);
+    state.selected_region.write(cx, None);
+    state.region_name_input.update(cx, |input, cx| {
+        input.set_value("", window, cx);
+    });
+}
+
+/// Replaces the regions with the photos found in the image, for scans of
+/// several photos at once
+fn detect_crop_regions(
+    window: &mut gpui::Window,
+    cx: &mut gpui::App,
+    state: &BasicropState,
+    source_asset: &LoadingImage,
+) {
+    let LoadingImage::Image(image) = source_asset else {
+        return;
+    };
+    let Some(image_buf) = rgba_image(image) else {
+        eprintln!("error: can't retrieve image buffer for detecting photos");
+        return;
+    };
+
+    // The regions are in the rotated and flipped image, like the crop
+    let image_transform = *state.image_transform.read(cx);
+    let tolerance = state.detect_tolerance_slider.read(cx).value().start();
+    let crop_regions = state.crop_regions.clone();
+    let selected_region = state.selected_region.clone();
+    let window_handle = window.window_handle();
+    cx.spawn(async move |cx: &mut gpui::AsyncApp| {
+        let photos = cx
+            .background_spawn(async move {
+                detect_photos(&image_transform.apply(&image_buf), tolerance)
+            })
+            .await;
+        println!("info: detected {} photos", photos.len());
+
+        // The regions already added are kept when nothing is found
+        if photos.is_empty() {
+            let message = "No photos found, try a different tolerance";
+            let _ = window_handle.update(cx, |_, window, cx| {
+                window.push_notification(Notification::warning(message), cx);
+            });
+            return;
+        }
+
+        let regions = photos
+            .into_iter()
+            .map(|photo| CropRegion {
+                name: String::new(),
+                image_crop: photo.into(),
+            })
+            .collect();
+        let _ = crop_regions.write(cx, regions);
+        let _ = selected_region.write(cx, None);
+    })
+    .detach();
+}
+
+fn render_empty_state(
+    open_image_request: &Entity<Option<SourceImage>>,
+    recent_files: &[PathBuf],
+) -> impl IntoElement {
+    div()
+        .flex()
+        .flex_col()
+        .items_center()
+        .gap_3()
+        .child("No image open, drop an image here or open one")
+        .child(
+            Button::new("empty-open-btn")
+                .icon(IconName::FolderOpen)
+                .label("Open Image...")
+                .border_1()
+                .border_color(rgb(0xd0d0d0))
+                .on_click({
+                    let open_image_request = open_image_request.clone();
+                    move |_, _, cx| prompt_for_image(cx, &open_image_request)
+                }),
+        )
+        .when(!recent_files.is_empty(), |this| {
+            this.child(div().pt_4().text_color(rgb(0x606060)).child("Recent files"))
+                .children(recent_files.iter().enumerate().map(|(index, recent_file)| {
+                    Button::new(("recent-file-btn", index))
+                        .ghost()
+                        .small()
+                        .label(recent_file.to_string_lossy().to_string())
+                        .on_click({
+                            let open_image_request = open_image_request.clone();
+                            let recent_file = recent_file.clone();
+                            move |_, _, cx| {
+                                open_image_request
+                                    .write(cx, Some(SourceImage::Path(recent_file.clone())))
+                            }
+                        })
+                }))
+        })
+}
+
+fn prompt_for_image(cx: &mut gpui::App, open_image_request: &Entity<Option<SourceImage>>) {
+    let image_paths = cx.prompt_for_paths(PathPromptOptions {
+        files: true,
+        directories: false,
+        multiple: false,
+        prompt: Some("Open".into()),
+    });
+    let open_image_request = open_image_request.clone();
+
+    cx.spawn(
+        async move |cx: &mut gpui::AsyncApp| match image_paths.await {
+            Ok(Ok(Some(image_paths))) => {
+                if let Some(image_path) = image_paths.into_iter().next() {
+                    let _ = open_image_request.write(cx, Some(SourceImage::Path(image_path)));
+                }
+            }
+            Ok(Err(error)) => {
+                eprintln!("error: failed to open file dialog: {:?}", error);
+            }
+            _ => {}
+        },
+    )
+    .detach();
+}
+
+/// Asks where to save the current crop and settings as a recipe for
+/// `apply-recipe`
+fn save_recipe(
+    window: &mut gpui::Window,
+    cx: &mut gpui::App,
+    state: &BasicropState,
+    source_asset: &LoadingImage,
+) {
+    let (Some(final_crop), LoadingImage::Image(image)) =
+        (state.image_crop.read(cx).crop().to_final(), source_asset)
+    else {
+        return;
+    };
+
+    // The crop is in the rotated image, apply-recipe compares its size
+    let image_transform = *state.image_transform.read(cx);
+    let image_size = image.size(0);
+    let (image_width, image_height) =
+        image_transform.transformed_size(image_size.width.into(), image_size.height.into());
+    let recipe = Recipe {
+        image_width,
+        image_height,
+        crop: final_crop,
+        transform: image_transform,
+        shape: *state.crop_shape.read(cx),
+        fill: *state.canvas_fill.read(cx),
+        resize: *state.output_resize.read(cx),
+        on_size_mismatch: SizeMismatch::default(),
+        anchor: Anchor::default(),
+    };
+
+    let directory = match &state.image_source {
+        Some(SourceImage::Path(image_path)) => image_path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map(|parent| parent.to_path_buf()),
+        _ => None,
+    }
+    .unwrap_or_else(|| PathBuf::from("."));
+    let new_path = cx.prompt_for_new_path(&directory, Some("recipe.json"));
+
+    window
+        .spawn(cx, async move |_| match new_path.await {
+            Ok(Ok(Some(path))) => match recipe.save(&path) {
+                Ok(_) => println!("info: saved recipe to: {}", path.display()),
+                Err(error) => eprintln!("error: failed to save recipe: {error}"),
+            },
+            Ok(Err(error)) => {
+                eprintln!("error: failed to open save dialog: {:?}", error);
+            }
+            _ => {}
+        })
+        .detach();
+}
+
+fn prompt_for_dest_path(
+    window: &mut gpui::Window,
+    cx: &mut gpui::App,
+    dest_image_path: &Entity<PathBuf>,
+    dest_input: &Entity<InputState>,
+) {
+    let dest_path = dest_image_path.read(cx).clone();
+    let directory = match dest_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let file_name = dest_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string());
+    let new_path = cx.prompt_for_new_path(&directory, file_name.as_deref());
+    let dest_input = dest_input.clone();
+
+    window
+        .spawn(cx, async move |cx| match new_path.await {
+            Ok(Ok(Some(path))) => {
+                let _ = cx.update(|window, cx| {
+                    dest_input.update(cx, |input, cx| {
+                        input.set_value(path.to_string_lossy().to_string(), window, cx);
+                    });
+                });
+            }
+            Ok(Err(error)) => {
+                eprintln!("error: failed to open save dialog: {:?}", error);
+            }
+            _ => {}
+        })
+        .detach();
+}
+
+fn finalize_crop(
+    window: &mut gpui::Window,
+    cx: &mut gpui::App,
+    state: &BasicropState,
+    image_asset: &LoadingImage,
+) {
+    // The regions are exported instead of the crop when there are any
+    if state.crop_regions.read(cx).is_empty() && has_invalid_crop(cx, state) {
+        eprintln!("error: the crop is empty or out of range");
+        return;
+    }
+
+    if state.to_clipboard {
+        copy_crop(cx, state, image_asset, true);
+        return;
+    }
+
+    // The grid's cells or the regions are each saved next to the
+    // destination path
+    let dest_path = state.dest_image_path.read(cx).clone();
+    let grid_slice = *state.grid_slice.read(cx);
+    if *state.is_slicing.read(cx)
+        && let Some(grid_crop) = state.image_crop.read(cx).crop().to_final()
+        && let Some(image_bounds) = state.image_crop_initial.read(cx).to_final()
+    {
+        // Cells in the canvas around the image have nothing to crop
+        let crops = grid_slice
+            .cells(&grid_crop)
+            .into_iter()
+            .filter(|(_, _, cell)| cell.is_valid(image_bounds.width, image_bounds.height))
+            .map(|(row, column, cell)| {
+                let name = format!("{}_{}", row + 1, column + 1);
+                (cell, region_dest_path(&dest_path, 0, &name))
+            })
+            .collect();
+        export_crops(window, cx, state, image_asset, crops, grid_slice.skip_empty);
+        return;
+    }
+
+    let crop_regions = state.crop_regions.read(cx);
+    if !crop_regions.is_empty() {
+        let crops = crop_regions
+            .iter()
+            .enumerate()
+            .filter_map(|(index, region)| {
+                let final_crop = region.image_crop.to_final()?;
+                Some((
+                    final_crop,
+                    region_dest_path(&dest_path, index + 1, &region.name),
+                ))
+            })
+            .collect();
+        export_crops(window, cx, state, image_asset, crops, false);
+        return;
+    }
+
     let image_crop = state.image_crop.clone();
     let image_crop_initial = state.image_crop_initial.clone();
     let image_asset = image_asset.clone();
     let dest_image_path = state.dest_image_path.clone();
     let image_saved_notification = state.image_saved_notification.clone();
 
-    if image_crop.read(cx) == image_crop_initial.read(cx) {
+    let image_transform = *state.image_transform.read(cx);
+    let crop_shape = *state.crop_shape.read(cx);
+    let polygon_crop = active_polygon_crop(cx, state);
+    let canvas_fill = *state.canvas_fill.read(cx);
+    let output_resize = *state.output_resize.read(cx);
+    let output_options = *state.output_options.read(cx);
+    // A polygon can't be restored from a sidecar
+    let sidecar_image_path = match &state.image_source {
+        Some(SourceImage::Path(image_path))
+            if state.config.output.sidecar && polygon_crop.is_none() =>
+        {
+            Some(image_path.clone())
+        }
+        _ => None,
+    };
+
+    if image_crop.read(cx).crop() == image_crop_initial.read(cx)
+        && image_transform.is_identity()
+        && !crop_shape.has_mask()
+        && polygon_crop.is_none()
+        && output_resize.size == OutputSize::Original
+    {
         println!("info: image not cropped");
         image_saved_notification.write(cx, ());
         return;
     }
 
-    let image_crop_logged = image_crop.read(cx).clone().to_final().unwrap();
+    let image_crop_logged = image_crop.read(cx).crop().to_final().unwrap();
     println!(
         "info: cropping image with inputs: x: {}, y: {}, dimensions: {}x{}",
         image_crop_logged.crop_x,
@@ -240,80 +1438,56 @@ fn finalize_crop(cx: &mut gpui::App, state: &BasicropState, image_asset: &Loadin
     );
 
     let (Some(final_crop), LoadingImage::Image(image)) =
-        (image_crop.read(cx).to_final(), &image_asset)
+        (image_crop.read(cx).crop().to_final(), &image_asset)
     else {
         eprintln!("warn: can't save file due to uninitialized image");
         cx.shutdown();
         return;
     };
 
-    let image_size = image.size(0);
-    let cropped_image_buf: Option<image::ImageBuffer<image::Rgba<_>, Vec<_>>> =
-        image::ImageBuffer::from_raw(
-            image_size.width.into(),
-            image_size.height.into(),
-            image.as_bytes(0).unwrap().to_vec(),
-        );
-
-    let Some(mut cropped_image_buf) = cropped_image_buf else {
+    let Some(image_buf) = rgba_image(image) else {
         eprintln!("error: can't retrieve image buffer for cropping");
         cx.shutdown();
         return;
     };
 
     let dest_path = dest_image_path.read(cx).clone();
+    warn_mask_without_alpha(&crop_shape, polygon_crop.as_ref(), &dest_path);
     let image_saved_notification = image_saved_notification.clone();
     cx.spawn(async move |cx: &mut gpui::AsyncApp| {
         cx.background_spawn(async move {
-            let mut cropped_image_buf = image::imageops::crop(
-                &mut cropped_image_buf,
-                final_crop.crop_x,
-                final_crop.crop_y,
-                final_crop.width,
-                final_crop.height,
+            let saved = process_image(
+                &image_buf,
+                &image_transform,
+                &final_crop,
+                &canvas_fill,
+                &crop_shape,
+                polygon_crop.as_ref(),
+                &output_resize,
             )
-            .to_image();
-
-            // Convert from RGBA to BGRA.
-            for pixel in cropped_image_buf.as_chunks_mut::<4>().0 {
-                pixel.swap(0, 2);
-            }
+            .and_then(|cropped_image_buf| {
+                save_image(cropped_image_buf, &dest_path, &output_options)
+            });
 
-            let image_type = dest_path
-                .components()
-                .map(|component| component.as_os_str().to_str().unwrap().to_string())
-                .next_back()
-                .unwrap()
-                .rsplit('.')
-                .next_back()
-                .unwrap()
-                .to_lowercase();
-            let saved_image = match image_type.as_str() {
-                "png" | "webp" => image::save_buffer(
-                    &dest_path,
-                    cropped_image_buf.into_raw().as_slice(),
-                    final_crop.width,
-                    final_crop.height,
-                    image::ExtendedColorType::Rgba8,
-                ),
-                _ => image::save_buffer(
-                    &dest_path,
-                    image::DynamicImage::ImageRgba8(cropped_image_buf)
-                        .to_rgb8()
-                        .into_raw()
-                        .as_slice(),
-                    final_crop.width,
-                    final_crop.height,
-                    image::ExtendedColorType::Rgb8,
-                ),
-            };
-
-            match saved_image {
+            match saved {
                 Ok(_) => {
                     println!(
                         "info: cropped and saved image successfully to: {}",
                         dest_path.to_str().unwrap_or("[invalid_str]")
                     );
+                    if let Some(image_path) = sidecar_image_path {
+                        let output = SidecarOutput {
+                            path: dest_path,
+                            shape: crop_shape,
+                            fill: canvas_fill,
+                            resize: output_resize,
+                            jpeg_quality: output_options.jpeg_quality,
+                        };
+                        match Sidecar::save(&image_path, final_crop, image_transform, output) {
+                            Ok(path) => println!("info: saved sidecar to: {}", path.display()),
+                            Err(error) => eprintln!("warn: failed to save sidecar: {error}"),
+                        }
+                    }
                 }
                 Err(error) => {
                     eprintln!("error: failed to save cropped image: {:?}", error);
@@ -326,3 +1500,489 @@ fn finalize_crop(cx: &mut gpui::App, state: &BasicropState, image_asset: &Loadin
     })
     .detach();
 }
+
+/// Saves several crops of the image, each to its own path, optionally
+/// leaving out the fully transparent ones. The window stays open listing the
+/// crops that couldn't be saved when any fail
+fn export_crops(
+    window: &mut gpui::Window,
+    cx: &mut gpui::App,
+    state: &BasicropState,
+    image_asset: &LoadingImage,
+    crops: Vec<(FinalizedImageCrop, PathBuf)>,
+    skip_empty: bool,
+) {
+    let LoadingImage::Image(image) = image_asset else {
+        eprintln!("warn: can't save file due to uninitialized image");
+        cx.shutdown();
+        return;
+    };
+
+    let Some(image_buf) = rgba_image(image) else {
+        eprintln!("error: can't retrieve image buffer for cropping");
+        cx.shutdown();
+        return;
+    };
+
+    let image_transform = *state.image_transform.read(cx);
+    let crop_shape = *state.crop_shape.read(cx);
+    let canvas_fill = *state.canvas_fill.read(cx);
+    let output_resize = *state.output_resize.read(cx);
+    let output_options = *state.output_options.read(cx);
+    let dest_path = state.dest_image_path.read(cx).clone();
+    let image_saved_notification = state.image_saved_notification.clone();
+    let window_handle = window.window_handle();
+
+    warn_mask_without_alpha(&crop_shape, None, &dest_path);
+
+    let crop_count = crops.len();
+    cx.spawn(async move |cx: &mut gpui::AsyncApp| {
+        let failures = cx
+            .background_spawn(async move {
+                let mut failures = Vec::new();
+                for (final_crop, crop_path) in crops {
+                    println!(
+                        "info: cropping image with inputs: x: {}, y: {}, dimensions: {}x{}",
+                        final_crop.crop_x, final_crop.crop_y, final_crop.width, final_crop.height,
+                    );
+
+                    let cropped_image_buf = match process_image(
+                        &image_buf,
+                        &image_transform,
+                        &final_crop,
+                        &canvas_fill,
+                        &crop_shape,
+                        None,
+                        &output_resize,
+                    ) {
+                        Ok(cropped_image_buf) => cropped_image_buf,
+                        Err(error) => {
+                            eprintln!("error: failed to crop image: {:?}", error);
+                            failures.push(format!("{}: {}", crop_path.display(), error));
+                            continue;
+                        }
+                    };
+                    if skip_empty && is_transparent(&cropped_image_buf) {
+                        println!("info: skipped empty cell");
+                        continue;
+                    }
+
+                    match save_image(cropped_image_buf, &crop_path, &output_options) {
+                        Ok(_) => {
+                            println!(
+                                "info: cropped and saved image successfully to: {}",
+                                crop_path.to_str().unwrap_or("[invalid_str]")
+                            );
+                        }
+                        Err(error) => {
+                            eprintln!("error: failed to save cropped image: {:?}", error);
+                            failures.push(format!("{}: {}", crop_path.display(), error));
+                        }
+                    };
+                }
+                failures
+            })
+            .await;
+
+        if failures.is_empty() {
+            let _ = image_saved_notification.write(cx, ());
+            return;
+        }
+
+        let message = format!(
+            "{} of {} crops couldn't be saved:\n{}",
+            failures.len(),
+            crop_count,
+            failures.join("\n")
+        );
+        let _ = window_handle.update(cx, |_, window, cx| {
+            window.push_notification(Notification::error(message).autohide(false), cx);
+        });
+    })
+    .detach();
+}
+
+/// Rotates or flips the displayed image, moving the crop along with it so it
+/// still covers the same pixels
+fn apply_transform_op(
+    window: &mut gpui::Window,
+    cx: &mut gpui::App,
+    state: &BasicropState,
+    image_asset: &LoadingImage,
+    op: TransformOp,
+) {
+    let LoadingImage::Image(image) = image_asset else {
+        return;
+    };
+
+    let image_size = image.size(0);
+    let (crop_bounds_width, crop_bounds_height) =
+        (i32::from(image_size.width), i32::from(image_size.height));
+    let image_width = px(crop_bounds_width as f32);
+    let image_height = px(crop_bounds_height as f32);
+
+    let image_transform = state.image_transform.read(cx).then(op);
+    state.image_transform.write(cx, image_transform);
+    state.straighten_slider.update(cx, |slider, cx| {
+        slider.set_value(image_transform.straighten, window, cx);
+    });
+
+    let mut polygon_crop = state.polygon_crop.read(cx).clone();
+    for point in &mut polygon_crop.points {
+        *point = op.apply_to_point(*point, image_width, image_height);
+    }
+    state.polygon_crop.write(cx, polygon_crop);
+
+    let crop_regions = state
+        .crop_regions
+        .read(cx)
+        .iter()
+        .map(|region| CropRegion {
+            name: region.name.clone(),
+            image_crop: match region.image_crop.to_initialized() {
+                Some(crop) => op
+                    .apply_to_crop(&crop, crop_bounds_width, crop_bounds_height)
+                    .into(),
+                None => region.image_crop.clone(),
+            },
+        })
+        .collect();
+    state.crop_regions.write(cx, crop_regions);
+
+    if let Some(crop_initial) = state.image_crop_initial.read(cx).to_initialized() {
+        let crop_initial = op.apply_to_crop(&crop_initial, crop_bounds_width, crop_bounds_height);
+        state.image_crop_initial.write(cx, crop_initial.into());
+    }
+
+    let Some(crop) = state.image_crop.read(cx).crop().to_initialized() else {
+        return;
+    };
+    let crop = op.apply_to_crop(&crop, crop_bounds_width, crop_bounds_height);
+    state
+        .image_crop
+        .update(cx, |model, cx| model.set(crop, CropSource::Transform, cx));
+}
+
+/// Selects a crop, moves a polygon's vertex or levels the image along the
+/// line dragged across the canvas, depending on the tool in use
+pub fn handle_canvas_drag(
+    window: &mut gpui::Window,
+    cx: &mut gpui::App,
+    state: &BasicropState,
+    event: &DragEvent,
+) {
+    match *event {
+        DragEvent::Started(drag) => {
+            state.straighten_drag_angle.write(cx, None);
+            if *state.is_drawing_polygon.read(cx)
+                && let Some(canvas_view) = *state.canvas_view.read(cx)
+            {
+                let vertex = canvas_view.vertex_at(state.polygon_crop.read(cx), drag.start);
+                state.dragged_vertex.write(cx, vertex);
+            }
+            select_crop(cx, state, drag.start, drag.start);
+        }
+        DragEvent::Moved(drag) => {
+            select_crop(cx, state, drag.start, drag.position);
+
+            if let (Some(vertex), Some(canvas_view)) =
+                (*state.dragged_vertex.read(cx), *state.canvas_view.read(cx))
+            {
+                let mut polygon_crop = state.polygon_crop.read(cx).clone();
+                if let Some(point) = polygon_crop.points.get_mut(vertex) {
+                    *point = canvas_view.image_position(drag.position);
+                    set_polygon_crop(cx, state, polygon_crop);
+                }
+            }
+
+            if *state.is_straightening.read(cx) {
+                let line = drag.position - drag.start;
+                let angle = f32::from(line.y).atan2(f32::from(line.x)).to_degrees();
+                state.straighten_drag_angle.write(cx, Some(angle));
+            }
+        }
+        DragEvent::Dropped => {
+            state.dragged_vertex.write(cx, None);
+
+            if let Some(line_angle) = *state.straighten_drag_angle.read(cx) {
+                let image_transform = *state.image_transform.read(cx);
+                let degrees = image_transform.straighten + level_angle(line_angle);
+                set_straighten_angle(window, cx, state, degrees);
+                state.straighten_drag_angle.write(cx, None);
+            }
+        }
+        DragEvent::Cancelled => state.dragged_vertex.write(cx, None),
+    }
+}
+
+/// Selects the crop between the pixel boundaries nearest to two positions in
+/// the window dragged across on the canvas, unless dragging straightens the
+/// image or moves a vertex
+fn select_crop(
+    cx: &mut gpui::App,
+    state: &BasicropState,
+    start: gpui::Point<gpui::Pixels>,
+    end: gpui::Point<gpui::Pixels>,
+) {
+    if *state.is_straightening.read(cx) || *state.is_drawing_polygon.read(cx) {
+        return;
+    }
+    let Some(canvas_view) = *state.canvas_view.read(cx) else {
+        return;
+    };
+
+    let start = canvas_view.image_pixel(start);
+    let end = canvas_view.image_pixel(end);
+    let crop = InitializedImageCrop {
+        crop_x: start.x.min(end.x),
+        crop_y: start.y.min(end.y),
+        width: (end.x - start.x).abs(),
+        height: (end.y - start.y).abs(),
+    };
+    state
+        .image_crop
+        .update(cx, |model, cx| model.set(crop, CropSource::Canvas, cx));
+}
+
+/// Adds a vertex to the polygon being drawn, or closes it on a double-click
+/// or a click on its first vertex
+fn add_polygon_vertex(
+    cx: &mut gpui::App,
+    state: &BasicropState,
+    position: gpui::Point<gpui::Pixels>,
+    click_count: usize,
+) {
+    let Some(canvas_view) = *state.canvas_view.read(cx) else {
+        return;
+    };
+    let mut polygon_crop = state.polygon_crop.read(cx).clone();
+    if polygon_crop.closed {
+        return;
+    }
+
+    match canvas_view.vertex_at(&polygon_crop, position) {
+        _ if click_count >= 2 => polygon_crop.closed = true,
+        Some(0) if polygon_crop.points.len() >= 3 => polygon_crop.closed = true,
+        // Clicking an existing vertex starts dragging it
+        Some(_) => return,
+        None => polygon_crop
+            .points
+            .push(canvas_view.image_position(position)),
+    }
+
+    set_polygon_crop(cx, state, polygon_crop);
+}
+
+/// Replaces the polygon, cropping to its bounding box once it's closed
+fn set_polygon_crop(cx: &mut gpui::App, state: &BasicropState, polygon_crop: PolygonCrop) {
+    let bounding_box = polygon_crop
+        .is_complete()
+        .then(|| polygon_crop.bounding_box())
+        .flatten();
+    state.polygon_crop.write(cx, polygon_crop);
+
+    if let Some(crop) = bounding_box {
+        state
+            .image_crop
+            .update(cx, |model, cx| model.set(crop, CropSource::Polygon, cx));
+    }
+}
+
+/// Whether the crop of the loaded image is empty or out of range, see
+/// [`FinalizedImageCrop::is_valid`]
+fn has_invalid_crop(cx: &gpui::App, state: &BasicropState) -> bool {
+    let image_crop = state.image_crop.read(cx).crop().to_final();
+    let image_bounds = state.image_crop_initial.read(cx).to_final();
+    match (image_crop, image_bounds) {
+        (Some(crop), Some(bounds)) => !crop.is_valid(bounds.width, bounds.height),
+        _ => false,
+    }
+}
+
+/// The polygon to mask the saved image with, if one is drawn
+fn active_polygon_crop(cx: &gpui::App, state: &BasicropState) -> Option<PolygonCrop> {
+    let polygon_crop = state.polygon_crop.read(cx);
+    (*state.is_drawing_polygon.read(cx) && polygon_crop.is_complete()).then(|| polygon_crop.clone())
+}
+
+/// Changes the straighten angle, limiting the crop to the region of the
+/// rotated image without empty corners
+pub fn set_straighten_angle(
+    window: &mut gpui::Window,
+    cx: &mut gpui::App,
+    state: &BasicropState,
+    degrees: f32,
+) {
+    let previous_transform = *state.image_transform.read(cx);
+    let image_transform = previous_transform.with_straighten(degrees);
+    state.image_transform.write(cx, image_transform);
+    state.straighten_slider.update(cx, |slider, cx| {
+        slider.set_value(image_transform.straighten, window, cx);
+    });
+
+    let (Some(crop), Some(image)) = (
+        state.image_crop.read(cx).crop().to_initialized(),
+        state.image_crop_initial.read(cx).to_initialized(),
+    ) else {
+        return;
+    };
+
+    let image_width = image.width as f32;
+    let image_height = image.height as f32;
+    let (previous_x, previous_y, previous_width, previous_height) =
+        previous_transform.straightened_bounds(image_width, image_height);
+    let previous_bounds = InitializedImageCrop::inside_edges(
+        previous_x,
+        previous_y,
+        previous_x + previous_width,
+        previous_y + previous_height,
+    );
+    let (bounds_x, bounds_y, bounds_width, bounds_height) =
+        image_transform.straightened_bounds(image_width, image_height);
+    let (bounds_right, bounds_bottom) = (bounds_x + bounds_width, bounds_y + bounds_height);
+
+    // A crop covering the whole straightened region keeps covering it,
+    // anything else is only shrunk to fit
+    let crop_rect = (
+        crop.crop_x as f32,
+        crop.crop_y as f32,
+        (crop.crop_x + crop.width) as f32,
+        (crop.crop_y + crop.height) as f32,
+    );
+    let crop = if crop == previous_bounds {
+        InitializedImageCrop::inside_edges(bounds_x, bounds_y, bounds_right, bounds_bottom)
+    } else {
+        InitializedImageCrop::inside_edges(
+            crop_rect.0.clamp(bounds_x, bounds_right),
+            crop_rect.1.clamp(bounds_y, bounds_bottom),
+            crop_rect.2.clamp(bounds_x, bounds_right),
+            crop_rect.3.clamp(bounds_y, bounds_bottom),
+        )
+    };
+
+    state
+        .image_crop
+        .update(cx, |model, cx| model.set(crop, CropSource::Transform, cx));
+}
+
+/// The straighten angle change that makes a line at `line_angle` degrees
+/// level, or plumb when it's closer to vertical
+fn level_angle(line_angle: f32) -> f32 {
+    let mut line_angle = line_angle % 180.;
+    if line_angle > 90. {
+        line_angle -= 180.;
+    } else if line_angle <= -90. {
+        line_angle += 180.;
+    }
+
+    if line_angle > 45. {
+        90. - line_angle
+    } else if line_angle < -45. {
+        -90. - line_angle
+    } else {
+        -line_angle
+    }
+}
+
+/// On X11 and Wayland the clipboard is served by the application that copied
+/// it, so its contents are lost when it quits
+const CLIPBOARD_NEEDS_OWNER: bool = cfg!(any(target_os = "linux", target_os = "freebsd"));
+
+/// How often the clipboard is checked while waiting for another copy
+const CLIPBOARD_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Minimizes the windows and waits until something else is copied, so the
+/// cropped image can still be pasted after finalizing
+async fn keep_clipboard(cx: &mut gpui::AsyncApp, clipboard_item: ClipboardItem) {
+    println!("info: keeping the cropped image on the clipboard until something else is copied");
+    let _ = cx.update(|cx| {
+        for window in cx.windows() {
+            let _ = window.update(cx, |_, window, _| window.minimize_window());
+        }
+    });
+    loop {
+        cx.background_executor()
+            .timer(CLIPBOARD_POLL_INTERVAL)
+            .await;
+        let owned = cx.update(|cx| cx.read_from_clipboard().as_ref() == Some(&clipboard_item));
+        if !owned.unwrap_or(false) {
+            break;
+        }
+    }
+}
+
+/// Copies the cropped image to the clipboard as a PNG, and quits afterwards
+/// when `finalize` is set
+fn copy_crop(
+    cx: &mut gpui::App,
+    state: &BasicropState,
+    image_asset: &LoadingImage,
+    finalize: bool,
+) {
+    let image_saved_notification = state.image_saved_notification.clone();
+
+    if has_invalid_crop(cx, state) {
+        eprintln!("error: the crop is empty or out of range");
+        return;
+    }
+
+    let (Some(final_crop), LoadingImage::Image(image)) =
+        (state.image_crop.read(cx).crop().to_final(), image_asset)
+    else {
+        eprintln!("warn: can't copy image due to uninitialized image");
+        return;
+    };
+
+    let Some(image_buf) = rgba_image(image) else {
+        eprintln!("error: can't retrieve image buffer for cropping");
+        return;
+    };
+
+    let image_transform = *state.image_transform.read(cx);
+    let crop_shape = *state.crop_shape.read(cx);
+    let polygon_crop = active_polygon_crop(cx, state);
+    let canvas_fill = *state.canvas_fill.read(cx);
+    let output_resize = *state.output_resize.read(cx);
+    let (width, height) = output_resize
+        .size
+        .target_size(final_crop.width, final_crop.height);
+    cx.spawn(async move |cx: &mut gpui::AsyncApp| {
+        let png_bytes = cx
+            .background_spawn(async move {
+                process_image(
+                    &image_buf,
+                    &image_transform,
+                    &final_crop,
+                    &canvas_fill,
+                    &crop_shape,
+                    polygon_crop.as_ref(),
+                    &output_resize,
+                )
+                .and_then(|cropped_image_buf| encode_png(&cropped_image_buf))
+            })
+            .await;
+
+        match png_bytes {
+            Ok(png_bytes) => {
+                let clipboard_image = gpui::Image::from_bytes(gpui::ImageFormat::Png, png_bytes);
+                let clipboard_item = ClipboardItem::new_image(&clipboard_image);
+                let _ = cx.update(|cx| cx.write_to_clipboard(clipboard_item.clone()));
+                println!(
+                    "info: copied cropped image to clipboard with dimensions: {}x{}",
+                    width, height,
+                );
+                if finalize && CLIPBOARD_NEEDS_OWNER {
+                    keep_clipboard(cx, clipboard_item).await;
+                }
+            }
+            Err(error) => {
+                eprintln!("error: failed to crop image for the clipboard: {:?}", error);
+            }
+        }
+
+        if finalize {
+            let _ = image_saved_notification.write(cx, ());
+        }
+    })
+    .detach();
+}
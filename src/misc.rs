@@ -1,4 +1,6 @@
-use gpui::{Pixels, Point, RenderImage};
+use crate::crop_pipeline::decode_image;
+use gpui::{App, ClipboardEntry, RenderImage};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 #[derive(Clone)]
@@ -6,6 +8,7 @@ pub enum LoadingImage {
     Image(Arc<RenderImage>),
     Failed,
     Loading,
+    Empty,
 }
 
 impl LoadingImage {
@@ -17,7 +20,79 @@ impl LoadingImage {
     }
 }
 
-pub enum CroppingMousePosition {
-    Initial(Point<Pixels>),
-    Moved(Point<Pixels>),
+/// Where the image being cropped comes from
+#[derive(Clone, Debug)]
+pub enum SourceImage {
+    Path(PathBuf),
+    Decoded(Arc<RenderImage>),
+}
+
+/// Destination of one of several exported regions, `{stem}_{name}.{ext}`, or
+/// `{stem}_{index}.{ext}` for regions without a name
+pub fn region_dest_path(dest_image_path: &Path, index: usize, name: &str) -> PathBuf {
+    let stem = dest_image_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let suffix = match name.trim() {
+        "" => index.to_string(),
+        name => name.replace(['/', '\\'], "_"),
+    };
+
+    let mut file_name = format!("{stem}_{suffix}");
+    if let Some(extension) = dest_image_path.extension() {
+        file_name.push('.');
+        file_name.push_str(&extension.to_string_lossy());
+    }
+    dest_image_path.with_file_name(file_name)
+}
+
+/// Decodes the first image found on the clipboard
+pub fn read_clipboard_image(cx: &App) -> Option<SourceImage> {
+    let clipboard_image = cx
+        .read_from_clipboard()?
+        .into_entries()
+        .find_map(|entry| match entry {
+            ClipboardEntry::Image(image) => Some(image),
+            _ => None,
+        });
+
+    let Some(clipboard_image) = clipboard_image else {
+        eprintln!("warn: clipboard doesn't contain an image");
+        return None;
+    };
+
+    match decode_image(clipboard_image.bytes()) {
+        Ok(image) => Some(SourceImage::Decoded(image)),
+        Err(error) => {
+            eprintln!("error: failed to decode clipboard image: {:?}", error);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_are_saved_next_to_the_destination() {
+        let dest_path = Path::new("/photos/scan.cropped.jpg");
+        assert_eq!(
+            region_dest_path(dest_path, 2, ""),
+            PathBuf::from("/photos/scan.cropped_2.jpg")
+        );
+        assert_eq!(
+            region_dest_path(dest_path, 2, " beach "),
+            PathBuf::from("/photos/scan.cropped_beach.jpg")
+        );
+        assert_eq!(
+            region_dest_path(dest_path, 2, "a/b\\c"),
+            PathBuf::from("/photos/scan.cropped_a_b_c.jpg")
+        );
+        assert_eq!(
+            region_dest_path(Path::new("scan"), 1, ""),
+            PathBuf::from("scan_1")
+        );
+    }
 }
@@ -0,0 +1,149 @@
+use image::ImageFormat;
+use image::codecs::png::CompressionType;
+use std::path::Path;
+
+/// PNG compression levels offered in the window, with their labels
+pub const PNG_COMPRESSIONS: [(&str, CompressionType); 3] = [
+    ("Fast", CompressionType::Fast),
+    ("Balanced", CompressionType::Default),
+    ("Smallest", CompressionType::Best),
+];
+
+/// The format an image will be saved in, derived from the destination path's
+/// file extension
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Known(ImageFormat),
+    Unknown,
+}
+
+impl OutputFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match ImageFormat::from_path(path) {
+            Ok(format) => OutputFormat::Known(format),
+            Err(_) => OutputFormat::Unknown,
+        }
+    }
+
+    /// Whether the cropped image is saved with its alpha channel, formats
+    /// without it are saved as RGB
+    pub fn supports_alpha(&self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Known(ImageFormat::Png) | OutputFormat::Known(ImageFormat::WebP)
+        )
+    }
+
+    /// Whether the format is saved with a quality setting, lossy JPEG
+    pub fn has_quality(&self) -> bool {
+        *self == OutputFormat::Known(ImageFormat::Jpeg)
+    }
+
+    /// Whether the format is saved with a compression level, PNG
+    pub fn has_compression(&self) -> bool {
+        *self == OutputFormat::Known(ImageFormat::Png)
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            OutputFormat::Known(format) => {
+                let name = format.extensions_str()[0].to_uppercase();
+                let channels = if self.supports_alpha() { "RGBA" } else { "RGB" };
+                format!("{name} ({channels})")
+            }
+            OutputFormat::Unknown => "Unknown format".to_string(),
+        }
+    }
+}
+
+/// Encoder settings of the saved image, only those of its format are used
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OutputOptions {
+    pub jpeg_quality: u8,
+    pub png_compression: CompressionType,
+}
+
+impl OutputOptions {
+    /// The default options, with the configured JPEG quality
+    pub fn new(jpeg_quality: u8) -> Self {
+        OutputOptions {
+            jpeg_quality,
+            png_compression: PNG_COMPRESSIONS[0].1,
+        }
+    }
+
+    /// The options for saving in another format, those that don't apply to
+    /// it are reset to `defaults`
+    pub fn for_format(&self, output_format: OutputFormat, defaults: &OutputOptions) -> Self {
+        OutputOptions {
+            jpeg_quality: if output_format.has_quality() {
+                self.jpeg_quality
+            } else {
+                defaults.jpeg_quality
+            },
+            png_compression: if output_format.has_compression() {
+                self.png_compression
+            } else {
+                defaults.png_compression
+            },
+        }
+    }
+
+    /// Parses the quality field in the window, from 1 to 100
+    pub fn parse_jpeg_quality(value: &str) -> Option<u8> {
+        value
+            .trim()
+            .parse()
+            .ok()
+            .filter(|quality| (1..=100).contains(quality))
+    }
+
+    pub fn parse_png_compression(label: &str) -> Option<CompressionType> {
+        PNG_COMPRESSIONS
+            .iter()
+            .find(|(compression_label, _)| *compression_label == label)
+            .map(|(_, compression)| *compression)
+    }
+
+    pub fn png_compression_label(&self) -> &'static str {
+        PNG_COMPRESSIONS
+            .iter()
+            .find(|(_, compression)| *compression == self.png_compression)
+            .map(|(label, _)| *label)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_of_other_formats_are_reset() {
+        let defaults = OutputOptions::new(90);
+        let options = OutputOptions {
+            jpeg_quality: 40,
+            png_compression: CompressionType::Best,
+        };
+        let jpeg = options.for_format(OutputFormat::Known(ImageFormat::Jpeg), &defaults);
+        assert_eq!(jpeg.jpeg_quality, 40);
+        assert_eq!(jpeg.png_compression, defaults.png_compression);
+        let png = options.for_format(OutputFormat::Known(ImageFormat::Png), &defaults);
+        assert_eq!(png.jpeg_quality, 90);
+        assert_eq!(png.png_compression, CompressionType::Best);
+        let webp = options.for_format(OutputFormat::Known(ImageFormat::WebP), &defaults);
+        assert_eq!(webp, defaults);
+    }
+
+    #[test]
+    fn option_fields_are_parsed() {
+        assert_eq!(OutputOptions::parse_jpeg_quality(" 85 "), Some(85));
+        assert_eq!(OutputOptions::parse_jpeg_quality("0"), None);
+        assert_eq!(OutputOptions::parse_jpeg_quality("101"), None);
+        assert_eq!(
+            OutputOptions::parse_png_compression("Smallest"),
+            Some(CompressionType::Best)
+        );
+        assert_eq!(OutputOptions::parse_png_compression("Tiny"), None);
+    }
+}
@@ -0,0 +1,410 @@
+use image::RgbaImage;
+use image::imageops::{self, FilterType};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Resampling filters offered for resizing, with their display names
+pub const FILTERS: [(&str, FilterType); 5] = [
+    ("Nearest", FilterType::Nearest),
+    ("Triangle", FilterType::Triangle),
+    ("CatmullRom", FilterType::CatmullRom),
+    ("Gaussian", FilterType::Gaussian),
+    ("Lanczos3", FilterType::Lanczos3),
+];
+
+/// Labels of the output size modes in the window, in the order of the
+/// variants of [`OutputSize`]
+pub const OUTPUT_SIZE_MODES: [&str; 4] = ["Original size", "Exact size", "Scale", "Fit within"];
+
+/// Largest number of pixels in a resized image, 1 GiB as RGBA
+pub const MAX_OUTPUT_PIXELS: u64 = 1 << 28;
+
+/// Largest percentage a crop can be scaled by
+pub const MAX_OUTPUT_PERCENT: f32 = 1000.;
+
+/// Size of the saved image relative to the cropped region
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputSize {
+    #[default]
+    Original,
+    /// An exact width and/or height, a missing side keeps the aspect ratio
+    Exact {
+        width: Option<u32>,
+        height: Option<u32>,
+    },
+    Percent(f32),
+    /// The largest size that fits within the box keeping the aspect ratio,
+    /// smaller crops aren't enlarged
+    Fit {
+        width: u32,
+        height: u32,
+    },
+}
+
+/// How the cropped image is resized before saving
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct OutputResize {
+    pub size: OutputSize,
+    #[serde(
+        serialize_with = "serialize_filter",
+        deserialize_with = "deserialize_filter"
+    )]
+    pub filter: FilterType,
+}
+
+impl Default for OutputResize {
+    fn default() -> Self {
+        OutputResize {
+            size: OutputSize::Original,
+            filter: FilterType::Lanczos3,
+        }
+    }
+}
+
+impl OutputSize {
+    /// Parses the value of `--resize`, `WIDTHxHEIGHT` where either side may
+    /// be left out
+    pub fn parse_exact(value: &str) -> Option<OutputSize> {
+        let (width, height) = parse_dimensions(value)?;
+        Some(OutputSize::Exact { width, height }).filter(OutputSize::is_valid)
+    }
+
+    /// Parses the value of `--fit`, `WIDTHxHEIGHT`
+    pub fn parse_fit(value: &str) -> Option<OutputSize> {
+        match parse_dimensions(value)? {
+            (Some(width), Some(height)) => {
+                Some(OutputSize::Fit { width, height }).filter(OutputSize::is_valid)
+            }
+            _ => None,
+        }
+    }
+
+    /// Parses the value of `--scale`, a percentage with an optional `%` up to
+    /// [`MAX_OUTPUT_PERCENT`]
+    pub fn parse_percent(value: &str) -> Option<OutputSize> {
+        let percent = value
+            .strip_suffix('%')
+            .unwrap_or(value)
+            .parse::<f32>()
+            .ok()?;
+        Some(OutputSize::Percent(percent)).filter(OutputSize::is_valid)
+    }
+
+    /// Whether the sides are non-zero and within [`MAX_OUTPUT_PIXELS`] and
+    /// the percentage within [`MAX_OUTPUT_PERCENT`], also checked for sizes
+    /// read from sidecars and recipes
+    pub fn is_valid(&self) -> bool {
+        let sides_valid = |width: Option<u32>, height: Option<u32>| {
+            let pixels = width.unwrap_or(1) as u64 * height.unwrap_or(1) as u64;
+            (width.is_some() || height.is_some())
+                && width != Some(0)
+                && height != Some(0)
+                && pixels <= MAX_OUTPUT_PIXELS
+        };
+
+        match *self {
+            OutputSize::Original => true,
+            OutputSize::Exact { width, height } => sides_valid(width, height),
+            OutputSize::Percent(percent) => {
+                percent.is_finite() && percent > 0. && percent <= MAX_OUTPUT_PERCENT
+            }
+            OutputSize::Fit { width, height } => sides_valid(Some(width), Some(height)),
+        }
+    }
+
+    /// Builds the output size from the mode and text fields in the window,
+    /// invalid fields keep the original size
+    pub fn from_fields(mode: &str, width: &str, height: &str, percent: &str) -> OutputSize {
+        let size = match OUTPUT_SIZE_MODES.iter().position(|label| *label == mode) {
+            Some(1) => OutputSize::parse_exact(&format!("{}x{}", width.trim(), height.trim())),
+            Some(2) => OutputSize::parse_percent(percent.trim()),
+            Some(3) => OutputSize::parse_fit(&format!("{}x{}", width.trim(), height.trim())),
+            _ => None,
+        };
+
+        size.unwrap_or_default()
+    }
+
+    pub fn mode(&self) -> &'static str {
+        match self {
+            OutputSize::Original => OUTPUT_SIZE_MODES[0],
+            OutputSize::Exact { .. } => OUTPUT_SIZE_MODES[1],
+            OutputSize::Percent(_) => OUTPUT_SIZE_MODES[2],
+            OutputSize::Fit { .. } => OUTPUT_SIZE_MODES[3],
+        }
+    }
+
+    /// The width, height and percentage to show in the window's fields
+    pub fn field_values(&self) -> (String, String, String) {
+        let side = |side: Option<u32>| side.map(|side| side.to_string()).unwrap_or_default();
+
+        match *self {
+            OutputSize::Original => (String::new(), String::new(), "100".to_string()),
+            OutputSize::Exact { width, height } => (side(width), side(height), "100".to_string()),
+            OutputSize::Percent(percent) => (String::new(), String::new(), percent.to_string()),
+            OutputSize::Fit { width, height } => {
+                (width.to_string(), height.to_string(), "100".to_string())
+            }
+        }
+    }
+
+    /// Dimensions of a crop with the given size after resizing, larger sizes
+    /// are scaled down to [`MAX_OUTPUT_PIXELS`] keeping the aspect ratio
+    pub fn target_size(&self, width: u32, height: u32) -> (u32, u32) {
+        if width == 0 || height == 0 {
+            return (width, height);
+        }
+
+        let aspect_ratio = width as f64 / height as f64;
+        let (target_width, target_height) = match *self {
+            OutputSize::Original => return (width, height),
+            OutputSize::Exact {
+                width: Some(target_width),
+                height: Some(target_height),
+            } => (target_width as f64, target_height as f64),
+            OutputSize::Exact {
+                width: Some(target_width),
+                height: None,
+            } => (target_width as f64, target_width as f64 / aspect_ratio),
+            OutputSize::Exact {
+                width: None,
+                height: Some(target_height),
+            } => (target_height as f64 * aspect_ratio, target_height as f64),
+            OutputSize::Exact {
+                width: None,
+                height: None,
+            } => return (width, height),
+            OutputSize::Percent(percent) => (
+                width as f64 * percent as f64 / 100.,
+                height as f64 * percent as f64 / 100.,
+            ),
+            OutputSize::Fit {
+                width: box_width,
+                height: box_height,
+            } => {
+                let scale = (box_width as f64 / width as f64)
+                    .min(box_height as f64 / height as f64)
+                    .min(1.);
+                (width as f64 * scale, height as f64 * scale)
+            }
+        };
+
+        let (target_width, target_height) = (target_width.round(), target_height.round());
+        let scale = (MAX_OUTPUT_PIXELS as f64 / (target_width * target_height).max(1.))
+            .sqrt()
+            .min(1.);
+        let side = |side: f64| (side * scale).floor().clamp(1., MAX_OUTPUT_PIXELS as f64) as u32;
+        (side(target_width), side(target_height))
+    }
+}
+
+impl OutputResize {
+    /// Parses the value of `--filter`, one of the names in [`FILTERS`]
+    pub fn parse_filter(name: &str) -> Option<FilterType> {
+        FILTERS
+            .iter()
+            .find(|(filter_name, _)| filter_name.eq_ignore_ascii_case(name))
+            .map(|(_, filter)| *filter)
+    }
+
+    pub fn filter_name(&self) -> &'static str {
+        FILTERS
+            .iter()
+            .find(|(_, filter)| *filter == self.filter)
+            .map(|(name, _)| *name)
+            .unwrap_or_default()
+    }
+
+    pub fn apply(&self, image_buf: RgbaImage) -> RgbaImage {
+        let (width, height) = image_buf.dimensions();
+        let (target_width, target_height) = self.size.target_size(width, height);
+        if (target_width, target_height) == (width, height) {
+            return image_buf;
+        }
+
+        imageops::resize(&image_buf, target_width, target_height, self.filter)
+    }
+}
+
+/// Writes a filter by its name in [`FILTERS`]
+fn serialize_filter<S: Serializer>(filter: &FilterType, serializer: S) -> Result<S::Ok, S::Error> {
+    let resize = OutputResize {
+        filter: *filter,
+        ..Default::default()
+    };
+    serializer.serialize_str(resize.filter_name())
+}
+
+fn deserialize_filter<'de, D: Deserializer<'de>>(deserializer: D) -> Result<FilterType, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    OutputResize::parse_filter(&name)
+        .ok_or_else(|| serde::de::Error::custom(format!("unknown filter {name}")))
+}
+
+/// Parses `WIDTHxHEIGHT` where either side may be empty
+fn parse_dimensions(value: &str) -> Option<(Option<u32>, Option<u32>)> {
+    let (width, height) = value.split_once(['x', 'X'])?;
+    let parse_side = |side: &str| -> Option<Option<u32>> {
+        if side.is_empty() {
+            return Some(None);
+        }
+        side.parse::<u32>().ok().map(Some)
+    };
+
+    Some((parse_side(width)?, parse_side(height)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_are_parsed() {
+        assert_eq!(
+            OutputSize::parse_exact("200x"),
+            Some(OutputSize::Exact {
+                width: Some(200),
+                height: None
+            })
+        );
+        assert_eq!(
+            OutputSize::parse_exact("x100"),
+            Some(OutputSize::Exact {
+                width: None,
+                height: Some(100)
+            })
+        );
+        assert_eq!(OutputSize::parse_exact("x"), None);
+        assert_eq!(OutputSize::parse_exact("0x100"), None);
+        assert_eq!(
+            OutputSize::parse_exact("65536x100"),
+            Some(OutputSize::Exact {
+                width: Some(65536),
+                height: Some(100)
+            })
+        );
+        assert_eq!(OutputSize::parse_exact("65536x65536"), None);
+        assert_eq!(OutputSize::parse_fit("200x"), None);
+        assert_eq!(
+            OutputSize::parse_fit("200x100"),
+            Some(OutputSize::Fit {
+                width: 200,
+                height: 100
+            })
+        );
+        assert_eq!(
+            OutputSize::parse_percent("50%"),
+            Some(OutputSize::Percent(50.))
+        );
+        assert_eq!(OutputSize::parse_percent("0"), None);
+        assert_eq!(OutputSize::parse_percent("1001%"), None);
+        assert_eq!(OutputSize::parse_percent("inf"), None);
+    }
+
+    #[test]
+    fn fields_map_back_to_the_same_size() {
+        let sizes = [
+            OutputSize::Original,
+            OutputSize::Exact {
+                width: Some(200),
+                height: None,
+            },
+            OutputSize::Exact {
+                width: Some(200),
+                height: Some(100),
+            },
+            OutputSize::Percent(12.5),
+            OutputSize::Fit {
+                width: 640,
+                height: 480,
+            },
+        ];
+        for size in sizes {
+            let (width, height, percent) = size.field_values();
+            assert_eq!(
+                OutputSize::from_fields(size.mode(), &width, &height, &percent),
+                size
+            );
+        }
+        assert_eq!(
+            OutputSize::from_fields(OUTPUT_SIZE_MODES[1], "", "", "100"),
+            OutputSize::Original
+        );
+    }
+
+    #[test]
+    fn target_size_keeps_the_aspect_ratio() {
+        let exact_width = OutputSize::Exact {
+            width: Some(200),
+            height: None,
+        };
+        assert_eq!(exact_width.target_size(400, 300), (200, 150));
+        assert_eq!(OutputSize::Percent(50.).target_size(401, 301), (201, 151));
+
+        let fit = OutputSize::Fit {
+            width: 100,
+            height: 100,
+        };
+        assert_eq!(fit.target_size(400, 200), (100, 50));
+        assert_eq!(fit.target_size(40, 20), (40, 20));
+    }
+
+    #[test]
+    fn target_size_is_clamped() {
+        let exact_height = OutputSize::Exact {
+            width: None,
+            height: Some(1),
+        };
+        assert_eq!(exact_height.target_size(10, 1000), (1, 1));
+        assert_eq!(
+            OutputSize::Percent(MAX_OUTPUT_PERCENT).target_size(10000, 10),
+            (100000, 100)
+        );
+
+        let (width, height) = OutputSize::Percent(MAX_OUTPUT_PERCENT).target_size(100000, 10000);
+        assert!(width as u64 * height as u64 <= MAX_OUTPUT_PIXELS);
+        assert!(width > 50000 && (width as f64 / height as f64 - 10.).abs() < 0.01);
+        let one_side = OutputSize::Exact {
+            width: Some(u32::MAX),
+            height: None,
+        };
+        assert_eq!(one_side.target_size(1, 1), (16384, 16384));
+        assert_eq!(OutputSize::Percent(50.).target_size(0, 10), (0, 10));
+    }
+
+    #[test]
+    fn read_sizes_are_checked() {
+        let zero_side = OutputSize::Fit {
+            width: 0,
+            height: 100,
+        };
+        let too_large = OutputSize::Exact {
+            width: Some(100000),
+            height: Some(100000),
+        };
+        assert!(!zero_side.is_valid());
+        assert!(!too_large.is_valid());
+        assert!(!OutputSize::Percent(f32::NAN).is_valid());
+        assert!(
+            OutputSize::Exact {
+                width: None,
+                height: Some(100)
+            }
+            .is_valid()
+        );
+    }
+
+    #[test]
+    fn filters_are_found_by_name() {
+        assert_eq!(
+            OutputResize::parse_filter("lanczos3"),
+            Some(FilterType::Lanczos3)
+        );
+        assert_eq!(OutputResize::parse_filter("bicubic"), None);
+        let resize = OutputResize {
+            filter: FilterType::Nearest,
+            ..OutputResize::default()
+        };
+        assert_eq!(resize.filter_name(), "Nearest");
+    }
+}
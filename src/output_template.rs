@@ -0,0 +1,185 @@
+use crate::image_crop::FinalizedImageCrop;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Variables that can be used in an output template
+const VARIABLES: [&str; 9] = ["dir", "stem", "ext", "x", "y", "w", "h", "date", "counter"];
+
+/// Destination path of the cropped image built from the source path and the
+/// crop, as in `{dir}/{stem}_{w}x{h}{ext}`
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct OutputTemplate(String);
+
+impl Default for OutputTemplate {
+    fn default() -> Self {
+        OutputTemplate("{dir}/{stem}.cropped{ext}".to_string())
+    }
+}
+
+impl TryFrom<String> for OutputTemplate {
+    type Error = String;
+
+    fn try_from(template: String) -> Result<Self, Self::Error> {
+        OutputTemplate::parse(&template)
+    }
+}
+
+impl From<OutputTemplate> for String {
+    fn from(template: OutputTemplate) -> Self {
+        template.0
+    }
+}
+
+impl OutputTemplate {
+    /// Parses the value of `--output-template`, checking that every
+    /// `{variable}` is known
+    pub fn parse(template: &str) -> Result<OutputTemplate, String> {
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let Some(end) = rest[start..].find('}') else {
+                return Err(format!("unclosed {{ in output template {template}"));
+            };
+            let name = &rest[start + 1..start + end];
+            if !VARIABLES.contains(&name) {
+                return Err(format!(
+                    "unknown variable {{{name}}} in output template, expected one of: {}",
+                    VARIABLES
+                        .map(|variable| format!("{{{variable}}}"))
+                        .join(", ")
+                ));
+            }
+            rest = &rest[start + end + 1..];
+        }
+
+        Ok(OutputTemplate(template.to_string()))
+    }
+
+    /// Whether the path depends on the crop, so it has to be built again
+    /// when the crop changes
+    pub fn uses_crop(&self) -> bool {
+        ["{x}", "{y}", "{w}", "{h}"]
+            .iter()
+            .any(|variable| self.0.contains(variable))
+    }
+
+    /// The destination for cropping an image, `{counter}` counts up from 1
+    /// to the first path that doesn't exist yet
+    pub fn expand(&self, image_path: &Path, crop: &FinalizedImageCrop) -> PathBuf {
+        let parent = image_path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty());
+        let dir = parent.unwrap_or(Path::new(".")).to_string_lossy();
+        let stem = image_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default();
+        let ext = image_path
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .unwrap_or_default();
+        let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+
+        let path_with_counter = |counter: u32| {
+            let path = PathBuf::from(self.render(|name| match name {
+                "dir" => dir.to_string(),
+                "stem" => stem.to_string(),
+                "ext" => ext.clone(),
+                "x" => crop.crop_x.to_string(),
+                "y" => crop.crop_y.to_string(),
+                "w" => crop.width.to_string(),
+                "h" => crop.height.to_string(),
+                "date" => date.clone(),
+                _ => counter.to_string(),
+            }));
+            // Keep relative source paths relative to the working directory
+            // without a leading ./
+            match parent {
+                None => path
+                    .strip_prefix(".")
+                    .map(Path::to_path_buf)
+                    .unwrap_or(path),
+                Some(_) => path,
+            }
+        };
+
+        if !self.0.contains("{counter}") {
+            return path_with_counter(0);
+        }
+        (1..)
+            .map(path_with_counter)
+            .find(|path| !path.exists())
+            .unwrap()
+    }
+
+    /// Replaces every `{variable}` in the template
+    fn render(&self, value: impl Fn(&str) -> String) -> String {
+        let mut rendered = String::new();
+        let mut rest = self.0.as_str();
+        while let Some(start) = rest.find('{') {
+            let end = start + rest[start..].find('}').unwrap();
+            rendered.push_str(&rest[..start]);
+            rendered.push_str(&value(&rest[start + 1..end]));
+            rest = &rest[end + 1..];
+        }
+        rendered.push_str(rest);
+
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crop() -> FinalizedImageCrop {
+        FinalizedImageCrop {
+            crop_x: -5,
+            crop_y: 10,
+            width: 640,
+            height: 480,
+        }
+    }
+
+    #[test]
+    fn unknown_and_unclosed_variables_are_rejected() {
+        assert!(OutputTemplate::parse("{dir}/{stem}_{w}x{h}{ext}").is_ok());
+        assert!(OutputTemplate::parse("{stem}_{width}").is_err());
+        assert!(OutputTemplate::parse("{stem").is_err());
+    }
+
+    #[test]
+    fn variables_are_expanded() {
+        let template = OutputTemplate::parse("{dir}/{stem}_{x}_{y}_{w}x{h}{ext}").unwrap();
+        assert!(template.uses_crop());
+        assert_eq!(
+            template.expand(Path::new("/photos/cat.jpg"), &crop()),
+            PathBuf::from("/photos/cat_-5_10_640x480.jpg")
+        );
+        assert!(!OutputTemplate::default().uses_crop());
+        assert_eq!(
+            OutputTemplate::default().expand(Path::new("/photos/cat"), &crop()),
+            PathBuf::from("/photos/cat.cropped")
+        );
+    }
+
+    #[test]
+    fn relative_paths_stay_relative() {
+        assert_eq!(
+            OutputTemplate::default().expand(Path::new("cat.png"), &crop()),
+            PathBuf::from("cat.cropped.png")
+        );
+    }
+
+    #[test]
+    fn counter_skips_existing_files() {
+        let dir = std::env::temp_dir().join("basicrop_output_template_counter");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("cat_1.png"), []).unwrap();
+
+        let template = OutputTemplate::parse("{dir}/{stem}_{counter}{ext}").unwrap();
+        let dest_path = template.expand(&dir.join("cat.png"), &crop());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(dest_path, dir.join("cat_2.png"));
+    }
+}
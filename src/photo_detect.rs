@@ -0,0 +1,237 @@
+use crate::image_crop::FinalizedImageCrop;
+use image::{Rgba, RgbaImage, imageops};
+
+/// Longest side of the downscaled scan the photos are searched in
+const DETECT_SIZE: u32 = 600;
+
+/// Smallest photo found, relative to the area of the scan, so dust and
+/// scratches aren't taken for photos
+const MIN_PHOTO_AREA: f32 = 0.005;
+
+/// Default difference from the background, in percent, for pixels to count
+/// as part of a photo
+pub const DEFAULT_DETECT_TOLERANCE: f32 = 12.;
+
+/// Finds the bounding boxes of photos scanned together on one sheet, in
+/// reading order. Pixels differing from the scanner background, sampled
+/// along the edges of the scan, by more than `tolerance` percent are
+/// grouped into connected regions. Photos covering most of the edges of the
+/// scan are taken for the background, leaving the rest of the scan found as
+/// the photo
+pub fn detect_photos(image_buf: &RgbaImage, tolerance: f32) -> Vec<FinalizedImageCrop> {
+    let (width, height) = image_buf.dimensions();
+    if width == 0 || height == 0 {
+        return vec![];
+    }
+
+    // Searching a downscaled copy is fast and smooths out scanner noise
+    let scale = (width.max(height) as f32 / DETECT_SIZE as f32).max(1.);
+    let mask_width = ((width as f32 / scale).round() as u32).max(1);
+    let mask_height = ((height as f32 / scale).round() as u32).max(1);
+    let thumbnail = imageops::thumbnail(image_buf, mask_width, mask_height);
+
+    let background = background_color(&thumbnail);
+    let threshold = tolerance.clamp(0., 100.) / 100. * 255.;
+    let mask: Vec<bool> = thumbnail
+        .pixels()
+        .map(|pixel| pixel.0[3] >= 128 && color_distance(pixel, &background) > threshold)
+        .collect();
+
+    let min_area = (mask_width * mask_height) as f32 * MIN_PHOTO_AREA;
+    let boxes: Vec<_> = connected_boxes(&mask, mask_width, mask_height)
+        .into_iter()
+        .filter(|(left, top, right, bottom)| ((right - left) * (bottom - top)) as f32 >= min_area)
+        .collect();
+
+    // Parts of a photo cut off by areas close to the background color are
+    // found as separate regions within it
+    let is_inside_other = |index: usize| {
+        let (left, top, right, bottom) = boxes[index];
+        boxes
+            .iter()
+            .enumerate()
+            .any(|(other, &(o_left, o_top, o_right, o_bottom))| {
+                other != index
+                    && o_left <= left
+                    && o_top <= top
+                    && o_right >= right
+                    && o_bottom >= bottom
+                    && (o_left, o_top, o_right, o_bottom) != (left, top, right, bottom)
+            })
+    };
+
+    let (scale_x, scale_y) = (
+        width as f32 / mask_width as f32,
+        height as f32 / mask_height as f32,
+    );
+    let mut photos: Vec<_> = (0..boxes.len())
+        .filter(|index| !is_inside_other(*index))
+        .map(|index| {
+            let (left, top, right, bottom) = boxes[index];
+            let left = ((left as f32 * scale_x).floor() as u32).min(width);
+            let top = ((top as f32 * scale_y).floor() as u32).min(height);
+            let right = ((right as f32 * scale_x).ceil() as u32).min(width);
+            let bottom = ((bottom as f32 * scale_y).ceil() as u32).min(height);
+            FinalizedImageCrop {
+                crop_x: left as i32,
+                crop_y: top as i32,
+                width: right - left,
+                height: bottom - top,
+            }
+        })
+        .collect();
+    photos.sort_by_key(|photo| (photo.crop_y, photo.crop_x));
+
+    photos
+}
+
+/// The median color of the pixels along the edges of the image, photos
+/// touching the edges pull it toward their own color
+fn background_color(image_buf: &RgbaImage) -> [f32; 3] {
+    let (width, height) = image_buf.dimensions();
+    let edge_pixels: Vec<_> = (0..width)
+        .flat_map(|x| [(x, 0), (x, height - 1)])
+        .chain((0..height).flat_map(|y| [(0, y), (width - 1, y)]))
+        .map(|(x, y)| image_buf.get_pixel(x, y))
+        .collect();
+
+    [0, 1, 2].map(|channel| {
+        let mut values: Vec<_> = edge_pixels.iter().map(|pixel| pixel.0[channel]).collect();
+        values.sort_unstable();
+        values[values.len() / 2] as f32
+    })
+}
+
+/// Largest difference between the color channels of a pixel and a color
+fn color_distance(pixel: &Rgba<u8>, color: &[f32; 3]) -> f32 {
+    (0..3)
+        .map(|channel| (pixel.0[channel] as f32 - color[channel]).abs())
+        .fold(0., f32::max)
+}
+
+/// Bounding boxes of the 8-connected regions of set cells in the mask, as
+/// left, top, right and bottom edges
+fn connected_boxes(mask: &[bool], width: u32, height: u32) -> Vec<(u32, u32, u32, u32)> {
+    let (width, height) = (width as usize, height as usize);
+    let mut visited = vec![false; mask.len()];
+    let mut boxes = vec![];
+    let mut stack = vec![];
+
+    for start in 0..mask.len() {
+        if !mask[start] || visited[start] {
+            continue;
+        }
+
+        visited[start] = true;
+        stack.push(start);
+        let (mut left, mut top) = (start % width, start / width);
+        let (mut right, mut bottom) = (left, top);
+        while let Some(index) = stack.pop() {
+            let (x, y) = (index % width, index / width);
+            left = left.min(x);
+            right = right.max(x);
+            top = top.min(y);
+            bottom = bottom.max(y);
+
+            for neighbor_y in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                for neighbor_x in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                    let neighbor = neighbor_y * width + neighbor_x;
+                    if mask[neighbor] && !visited[neighbor] {
+                        visited[neighbor] = true;
+                        stack.push(neighbor);
+                    }
+                }
+            }
+        }
+
+        boxes.push((left as u32, top as u32, right as u32 + 1, bottom as u32 + 1));
+    }
+
+    boxes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BACKGROUND: Rgba<u8> = Rgba([250, 250, 250, 255]);
+    const PHOTO: Rgba<u8> = Rgba([40, 60, 80, 255]);
+
+    fn crop(crop_x: i32, crop_y: i32, width: u32, height: u32) -> FinalizedImageCrop {
+        FinalizedImageCrop {
+            crop_x,
+            crop_y,
+            width,
+            height,
+        }
+    }
+
+    /// A 300x200 scan with rectangles of the photo color
+    fn scan(rectangles: &[(u32, u32, u32, u32)]) -> RgbaImage {
+        let mut image_buf = RgbaImage::from_pixel(300, 200, BACKGROUND);
+        for &(left, top, width, height) in rectangles {
+            for y in top..top + height {
+                for x in left..left + width {
+                    image_buf.put_pixel(x, y, PHOTO);
+                }
+            }
+        }
+        image_buf
+    }
+
+    #[test]
+    fn photos_are_found_in_reading_order() {
+        let photos = detect_photos(
+            &scan(&[(20, 110, 100, 70), (180, 20, 90, 60)]),
+            DEFAULT_DETECT_TOLERANCE,
+        );
+        assert_eq!(photos, vec![crop(180, 20, 90, 60), crop(20, 110, 100, 70)]);
+    }
+
+    #[test]
+    fn dust_is_ignored() {
+        // 3x3 is below the smallest photo of 300 pixels in a 300x200 scan
+        let photos = detect_photos(
+            &scan(&[(10, 10, 3, 3), (100, 50, 60, 40)]),
+            DEFAULT_DETECT_TOLERANCE,
+        );
+        assert_eq!(photos, vec![crop(100, 50, 60, 40)]);
+    }
+
+    #[test]
+    fn regions_within_a_photo_are_part_of_it() {
+        // A photo with a large light area around a dark detail
+        let mut image_buf = scan(&[(50, 40, 200, 120)]);
+        for y in 60..140 {
+            for x in 70..230 {
+                image_buf.put_pixel(x, y, BACKGROUND);
+            }
+        }
+        for y in 90..110 {
+            for x in 140..160 {
+                image_buf.put_pixel(x, y, PHOTO);
+            }
+        }
+
+        let photos = detect_photos(&image_buf, DEFAULT_DETECT_TOLERANCE);
+        assert_eq!(photos, vec![crop(50, 40, 200, 120)]);
+    }
+
+    #[test]
+    fn blank_scan_has_no_photos() {
+        assert!(detect_photos(&scan(&[]), DEFAULT_DETECT_TOLERANCE).is_empty());
+        assert!(detect_photos(&RgbaImage::new(0, 0), DEFAULT_DETECT_TOLERANCE).is_empty());
+    }
+
+    #[test]
+    fn photo_covering_most_edges_is_taken_for_the_background() {
+        // The photo covers 600 of the 1000 edge pixels, so the median edge
+        // color is the photo's and the uncovered strip is found instead
+        let image_buf = scan(&[(0, 0, 200, 200)]);
+        let background = background_color(&image_buf);
+        assert_eq!(background, [40., 60., 80.]);
+
+        let photos = detect_photos(&image_buf, DEFAULT_DETECT_TOLERANCE);
+        assert_eq!(photos, vec![crop(200, 0, 100, 200)]);
+    }
+}
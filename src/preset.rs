@@ -0,0 +1,212 @@
+use crate::output_size::OutputSize;
+use serde::{Deserialize, Serialize};
+
+/// A named aspect ratio for the crop, optionally with the size it's resized
+/// to, as in "YouTube thumbnail 1280x720" or "Avatar 1:1 512"
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "PresetConfig", into = "PresetConfig")]
+pub struct Preset {
+    pub name: String,
+    /// Width and height of the aspect ratio
+    pub ratio: (u32, u32),
+    pub size: Option<OutputSize>,
+}
+
+/// A preset as written in the config file, `ratio` as `WIDTH:HEIGHT` and
+/// `size` as `WIDTHxHEIGHT` where either side may be left out
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct PresetConfig {
+    name: String,
+    ratio: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<String>,
+}
+
+impl From<Preset> for PresetConfig {
+    fn from(preset: Preset) -> Self {
+        let size = preset.size.map(|size| {
+            let (width, height, _) = size.field_values();
+            format!("{width}x{height}")
+        });
+
+        PresetConfig {
+            name: preset.name,
+            ratio: Some(format!("{}:{}", preset.ratio.0, preset.ratio.1)),
+            size,
+        }
+    }
+}
+
+impl TryFrom<PresetConfig> for Preset {
+    type Error = String;
+
+    fn try_from(config: PresetConfig) -> Result<Self, Self::Error> {
+        let name = config.name;
+        let size = match config.size {
+            Some(size) => Some(OutputSize::parse_exact(&size).ok_or_else(|| {
+                format!("invalid size {size} for preset {name}, expected WIDTHxHEIGHT")
+            })?),
+            None => None,
+        };
+        let ratio = match (config.ratio, size) {
+            (Some(ratio), _) => parse_ratio(&ratio).ok_or_else(|| {
+                format!("invalid ratio {ratio} for preset {name}, expected WIDTH:HEIGHT")
+            })?,
+            (
+                None,
+                Some(OutputSize::Exact {
+                    width: Some(width),
+                    height: Some(height),
+                }),
+            ) => (width, height),
+            _ => {
+                return Err(format!(
+                    "preset {name} needs a ratio or a WIDTHxHEIGHT size"
+                ));
+            }
+        };
+
+        Ok(Preset { name, ratio, size })
+    }
+}
+
+impl Preset {
+    fn new(name: &str, ratio: (u32, u32), size: Option<(u32, u32)>) -> Preset {
+        Preset {
+            name: name.to_string(),
+            ratio,
+            size: size.map(|(width, height)| OutputSize::Exact {
+                width: Some(width),
+                height: Some(height),
+            }),
+        }
+    }
+
+    /// The largest crop with the preset's aspect ratio centered in a
+    /// region, as x, y, width and height. An empty region gives an empty crop
+    /// at its origin
+    pub fn crop(&self, x: f32, y: f32, width: f32, height: f32) -> (f32, f32, f32, f32) {
+        if !(width > 0. && height > 0.) {
+            return (x, y, 0., 0.);
+        }
+
+        let ratio = self.ratio.0 as f32 / self.ratio.1 as f32;
+        let (crop_width, crop_height) = if width / height > ratio {
+            (height * ratio, height)
+        } else {
+            (width, width / ratio)
+        };
+        let crop_width = crop_width.floor();
+        let crop_height = crop_height.floor();
+
+        (
+            x + ((width - crop_width) / 2.).floor(),
+            y + ((height - crop_height) / 2.).floor(),
+            crop_width,
+            crop_height,
+        )
+    }
+}
+
+/// Presets offered when the config file doesn't list any
+pub fn default_presets() -> Vec<Preset> {
+    vec![
+        Preset::new("Square 1:1", (1, 1), None),
+        Preset::new("Photo 3:2", (3, 2), None),
+        Preset::new("Standard 4:3", (4, 3), None),
+        Preset::new("Widescreen 16:9", (16, 9), None),
+        Preset::new("YouTube thumbnail", (16, 9), Some((1280, 720))),
+        Preset::new("Avatar", (1, 1), Some((512, 512))),
+    ]
+}
+
+/// Finds a preset by name, ignoring case
+pub fn find_preset<'a>(presets: &'a [Preset], name: &str) -> Option<&'a Preset> {
+    presets
+        .iter()
+        .find(|preset| preset.name.eq_ignore_ascii_case(name.trim()))
+}
+
+/// Parses `WIDTH:HEIGHT` where both are positive
+fn parse_ratio(ratio: &str) -> Option<(u32, u32)> {
+    let (width, height) = ratio.split_once(':')?;
+    let parse_side = |side: &str| side.trim().parse::<u32>().ok().filter(|side| *side > 0);
+
+    Some((parse_side(width)?, parse_side(height)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(ratio: Option<&str>, size: Option<&str>) -> PresetConfig {
+        PresetConfig {
+            name: "Test".to_string(),
+            ratio: ratio.map(str::to_string),
+            size: size.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn ratios_are_parsed() {
+        assert_eq!(parse_ratio("16:9"), Some((16, 9)));
+        assert_eq!(parse_ratio(" 4 : 3 "), Some((4, 3)));
+        assert_eq!(parse_ratio("0:1"), None);
+        assert_eq!(parse_ratio("16x9"), None);
+        assert_eq!(parse_ratio("a:b"), None);
+    }
+
+    #[test]
+    fn presets_round_trip_through_the_config() {
+        for preset in default_presets() {
+            let config = PresetConfig::from(preset.clone());
+            assert_eq!(Preset::try_from(config), Ok(preset));
+        }
+    }
+
+    #[test]
+    fn size_without_ratio_gives_the_ratio() {
+        let preset = Preset::try_from(config(None, Some("1280x720"))).unwrap();
+        assert_eq!(preset.ratio, (1280, 720));
+        assert_eq!(
+            preset.size,
+            Some(OutputSize::Exact {
+                width: Some(1280),
+                height: Some(720)
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_presets_are_explained() {
+        assert_eq!(
+            Preset::try_from(config(Some("16-9"), None)),
+            Err("invalid ratio 16-9 for preset Test, expected WIDTH:HEIGHT".to_string())
+        );
+        assert_eq!(
+            Preset::try_from(config(Some("1:1"), Some("big"))),
+            Err("invalid size big for preset Test, expected WIDTHxHEIGHT".to_string())
+        );
+        assert_eq!(
+            Preset::try_from(config(None, Some("1280x"))),
+            Err("preset Test needs a ratio or a WIDTHxHEIGHT size".to_string())
+        );
+    }
+
+    #[test]
+    fn crop_is_centered_in_the_region() {
+        let square = Preset::new("Square", (1, 1), None);
+        assert_eq!(square.crop(10., 20., 200., 100.), (60., 20., 100., 100.));
+        let wide = Preset::new("Wide", (16, 9), None);
+        assert_eq!(wide.crop(0., 0., 100., 100.), (0., 22., 100., 56.));
+    }
+
+    #[test]
+    fn empty_region_gives_an_empty_crop() {
+        let square = Preset::new("Square", (1, 1), None);
+        assert_eq!(square.crop(5., 5., 0., 100.), (5., 5., 0., 0.));
+        assert_eq!(square.crop(5., 5., 0., 0.), (5., 5., 0., 0.));
+        assert_eq!(square.crop(5., 5., -10., 100.), (5., 5., 0., 0.));
+    }
+}
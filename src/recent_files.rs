@@ -0,0 +1,94 @@
+use std::path::{Path, PathBuf};
+
+const MAX_RECENT_FILES: usize = 10;
+
+fn recent_files_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("basicrop").join("recent-files"))
+}
+
+/// Loads the recently opened images, most recent first
+pub fn load() -> Vec<PathBuf> {
+    let Some(path) = recent_files_path() else {
+        return vec![];
+    };
+
+    match std::fs::read_to_string(path) {
+        Ok(contents) => contents
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(PathBuf::from)
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// Puts the image first in the list, dropping its older entry and the oldest
+/// images past the limit
+fn push_recent(mut recent_files: Vec<PathBuf>, image_path: PathBuf) -> Vec<PathBuf> {
+    recent_files.retain(|recent| recent != &image_path);
+    recent_files.insert(0, image_path);
+    recent_files.truncate(MAX_RECENT_FILES);
+    recent_files
+}
+
+/// Moves the image to the top of the recent files list and persists it
+pub fn add(image_path: &Path) {
+    let Some(path) = recent_files_path() else {
+        eprintln!("warn: can't find config directory for recent files");
+        return;
+    };
+
+    let image_path = image_path
+        .canonicalize()
+        .unwrap_or_else(|_| image_path.to_path_buf());
+    let recent_files = push_recent(load(), image_path);
+
+    let contents = recent_files
+        .iter()
+        .filter_map(|recent| recent.to_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let saved = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&path, contents));
+
+    if let Err(error) = saved {
+        eprintln!("warn: failed to save recent files: {:?}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn latest_image_comes_first() {
+        let recent_files = push_recent(paths(&["b.png", "c.png"]), PathBuf::from("a.png"));
+        assert_eq!(recent_files, paths(&["a.png", "b.png", "c.png"]));
+    }
+
+    #[test]
+    fn reopened_image_moves_to_the_top_once() {
+        let recent_files = push_recent(paths(&["a.png", "b.png", "c.png"]), PathBuf::from("c.png"));
+        assert_eq!(recent_files, paths(&["c.png", "a.png", "b.png"]));
+    }
+
+    #[test]
+    fn oldest_images_are_dropped_past_the_limit() {
+        let mut recent_files = vec![];
+        for i in 0..MAX_RECENT_FILES + 3 {
+            recent_files = push_recent(recent_files, PathBuf::from(format!("{i}.png")));
+        }
+        assert_eq!(recent_files.len(), MAX_RECENT_FILES);
+        assert_eq!(
+            recent_files.first(),
+            Some(&PathBuf::from(format!("{}.png", MAX_RECENT_FILES + 2)))
+        );
+        assert_eq!(recent_files.last(), Some(&PathBuf::from("3.png")));
+    }
+}
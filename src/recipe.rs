@@ -0,0 +1,223 @@
+use crate::anchor::Anchor;
+use crate::canvas_fill::CanvasFill;
+use crate::crop_shape::CropShape;
+use crate::image_crop::FinalizedImageCrop;
+use crate::image_transform::ImageTransform;
+use crate::output_size::OutputResize;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// A crop with its rotation, shape and resize settings saved to a file, for
+/// cropping a series of images the same way
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Recipe {
+    /// Size of the rotated image the crop was made on
+    pub image_width: u32,
+    pub image_height: u32,
+    pub crop: FinalizedImageCrop,
+    pub transform: ImageTransform,
+    pub shape: CropShape,
+    pub fill: CanvasFill,
+    pub resize: OutputResize,
+    #[serde(default)]
+    pub on_size_mismatch: SizeMismatch,
+    /// Where the crop stays when anchoring it in an image of another size
+    #[serde(default)]
+    pub anchor: Anchor,
+}
+
+/// What to do with an image whose size differs from the recipe's
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SizeMismatch {
+    /// Leaves the image out
+    #[default]
+    Skip,
+    /// Scales the crop along with the image, keeping its aspect ratio
+    Scale,
+    /// Keeps the size of the crop and its offset from the anchor
+    Anchor,
+}
+
+impl SizeMismatch {
+    /// Parses the value of `--on-size-mismatch`, `skip`, `scale` or
+    /// `anchor`
+    pub fn parse(value: &str) -> Option<SizeMismatch> {
+        match value {
+            "skip" => Some(SizeMismatch::Skip),
+            "scale" => Some(SizeMismatch::Scale),
+            "anchor" => Some(SizeMismatch::Anchor),
+            _ => None,
+        }
+    }
+}
+
+impl Recipe {
+    pub fn load(path: &Path) -> Result<Recipe, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|error| format!("failed to read recipe {}: {}", path.display(), error))?;
+        let recipe: Recipe = serde_json::from_str(&contents)
+            .map_err(|error| format!("invalid recipe {}: {}", path.display(), error))?;
+        if recipe.image_width == 0 || recipe.image_height == 0 {
+            return Err(format!(
+                "invalid recipe {}: image size {}x{} is empty",
+                path.display(),
+                recipe.image_width,
+                recipe.image_height
+            ));
+        }
+        if !recipe.resize.size.is_valid() {
+            return Err(format!(
+                "invalid recipe {}: output size out of range",
+                path.display()
+            ));
+        }
+
+        Ok(recipe)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(self).map_err(|error| error.to_string())?;
+        std::fs::write(path, contents + "\n").map_err(|error| error.to_string())
+    }
+
+    /// The crop for a rotated image of the given size, none when it should
+    /// be skipped
+    pub fn crop_for(&self, width: u32, height: u32) -> Option<FinalizedImageCrop> {
+        if (width, height) == (self.image_width, self.image_height) {
+            return Some(self.crop.clone());
+        }
+
+        let crop = &self.crop;
+        match self.on_size_mismatch {
+            SizeMismatch::Skip => None,
+            SizeMismatch::Scale => {
+                // One factor keeps the crop's aspect ratio, the recipe's
+                // image is scaled to fit and centered in the other one
+                let scale = (width as f32 / self.image_width as f32)
+                    .min(height as f32 / self.image_height as f32);
+                let offset_x = (width as f32 - self.image_width as f32 * scale) / 2.;
+                let offset_y = (height as f32 - self.image_height as f32 * scale) / 2.;
+                Some(FinalizedImageCrop {
+                    crop_x: (crop.crop_x as f32 * scale + offset_x).round() as i32,
+                    crop_y: (crop.crop_y as f32 * scale + offset_y).round() as i32,
+                    width: ((crop.width as f32 * scale).round() as u32).max(1),
+                    height: ((crop.height as f32 * scale).round() as u32).max(1),
+                })
+            }
+            SizeMismatch::Anchor => {
+                let (fraction_x, fraction_y) = self.anchor.fractions();
+                let offset_x = (width as f32 - self.image_width as f32) * fraction_x;
+                let offset_y = (height as f32 - self.image_height as f32) * fraction_y;
+                Some(FinalizedImageCrop {
+                    crop_x: crop.crop_x + offset_x.round() as i32,
+                    crop_y: crop.crop_y + offset_y.round() as i32,
+                    width: crop.width,
+                    height: crop.height,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output_size::OutputSize;
+
+    fn crop(crop_x: i32, crop_y: i32, width: u32, height: u32) -> FinalizedImageCrop {
+        FinalizedImageCrop {
+            crop_x,
+            crop_y,
+            width,
+            height,
+        }
+    }
+
+    /// A 100x50 crop at 20, 10 in a 200x100 image
+    fn recipe(on_size_mismatch: SizeMismatch, anchor: Anchor) -> Recipe {
+        Recipe {
+            image_width: 200,
+            image_height: 100,
+            crop: crop(20, 10, 100, 50),
+            transform: ImageTransform::default(),
+            shape: CropShape::default(),
+            fill: CanvasFill::default(),
+            resize: OutputResize::default(),
+            on_size_mismatch,
+            anchor,
+        }
+    }
+
+    #[test]
+    fn image_of_the_same_size_keeps_the_crop() {
+        for on_size_mismatch in [
+            SizeMismatch::Skip,
+            SizeMismatch::Scale,
+            SizeMismatch::Anchor,
+        ] {
+            let recipe = recipe(on_size_mismatch, Anchor::Center);
+            assert_eq!(recipe.crop_for(200, 100), Some(crop(20, 10, 100, 50)));
+        }
+    }
+
+    #[test]
+    fn mismatched_image_is_skipped() {
+        let recipe = recipe(SizeMismatch::Skip, Anchor::default());
+        assert_eq!(recipe.crop_for(400, 200), None);
+    }
+
+    #[test]
+    fn crop_is_scaled_with_the_image() {
+        let recipe = recipe(SizeMismatch::Scale, Anchor::default());
+        assert_eq!(recipe.crop_for(400, 200), Some(crop(40, 20, 200, 100)));
+        // A taller image fits the recipe's width, centered vertically
+        assert_eq!(recipe.crop_for(200, 200), Some(crop(20, 60, 100, 50)));
+    }
+
+    #[test]
+    fn crop_keeps_its_offset_from_the_anchor() {
+        let top_left = recipe(SizeMismatch::Anchor, Anchor::TopLeft);
+        assert_eq!(top_left.crop_for(300, 150), Some(crop(20, 10, 100, 50)));
+        let center = recipe(SizeMismatch::Anchor, Anchor::Center);
+        assert_eq!(center.crop_for(300, 150), Some(crop(70, 35, 100, 50)));
+        let bottom_right = recipe(SizeMismatch::Anchor, Anchor::BottomRight);
+        assert_eq!(
+            bottom_right.crop_for(100, 50),
+            Some(crop(-80, -40, 100, 50))
+        );
+    }
+
+    #[test]
+    fn recipes_are_checked_when_loaded() {
+        let dir = std::env::temp_dir().join("basicrop-recipe-load");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("recipe.json");
+
+        let valid = recipe(SizeMismatch::Scale, Anchor::Center);
+        valid.save(&path).unwrap();
+        assert_eq!(Recipe::load(&path).unwrap().crop, valid.crop);
+
+        let empty = Recipe {
+            image_height: 0,
+            ..valid.clone()
+        };
+        empty.save(&path).unwrap();
+        let error = Recipe::load(&path).unwrap_err();
+        assert!(error.ends_with("image size 200x0 is empty"), "{error}");
+
+        let too_large = Recipe {
+            resize: OutputResize {
+                size: OutputSize::Fit {
+                    width: 100000,
+                    height: 100000,
+                },
+                ..valid.resize
+            },
+            ..valid
+        };
+        too_large.save(&path).unwrap();
+        let error = Recipe::load(&path).unwrap_err();
+        assert!(error.ends_with("output size out of range"), "{error}");
+    }
+}
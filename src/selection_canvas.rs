@@ -1,213 +1,697 @@
-use crate::counter_input::CounterView;
-use crate::image_crop::ImageCrop;
-use crate::misc::{CroppingMousePosition, LoadingImage};
+use crate::config::OverlayConfig;
+use crate::crop_model::CropModel;
+use crate::crop_shape::CropShape;
+use crate::grid_slice::GridSlice;
+use crate::guides::Guides;
+use crate::image_crop::{
+    CropRegion, FinalizedImageCrop, InitializedImageCrop, PolygonCrop, snap_to_pixel,
+};
+use crate::misc::LoadingImage;
+use crate::viewport_background::ViewportBackground;
 use gpui::{
-    BorderStyle, Bounds, Entity, IntoElement, PathBuilder, Pixels, Point, Size, Styled, canvas,
-    point, px, quad, rgba, size,
+    BorderStyle, Bounds, Corners, Entity, IntoElement, PathBuilder, Pixels, Point, Size, Styled,
+    canvas, point, px, quad, rgb, rgba,
 };
 
+/// Space around the image for extending the canvas, relative to the image's
+/// longest side
+const CANVAS_MARGIN: f32 = 0.08;
+
+/// Size of the checkerboard squares drawn over the extended canvas
+const CHECKERBOARD_SIZE: f32 = 8.;
+
+/// Smallest size of a grid cell in the window for drawing the grid
+const MIN_GRID_CELL_SIZE: f32 = 3.;
+
+/// Size of the handles drawn on polygon vertices
+const VERTEX_HANDLE_SIZE: f32 = 7.;
+
+/// Where the image was last painted in the window, for mapping mouse
+/// positions outside of painting. Positions are in logical pixels of the
+/// window, which are `scale_factor` physical pixels of the display
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CanvasView {
+    pub bounds: Bounds<Pixels>,
+    /// The image and the margin around it, where a crop can be selected
+    pub view_bounds: Bounds<Pixels>,
+    /// Where the image's top left corner is
+    pub image_origin: Point<Pixels>,
+    /// Logical pixels of the window per pixel of the image
+    pub scale: f32,
+    pub scale_factor: f32,
+}
+
+impl CanvasView {
+    /// Fits the region of the image between `view_left`, `view_top`,
+    /// `view_right` and `view_bottom` in image coordinates to the middle of
+    /// the canvas bounds, on a display with `scale_factor` physical pixels
+    /// per logical one
+    pub fn fit(
+        bounds: Bounds<Pixels>,
+        scale_factor: f32,
+        view_left: f32,
+        view_top: f32,
+        view_right: f32,
+        view_bottom: f32,
+    ) -> CanvasView {
+        let (view_width, view_height) = (view_right - view_left, view_bottom - view_top);
+        let scale = f32::from(bounds.size.width / view_width)
+            .min(f32::from(bounds.size.height / view_height));
+        let padding_x = (bounds.size.width - px(view_width * scale)) / 2.;
+        let padding_y = (bounds.size.height - px(view_height * scale)) / 2.;
+        let view_origin = bounds.origin + point(padding_x, padding_y);
+
+        CanvasView {
+            bounds,
+            view_bounds: Bounds::new(
+                view_origin,
+                Size {
+                    width: px(view_width * scale),
+                    height: px(view_height * scale),
+                },
+            ),
+            image_origin: view_origin - point(px(view_left), px(view_top)) * scale,
+            scale,
+            scale_factor,
+        }
+    }
+
+    /// The position of the physical pixel boundary nearest to a position in
+    /// the window
+    fn snap_to_physical_pixel(&self, position: Point<Pixels>) -> Point<Pixels> {
+        let snap =
+            |value: Pixels| px((f32::from(value) * self.scale_factor).round() / self.scale_factor);
+        point(snap(position.x), snap(position.y))
+    }
+
+    pub fn image_position(&self, position: Point<Pixels>) -> Point<Pixels> {
+        (position - self.image_origin) / self.scale
+    }
+
+    pub fn window_position(&self, position: Point<Pixels>) -> Point<Pixels> {
+        self.image_origin + position * self.scale
+    }
+
+    /// The pixel boundary of the image nearest to a position in the window,
+    /// clamped to where a crop can be selected
+    pub fn image_pixel(&self, position: Point<Pixels>) -> Point<i32> {
+        let view_corner = self.view_bounds.bottom_right();
+        let position = self.image_position(point(
+            position.x.max(self.view_bounds.origin.x).min(view_corner.x),
+            position.y.max(self.view_bounds.origin.y).min(view_corner.y),
+        ));
+        point(
+            snap_to_pixel(f32::from(position.x)),
+            snap_to_pixel(f32::from(position.y)),
+        )
+    }
+
+    /// Where a pixel boundary of the image is in the window
+    pub fn pixel_window_position(&self, pixel: Point<i32>) -> Point<Pixels> {
+        self.window_position(point(px(pixel.x as f32), px(pixel.y as f32)))
+    }
+
+    /// Where a crop of the image is in the window
+    pub fn crop_bounds(&self, crop: &InitializedImageCrop) -> Bounds<Pixels> {
+        Bounds::from_corners(
+            self.pixel_window_position(point(crop.crop_x, crop.crop_y)),
+            self.pixel_window_position(point(crop.crop_x + crop.width, crop.crop_y + crop.height)),
+        )
+    }
+
+    /// Where a crop of the image is painted, its corners moved to the
+    /// nearest physical pixel so outlines are sharp and line up with the
+    /// image painted the same way
+    pub fn painted_bounds(&self, crop: &InitializedImageCrop) -> Bounds<Pixels> {
+        let crop_bounds = self.crop_bounds(crop);
+        Bounds::from_corners(
+            self.snap_to_physical_pixel(crop_bounds.origin),
+            self.snap_to_physical_pixel(crop_bounds.bottom_right()),
+        )
+    }
+
+    /// Index of the polygon vertex under a position in the window
+    pub fn vertex_at(&self, polygon_crop: &PolygonCrop, position: Point<Pixels>) -> Option<usize> {
+        polygon_crop.points.iter().position(|vertex| {
+            let offset = self.window_position(*vertex) - position;
+            f32::from(offset.x).abs() <= VERTEX_HANDLE_SIZE
+                && f32::from(offset.y).abs() <= VERTEX_HANDLE_SIZE
+        })
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn selection_canvas(
-    crop_x: Entity<CounterView>,
-    crop_y: Entity<CounterView>,
-    width: Entity<CounterView>,
-    height: Entity<CounterView>,
     image_asset: LoadingImage,
-    image_crop: Entity<ImageCrop>,
-    is_selecting: Entity<bool>,
-    mouse_pos: Entity<CroppingMousePosition>,
-    mouse_initial_pos: Entity<Point<Pixels>>,
+    image_crop: Entity<CropModel>,
+    is_straightening: Entity<bool>,
+    crop_shape: Entity<CropShape>,
+    polygon_crop: Entity<PolygonCrop>,
+    is_drawing_polygon: Entity<bool>,
+    canvas_view: Entity<Option<CanvasView>>,
+    crop_regions: Entity<Vec<CropRegion>>,
+    is_slicing: Entity<bool>,
+    grid_slice: Entity<GridSlice>,
+    guides: Entity<Guides>,
+    background: Entity<ViewportBackground>,
+    overlay: OverlayConfig,
 ) -> impl IntoElement + Styled {
     canvas(
-        |_, _, _| {},
-        move |bounds, _, window, cx| {
-            // println!("Origin {{ x: {}, y: {} }}", bounds.origin.x, bounds.origin.y);
-            // println!("Bounds {{ width: {}, height: {} }}", bounds.size.width, bounds.size.height);
-            let image = match image_asset.get_image() {
-                Some(image) => image,
-                None => {
-                    return;
+        {
+            let image_asset = image_asset.clone();
+            let image_crop = image_crop.clone();
+            let is_straightening = is_straightening.clone();
+            let is_drawing_polygon = is_drawing_polygon.clone();
+            let crop_regions = crop_regions.clone();
+            move |bounds, window, cx| {
+                let image_size = image_asset.get_image()?.size(0);
+                let is_selecting = image_crop.read(cx).drag().is_some()
+                    && !*is_straightening.read(cx)
+                    && !*is_drawing_polygon.read(cx);
+
+                // The visible region in image coordinates, the image with a
+                // margin for extending the canvas, grown to fit the crop and
+                // regions past it
+                let image_width = i32::from(image_size.width) as f32;
+                let image_height = i32::from(image_size.height) as f32;
+                let margin = image_width.max(image_height) * CANVAS_MARGIN;
+                let (mut view_left, mut view_top) = (-margin, -margin);
+                let (mut view_right, mut view_bottom) =
+                    (image_width + margin, image_height + margin);
+                if !is_selecting {
+                    let current_crop = image_crop.read(cx).crop().to_initialized();
+                    let region_crops = crop_regions
+                        .read(cx)
+                        .iter()
+                        .filter_map(|region| region.image_crop.to_initialized());
+                    for crop in region_crops.chain(current_crop) {
+                        view_left = view_left.min(crop.crop_x as f32);
+                        view_top = view_top.min(crop.crop_y as f32);
+                        view_right = view_right.max((crop.crop_x + crop.width) as f32);
+                        view_bottom = view_bottom.max((crop.crop_y + crop.height) as f32);
+                    }
                 }
+
+                // Everything is painted through the same mapping that maps
+                // the mouse back to image pixels, it's kept for the mouse
+                // handlers without redrawing
+                let new_canvas_view = CanvasView::fit(
+                    bounds,
+                    window.scale_factor(),
+                    view_left,
+                    view_top,
+                    view_right,
+                    view_bottom,
+                );
+                canvas_view.update(cx, |canvas_view, _| *canvas_view = Some(new_canvas_view));
+                Some(new_canvas_view)
+            }
+        },
+        move |_, new_canvas_view, window, cx| {
+            let (Some(image), Some(new_canvas_view)) = (image_asset.get_image(), new_canvas_view)
+            else {
+                return;
             };
 
             let image_size = image.size(0);
-            let is_selecting_value = *is_selecting.read(cx);
-
-            // Calculate current location of image in viewport relative to
-            // the canvas bounds
-            let image_width = i32::from(image_size.width) as f32;
-            let image_height = i32::from(image_size.height) as f32;
-            let viewport_aspect_ratio = bounds.size.width / bounds.size.height;
-            let image_aspect_ratio = image_width / image_height;
-            let image_visible_scale = if viewport_aspect_ratio > image_aspect_ratio {
-                f32::from(bounds.size.height / image_height)
-            } else {
-                f32::from(bounds.size.width / image_width)
-            };
-            let image_visible_scale_inverse = 1. / image_visible_scale;
-            let image_visible_width = px(image_width * image_visible_scale);
-            let image_visible_height = px(image_height * image_visible_scale);
-
-            // We need this because only the canvas' height might not match the
-            // image's height, but the width will always match
-            let bounds_padding_x = (image_visible_width.max(bounds.size.width)
-                - image_visible_width.min(bounds.size.width))
-                / 2.;
-            let bounds_padding_y = (image_visible_height.max(bounds.size.height)
-                - image_visible_height.min(bounds.size.height))
-                / 2.;
-
-            // If we're selecting then we want to base coordinates off of
-            // the mouse, otherwise we want to use image_crop
-            let (mouse_initial, mouse_cur) = if is_selecting_value {
-                let mouse_initial_pos = *mouse_initial_pos.read(cx);
-                let mouse_pos = match mouse_pos.read(cx) {
-                    CroppingMousePosition::Initial(pos) => *pos + bounds.origin,
-                    CroppingMousePosition::Moved(pos) => *pos,
-                };
-
-                (
-                    point(
-                        (mouse_initial_pos.x + bounds.origin.x)
-                            .max(bounds.origin.x + bounds_padding_x)
-                            .min(bounds.origin.x + bounds_padding_x + image_visible_width),
-                        (mouse_initial_pos.y + bounds.origin.y)
-                            .max(bounds.origin.y + bounds_padding_y)
-                            .min(bounds.origin.y + bounds_padding_y + image_visible_height),
-                    ),
-                    point(
-                        mouse_pos
-                            .x
-                            .max(bounds.origin.x + bounds_padding_x)
-                            .min(bounds.origin.x + bounds_padding_x + image_visible_width),
-                        mouse_pos
-                            .y
-                            .max(bounds.origin.y + bounds_padding_y)
-                            .min(bounds.origin.y + bounds_padding_y + image_visible_height),
-                    ),
-                )
-            } else {
-                let image_visible_scale = image_visible_scale;
-                match *image_crop.read(cx) {
-                    ImageCrop::Cropped {
-                        crop_x,
-                        crop_y,
-                        width,
-                        height,
-                    } => (
-                        point(
-                            bounds.origin.x + crop_x * image_visible_scale + bounds_padding_x,
-                            bounds.origin.y + crop_y * image_visible_scale + bounds_padding_y,
-                        ),
-                        point(
-                            bounds.origin.x
-                                + (crop_x + width) * image_visible_scale
-                                + bounds_padding_x,
-                            bounds.origin.y
-                                + (crop_y + height) * image_visible_scale
-                                + bounds_padding_y,
-                        ),
-                    ),
-                    _ => (point(px(0.), px(0.)), point(px(0.), px(0.))),
-                }
-            };
+            // Dragging while straightening draws a guide line and while
+            // drawing a polygon moves its vertices instead of selecting a
+            // crop
+            let is_straightening_value = *is_straightening.read(cx);
+            let is_drawing_polygon_value = *is_drawing_polygon.read(cx);
+            let drag = image_crop.read(cx).drag();
+            let is_selecting_value =
+                drag.is_some() && !is_straightening_value && !is_drawing_polygon_value;
+            let region_crops: Vec<_> = crop_regions
+                .read(cx)
+                .iter()
+                .filter_map(|region| region.image_crop.to_initialized())
+                .collect();
 
-            let origin = point(
-                mouse_initial.x.min(mouse_cur.x),
-                mouse_initial.y.min(mouse_cur.y),
-            );
+            let view_bounds = new_canvas_view.view_bounds;
+            let image_bounds = new_canvas_view.painted_bounds(&InitializedImageCrop {
+                crop_x: 0,
+                crop_y: 0,
+                width: i32::from(image_size.width),
+                height: i32::from(image_size.height),
+            });
 
-            let se_corner = point(
-                mouse_initial.x.max(mouse_cur.x),
-                mouse_initial.y.max(mouse_cur.y),
-            );
+            // The light checkerboard marks where the canvas is extended past
+            // the image
+            paint_checkerboard(window, view_bounds, (0xffffff, 0xd8d8d8));
+            paint_checkerboard(window, image_bounds, background.read(cx).colors());
+            if let Err(error) =
+                window.paint_image(image_bounds, Corners::default(), image, 0, false)
+            {
+                eprintln!("error: failed to paint image: {:?}", error);
+            }
 
-            let image_crop_x_value =
-                (origin.x - bounds.origin.x - bounds_padding_x) * image_visible_scale_inverse;
-            let image_crop_y_value =
-                (origin.y - bounds.origin.y - bounds_padding_y) * image_visible_scale_inverse;
-            let image_width_value = (se_corner.x - origin.x) * image_visible_scale_inverse;
-            let image_height_value = (se_corner.y - origin.y) * image_visible_scale_inverse;
+            if is_drawing_polygon_value {
+                let vertices: Vec<_> = polygon_crop
+                    .read(cx)
+                    .points
+                    .iter()
+                    .map(|vertex| new_canvas_view.window_position(*vertex))
+                    .collect();
+                paint_polygon(
+                    window,
+                    &overlay,
+                    view_bounds,
+                    &vertices,
+                    polygon_crop.read(cx).is_complete(),
+                );
+                return;
+            }
+
+            // The crop is selected by dragging on the canvas, which writes
+            // it to the model
+            let crop = image_crop.read(cx).crop().to_final();
+            let crop_bounds = match image_crop.read(cx).crop().to_initialized() {
+                Some(crop) => new_canvas_view.painted_bounds(&crop),
+                None => Bounds::default(),
+            };
+            let (origin, se_corner) = (crop_bounds.origin, crop_bounds.bottom_right());
+
+            // Outline of the crop's shape in the window
+            let shape_outline: Vec<_> = crop_shape
+                .read(cx)
+                .outline(
+                    f32::from(crop_bounds.size.width),
+                    f32::from(crop_bounds.size.height),
+                    new_canvas_view.scale,
+                )
+                .into_iter()
+                .map(|(x, y)| origin + point(px(x), px(y)))
+                .collect();
 
             // let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
             // println!("[{}] in canvas", time.as_millis());
 
-            if *is_selecting.read(cx) {
-                let size = Size {
-                    width: se_corner.x - origin.x,
-                    height: se_corner.y - origin.y,
-                };
-                let quad_bounds = Bounds::new(origin, size);
+            if is_selecting_value {
                 window.paint_quad(quad(
-                    quad_bounds,
+                    crop_bounds,
                     px(0.),
-                    rgba(0x709ebe7f),
+                    overlay.selection.to_rgba(),
                     px(1.),
-                    rgba(0x709ebeaf),
+                    overlay.selection_border.to_rgba(),
                     BorderStyle::default(),
                 ));
 
-                let new_image_crop = ImageCrop::Cropped {
-                    crop_x: image_crop_x_value,
-                    crop_y: image_crop_y_value,
-                    width: image_width_value,
-                    height: image_height_value,
-                };
-                if &new_image_crop != image_crop.read(cx) {
-                    // println!("Updated crop: {:?}", new_image_crop);
-                    image_crop.write(cx, new_image_crop);
+                let mut builder = PathBuilder::stroke(px(1.));
+                builder.add_polygon(&shape_outline, true);
+                if let Ok(path) = builder.build() {
+                    window.paint_path(path, rgba(0xffffffdf));
                 }
             } else {
-                let occlusion_bounds = gpui::bounds(
-                    point(
-                        bounds.origin.x + bounds_padding_x,
-                        bounds.origin.y + bounds_padding_y,
-                    ),
-                    size(
-                        bounds.size.width - bounds_padding_x * 2.,
-                        bounds.size.height - bounds_padding_y * 2.,
-                    ),
-                );
                 let mut builder = PathBuilder::fill();
-                builder.move_to(occlusion_bounds.origin);
-                builder.line_to(occlusion_bounds.top_right());
-                builder.line_to(occlusion_bounds.bottom_right());
-                builder.line_to(occlusion_bounds.bottom_left());
-                builder.close();
-                builder.move_to(origin);
-                builder.line_to(point(se_corner.x, origin.y));
-                builder.line_to(point(se_corner.x, se_corner.y));
-                builder.line_to(point(origin.x, se_corner.y));
+                builder.move_to(view_bounds.origin);
+                builder.line_to(view_bounds.top_right());
+                builder.line_to(view_bounds.bottom_right());
+                builder.line_to(view_bounds.bottom_left());
                 builder.close();
+                builder.add_polygon(&shape_outline, true);
                 let path = builder.build().unwrap();
-                window.paint_path(path, rgba(0x000000c8));
+                window.paint_path(path, overlay.occlusion.to_rgba());
             }
 
-            if *is_selecting.read(cx) {
-                crop_x.update(cx, |input, cx| {
-                    input.get_state().update(cx, |input, cx| {
-                        let value = u32::from(image_crop_x_value).to_string();
-                        input.set_value(value, window, cx);
-                    });
-                });
-                crop_y.update(cx, |input, cx| {
-                    input.get_state().update(cx, |input, cx| {
-                        let value = u32::from(image_crop_y_value).to_string();
-                        input.set_value(value, window, cx);
-                    });
-                });
-                width.update(cx, |input, cx| {
-                    input.get_state().update(cx, |input, cx| {
-                        let value = u32::from(image_width_value).to_string();
-                        input.set_value(value, window, cx);
-                    });
-                });
-                height.update(cx, |input, cx| {
-                    input.get_state().update(cx, |input, cx| {
-                        let value = u32::from(image_height_value).to_string();
-                        input.set_value(value, window, cx);
-                    });
-                });
+            let fractions = guides.read(cx).fractions();
+            if !fractions.is_empty() {
+                let mut builder = PathBuilder::stroke(px(1.));
+                for fraction in fractions {
+                    let x = origin.x + (se_corner.x - origin.x) * *fraction;
+                    let y = origin.y + (se_corner.y - origin.y) * *fraction;
+                    builder.move_to(point(x, origin.y));
+                    builder.line_to(point(x, se_corner.y));
+                    builder.move_to(point(origin.x, y));
+                    builder.line_to(point(se_corner.x, y));
+                }
+                if let Ok(path) = builder.build() {
+                    window.paint_path(path, rgba(0xffffff9f));
+                }
+            }
+
+            if *is_slicing.read(cx)
+                && let Some(grid_crop) = crop
+            {
+                paint_grid(&new_canvas_view, grid_slice.read(cx), &grid_crop, window);
+            }
+
+            // Regions added for exporting, over the occlusion so they stay
+            // visible while selecting the next one
+            for crop in &region_crops {
+                window.paint_quad(quad(
+                    new_canvas_view.painted_bounds(crop),
+                    px(0.),
+                    rgba(0xf0a03018),
+                    px(1.5),
+                    rgba(0xf0a030ff),
+                    BorderStyle::default(),
+                ));
+            }
+
+            if is_straightening_value && let Some(drag) = drag {
+                let mut builder = PathBuilder::stroke(px(2.));
+                builder.move_to(drag.start);
+                builder.line_to(drag.position);
+                if let Ok(path) = builder.build() {
+                    window.paint_path(path, rgba(0xffd54aff));
+                }
             }
         },
     )
 }
+
+/// Paints the edges of a grid's cells as lines across the whole grid, one
+/// for each side of the columns and rows, leaving out grids too fine to
+/// tell the cells apart
+fn paint_grid(
+    canvas_view: &CanvasView,
+    grid_slice: &GridSlice,
+    crop: &FinalizedImageCrop,
+    window: &mut gpui::Window,
+) {
+    let (columns, rows, cell_width, cell_height) = grid_slice.dimensions(crop);
+    if columns == 0 || cell_width.min(cell_height) as f32 * canvas_view.scale < MIN_GRID_CELL_SIZE {
+        return;
+    }
+
+    // Sides of the cells in the image, a side shared by neighbouring cells
+    // without spacing between them is only drawn once
+    let edges = |start: i32, count: u32, side: u32| {
+        let start = start as f64 + grid_slice.margin as f64;
+        let step = side as f64 + grid_slice.spacing as f64;
+        let mut edges = vec![];
+        for index in 0..count {
+            let edge = start + index as f64 * step;
+            if index == 0 || grid_slice.spacing > 0 {
+                edges.push(edge);
+            }
+            edges.push(edge + side as f64);
+        }
+        edges
+    };
+    let window_position = |x: f64, y: f64| {
+        canvas_view
+            .snap_to_physical_pixel(canvas_view.window_position(point(px(x as f32), px(y as f32))))
+    };
+    let vertical_edges = edges(crop.crop_x, columns, cell_width);
+    let horizontal_edges = edges(crop.crop_y, rows, cell_height);
+    let (left, right) = (vertical_edges[0], vertical_edges[vertical_edges.len() - 1]);
+    let (top, bottom) = (
+        horizontal_edges[0],
+        horizontal_edges[horizontal_edges.len() - 1],
+    );
+
+    let mut builder = PathBuilder::stroke(px(1.));
+    for x in vertical_edges {
+        builder.move_to(window_position(x, top));
+        builder.line_to(window_position(x, bottom));
+    }
+    for y in horizontal_edges {
+        builder.move_to(window_position(left, y));
+        builder.line_to(window_position(right, y));
+    }
+    if let Ok(path) = builder.build() {
+        window.paint_path(path, rgba(0xffffffcf));
+    }
+}
+
+/// Paints the outline and vertex handles of a polygon, darkening the area
+/// outside it once it's closed
+fn paint_polygon(
+    window: &mut gpui::Window,
+    overlay: &OverlayConfig,
+    occlusion_bounds: Bounds<Pixels>,
+    vertices: &[Point<Pixels>],
+    is_complete: bool,
+) {
+    if is_complete {
+        let mut builder = PathBuilder::fill();
+        builder.move_to(occlusion_bounds.origin);
+        builder.line_to(occlusion_bounds.top_right());
+        builder.line_to(occlusion_bounds.bottom_right());
+        builder.line_to(occlusion_bounds.bottom_left());
+        builder.close();
+        builder.add_polygon(vertices, true);
+        if let Ok(path) = builder.build() {
+            window.paint_path(path, overlay.occlusion.to_rgba());
+        }
+    }
+
+    if vertices.len() >= 2 {
+        let mut builder = PathBuilder::stroke(px(1.5));
+        builder.add_polygon(vertices, is_complete);
+        if let Ok(path) = builder.build() {
+            window.paint_path(path, rgba(0x709ebeff));
+        }
+    }
+
+    for vertex in vertices {
+        let handle_size = px(VERTEX_HANDLE_SIZE);
+        window.paint_quad(quad(
+            Bounds::new(
+                *vertex - point(handle_size / 2., handle_size / 2.),
+                Size {
+                    width: handle_size,
+                    height: handle_size,
+                },
+            ),
+            px(1.),
+            rgb(0xffffff),
+            px(1.),
+            rgb(0x709ebe),
+            BorderStyle::default(),
+        ));
+    }
+}
+
+/// Paints a checkerboard of a light and dark color, or a solid color when
+/// they're the same. Only the squares in the visible part of the window are
+/// painted, so a zoomed in image doesn't paint thousands of hidden ones
+fn paint_checkerboard(
+    window: &mut gpui::Window,
+    checkerboard_bounds: Bounds<Pixels>,
+    (light, dark): (u32, u32),
+) {
+    let visible_bounds = checkerboard_bounds.intersect(&window.content_mask().bounds);
+    if visible_bounds.is_empty() {
+        return;
+    }
+
+    window.paint_quad(quad(
+        visible_bounds,
+        px(0.),
+        rgb(light),
+        px(0.),
+        rgb(light),
+        BorderStyle::default(),
+    ));
+    if light == dark {
+        return;
+    }
+
+    // Squares stay aligned to the checkerboard's origin, the first and last
+    // visible ones are clipped to the visible bounds
+    let visible_offset = visible_bounds.origin - checkerboard_bounds.origin;
+    let visible_corner = visible_bounds.bottom_right() - checkerboard_bounds.origin;
+    let first_column = (f32::from(visible_offset.x) / CHECKERBOARD_SIZE).floor() as u32;
+    let first_row = (f32::from(visible_offset.y) / CHECKERBOARD_SIZE).floor() as u32;
+    let columns = (f32::from(visible_corner.x) / CHECKERBOARD_SIZE).ceil() as u32;
+    let rows = (f32::from(visible_corner.y) / CHECKERBOARD_SIZE).ceil() as u32;
+    for row in first_row..rows {
+        let first_dark_column = first_column + (first_column + row) % 2;
+        for column in (first_dark_column..columns).step_by(2) {
+            let square_origin = checkerboard_bounds.origin
+                + point(
+                    px(column as f32 * CHECKERBOARD_SIZE),
+                    px(row as f32 * CHECKERBOARD_SIZE),
+                );
+            let square = Bounds::new(
+                square_origin,
+                Size {
+                    width: px(CHECKERBOARD_SIZE),
+                    height: px(CHECKERBOARD_SIZE),
+                },
+            );
+            window.paint_quad(quad(
+                square.intersect(&visible_bounds),
+                px(0.),
+                rgb(dark),
+                px(0.),
+                rgb(dark),
+                BorderStyle::default(),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Scale factors of common displays, including fractional scaling
+    const SCALE_FACTORS: [f32; 7] = [1., 1.25, 1.5, 1.75, 2., 2.25, 3.];
+
+    /// The view of an image with the margin around it, as it's painted in
+    /// a canvas of the given bounds
+    fn image_view(
+        bounds: Bounds<Pixels>,
+        scale_factor: f32,
+        image_width: u32,
+        image_height: u32,
+    ) -> CanvasView {
+        let (image_width, image_height) = (image_width as f32, image_height as f32);
+        let margin = image_width.max(image_height) * CANVAS_MARGIN;
+        CanvasView::fit(
+            bounds,
+            scale_factor,
+            -margin,
+            -margin,
+            image_width + margin,
+            image_height + margin,
+        )
+    }
+
+    /// A crop between fractions of the image's width and height
+    fn crop_between(
+        fractions: [f32; 4],
+        image_width: u32,
+        image_height: u32,
+    ) -> InitializedImageCrop {
+        let [left, top, right, bottom] = fractions;
+        let (left, right) = (left.min(right), left.max(right));
+        let (top, bottom) = (top.min(bottom), top.max(bottom));
+        InitializedImageCrop::inside_edges(
+            image_width as f32 * left,
+            image_height as f32 * top,
+            image_width as f32 * right,
+            image_height as f32 * bottom,
+        )
+    }
+
+    fn is_on_physical_pixel(value: Pixels, scale_factor: f32) -> bool {
+        let physical = f32::from(value) * scale_factor;
+        (physical - physical.round()).abs() < 0.01
+    }
+
+    fn canvas_bounds() -> impl Strategy<Value = Bounds<Pixels>> {
+        (0f32..400., 0f32..400., 50f32..4000., 50f32..3000.).prop_map(|(x, y, width, height)| {
+            Bounds::new(
+                point(px(x), px(y)),
+                Size {
+                    width: px(width),
+                    height: px(height),
+                },
+            )
+        })
+    }
+
+    fn scale_factor() -> impl Strategy<Value = f32> {
+        proptest::sample::select(SCALE_FACTORS.to_vec())
+    }
+
+    proptest! {
+        #[test]
+        fn pixel_boundaries_map_back_to_themselves(
+            bounds in canvas_bounds(),
+            scale_factor in scale_factor(),
+            image_width in 1u32..10000,
+            image_height in 1u32..10000,
+            fraction_x in 0f32..=1.,
+            fraction_y in 0f32..=1.,
+        ) {
+            let view = image_view(bounds, scale_factor, image_width, image_height);
+            let pixel = point(
+                (image_width as f32 * fraction_x) as i32,
+                (image_height as f32 * fraction_y) as i32,
+            );
+
+            prop_assert_eq!(view.image_pixel(view.pixel_window_position(pixel)), pixel);
+        }
+
+        #[test]
+        fn window_positions_snap_to_the_nearest_pixel_boundary(
+            bounds in canvas_bounds(),
+            scale_factor in scale_factor(),
+            image_width in 1u32..10000,
+            image_height in 1u32..10000,
+            fraction_x in 0f32..=1.,
+            fraction_y in 0f32..=1.,
+        ) {
+            let view = image_view(bounds, scale_factor, image_width, image_height);
+            let position = view.view_bounds.origin
+                + point(
+                    view.view_bounds.size.width * fraction_x,
+                    view.view_bounds.size.height * fraction_y,
+                );
+            let pixel = view.image_pixel(position);
+            let snapped = view.pixel_window_position(pixel);
+
+            // Snapping again doesn't move it and it's within half a pixel
+            prop_assert_eq!(view.image_pixel(snapped), pixel);
+            let tolerance = view.scale / 2. + 0.01;
+            prop_assert!(f32::from(snapped.x - position.x).abs() <= tolerance);
+            prop_assert!(f32::from(snapped.y - position.y).abs() <= tolerance);
+        }
+
+        #[test]
+        fn crop_bounds_select_the_same_crop(
+            bounds in canvas_bounds(),
+            scale_factor in scale_factor(),
+            image_width in 1u32..10000,
+            image_height in 1u32..10000,
+            fractions in proptest::array::uniform4(0f32..=1.),
+        ) {
+            let view = image_view(bounds, scale_factor, image_width, image_height);
+            let crop = crop_between(fractions, image_width, image_height);
+
+            let crop_bounds = view.crop_bounds(&crop);
+            let origin = view.image_pixel(crop_bounds.origin);
+            let corner = view.image_pixel(crop_bounds.bottom_right());
+            prop_assert_eq!((origin.x, origin.y), (crop.crop_x, crop.crop_y));
+            prop_assert_eq!((corner.x - origin.x, corner.y - origin.y), (crop.width, crop.height));
+        }
+
+        #[test]
+        fn painted_crops_are_on_physical_pixels(
+            bounds in canvas_bounds(),
+            scale_factor in scale_factor(),
+            image_width in 1u32..10000,
+            image_height in 1u32..10000,
+            fractions in proptest::array::uniform4(0f32..=1.),
+        ) {
+            let view = image_view(bounds, scale_factor, image_width, image_height);
+            let crop = crop_between(fractions, image_width, image_height);
+            let image = InitializedImageCrop {
+                crop_x: 0,
+                crop_y: 0,
+                width: image_width as i32,
+                height: image_height as i32,
+            };
+
+            // Within half a physical pixel of the crop's exact bounds
+            let crop_bounds = view.crop_bounds(&crop);
+            let painted_bounds = view.painted_bounds(&crop);
+            let tolerance = 0.5 / scale_factor + 0.01;
+            for (painted, exact) in [
+                (painted_bounds.origin, crop_bounds.origin),
+                (painted_bounds.bottom_right(), crop_bounds.bottom_right()),
+            ] {
+                prop_assert!(is_on_physical_pixel(painted.x, scale_factor));
+                prop_assert!(is_on_physical_pixel(painted.y, scale_factor));
+                prop_assert!(f32::from(painted.x - exact.x).abs() <= tolerance);
+                prop_assert!(f32::from(painted.y - exact.y).abs() <= tolerance);
+            }
+
+            // Edges on the image's edges line up with the painted image
+            let image_bounds = view.painted_bounds(&image);
+            if crop.crop_x == 0 {
+                prop_assert_eq!(painted_bounds.origin.x, image_bounds.origin.x);
+            }
+            if crop.crop_y + crop.height == image.height {
+                prop_assert_eq!(painted_bounds.bottom(), image_bounds.bottom());
+            }
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use crate::canvas_fill::CanvasFill;
+use crate::crop_shape::CropShape;
+use crate::image_crop::FinalizedImageCrop;
+use crate::image_transform::ImageTransform;
+use crate::output_size::OutputResize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// The settings an image was cropped with, saved next to the source image as
+/// `{file name}.basicrop.json` so it can be cropped again from the original
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Sidecar {
+    /// SHA-256 of the source image, a sidecar of a changed image is ignored
+    pub source_hash: String,
+    /// In the rotated, flipped and straightened image
+    pub crop: FinalizedImageCrop,
+    pub transform: ImageTransform,
+    pub output: SidecarOutput,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SidecarOutput {
+    pub path: PathBuf,
+    pub shape: CropShape,
+    pub fill: CanvasFill,
+    pub resize: OutputResize,
+    pub jpeg_quality: u8,
+}
+
+pub fn sidecar_path(image_path: &Path) -> PathBuf {
+    let mut file_name = image_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".basicrop.json");
+    image_path.with_file_name(file_name)
+}
+
+/// Hex encoded SHA-256 of a file's contents
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    let bytes = std::fs::read(path)?;
+    Ok(format!("{:x}", Sha256::digest(bytes)))
+}
+
+impl Sidecar {
+    /// Reads the sidecar of a source image, none if it doesn't have one
+    pub fn load(image_path: &Path) -> Result<Option<Sidecar>, String> {
+        let path = sidecar_path(image_path);
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return Ok(None);
+        };
+
+        let sidecar: Sidecar = serde_json::from_str(&contents)
+            .map_err(|error| format!("invalid sidecar {}: {}", path.display(), error))?;
+        if !sidecar.output.resize.size.is_valid() {
+            return Err(format!(
+                "invalid sidecar {}: output size out of range",
+                path.display()
+            ));
+        }
+        let source_hash = hash_file(image_path)
+            .map_err(|error| format!("failed to read {}: {}", image_path.display(), error))?;
+        if sidecar.source_hash != source_hash {
+            return Err(format!(
+                "{} changed since {} was saved",
+                image_path.display(),
+                path.display()
+            ));
+        }
+
+        Ok(Some(sidecar))
+    }
+
+    /// Writes the sidecar of a source image, with the output path made
+    /// absolute so it still points to the same file from elsewhere
+    pub fn save(
+        image_path: &Path,
+        crop: FinalizedImageCrop,
+        transform: ImageTransform,
+        mut output: SidecarOutput,
+    ) -> Result<PathBuf, String> {
+        output.path = std::path::absolute(&output.path).map_err(|error| error.to_string())?;
+        let sidecar = Sidecar {
+            source_hash: hash_file(image_path).map_err(|error| error.to_string())?,
+            crop,
+            transform,
+            output,
+        };
+        let contents = serde_json::to_string_pretty(&sidecar).map_err(|error| error.to_string())?;
+
+        let path = sidecar_path(image_path);
+        std::fs::write(&path, contents + "\n").map_err(|error| error.to_string())?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output_size::OutputSize;
+    use image::Rgba;
+    use image::imageops::FilterType;
+
+    /// An empty directory for a test, removed by the next run of the test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("basicrop-sidecar-{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn output(dir: &Path) -> SidecarOutput {
+        SidecarOutput {
+            path: dir.join("photo-cropped.png"),
+            shape: CropShape::Ellipse,
+            fill: CanvasFill::Color(Rgba([10, 20, 30, 255])),
+            resize: OutputResize {
+                size: OutputSize::Percent(50.),
+                filter: FilterType::Nearest,
+            },
+            jpeg_quality: 80,
+        }
+    }
+
+    fn crop() -> FinalizedImageCrop {
+        FinalizedImageCrop {
+            crop_x: -5,
+            crop_y: 10,
+            width: 40,
+            height: 30,
+        }
+    }
+
+    #[test]
+    fn saved_sidecar_is_loaded_back() {
+        let dir = test_dir("round-trip");
+        let image_path = dir.join("photo.png");
+        std::fs::write(&image_path, b"image contents").unwrap();
+        let transform = ImageTransform {
+            quarter_turns: 1,
+            flipped: true,
+            straighten: 2.5,
+        };
+
+        let path = Sidecar::save(&image_path, crop(), transform, output(&dir)).unwrap();
+        assert_eq!(path, dir.join("photo.png.basicrop.json"));
+        let sidecar = Sidecar::load(&image_path).unwrap().unwrap();
+        assert_eq!(sidecar.crop, crop());
+        assert_eq!(sidecar.transform, transform);
+        assert_eq!(sidecar.output.path, output(&dir).path);
+        assert_eq!(sidecar.output.shape, CropShape::Ellipse);
+        assert_eq!(sidecar.output.fill, output(&dir).fill);
+        assert_eq!(sidecar.output.resize, output(&dir).resize);
+        assert_eq!(sidecar.output.jpeg_quality, 80);
+    }
+
+    #[test]
+    fn sidecar_of_a_changed_image_is_rejected() {
+        let dir = test_dir("changed");
+        let image_path = dir.join("photo.png");
+        std::fs::write(&image_path, b"image contents").unwrap();
+        Sidecar::save(&image_path, crop(), ImageTransform::default(), output(&dir)).unwrap();
+
+        std::fs::write(&image_path, b"edited image contents").unwrap();
+        let error = Sidecar::load(&image_path).unwrap_err();
+        assert!(error.contains("changed since"), "{error}");
+    }
+
+    #[test]
+    fn image_without_sidecar_has_none() {
+        let dir = test_dir("missing");
+        let image_path = dir.join("photo.png");
+        std::fs::write(&image_path, b"image contents").unwrap();
+        assert!(Sidecar::load(&image_path).unwrap().is_none());
+    }
+}
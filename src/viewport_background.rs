@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+/// The backgrounds offered in the window, with their labels
+pub const VIEWPORT_BACKGROUNDS: [(&str, ViewportBackground); 4] = [
+    ("Checkerboard", ViewportBackground::Checkerboard),
+    ("White", ViewportBackground::White),
+    ("Gray", ViewportBackground::Gray),
+    ("Black", ViewportBackground::Black),
+];
+
+/// What's painted behind the image, showing through its transparent pixels
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ViewportBackground {
+    /// Darker than the checkerboard of the extended canvas, so the edges of
+    /// the image stay visible
+    #[default]
+    Checkerboard,
+    White,
+    Gray,
+    Black,
+}
+
+impl ViewportBackground {
+    pub fn from_label(label: &str) -> ViewportBackground {
+        VIEWPORT_BACKGROUNDS
+            .iter()
+            .find(|(background_label, _)| *background_label == label)
+            .map(|(_, background)| *background)
+            .unwrap_or_default()
+    }
+
+    pub fn label(&self) -> &'static str {
+        VIEWPORT_BACKGROUNDS
+            .iter()
+            .find(|(_, background)| background == self)
+            .map(|(label, _)| *label)
+            .unwrap_or_default()
+    }
+
+    /// The light and dark colors of the squares, the same for a solid color
+    pub fn colors(&self) -> (u32, u32) {
+        match self {
+            ViewportBackground::Checkerboard => (0xb0b0b0, 0x808080),
+            ViewportBackground::White => (0xffffff, 0xffffff),
+            ViewportBackground::Gray => (0x808080, 0x808080),
+            ViewportBackground::Black => (0x000000, 0x000000),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_map_back_to_their_backgrounds() {
+        for (label, background) in VIEWPORT_BACKGROUNDS {
+            assert_eq!(background.label(), label);
+            assert_eq!(ViewportBackground::from_label(label), background);
+        }
+        assert_eq!(
+            ViewportBackground::from_label("Purple"),
+            ViewportBackground::Checkerboard
+        );
+    }
+}